@@ -0,0 +1,78 @@
+//! Keep the system from sleeping while searching.
+
+use anyhow::Result;
+#[cfg(not(windows))]
+use std::process::{Child, Command, Stdio};
+
+/// Prevents system sleep until dropped.
+///
+/// On macOS and Linux a `caffeinate` or `systemd-inhibit` child process holds
+/// the assertion, on Windows the execution state of the creating thread,
+/// which therefore has to drop it too.
+#[derive(Debug)]
+pub struct KeepAwake {
+    #[cfg(not(windows))]
+    child: Child,
+}
+
+#[cfg(windows)]
+mod ffi {
+    pub const ES_CONTINUOUS: u32 = 0x8000_0000;
+    pub const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+}
+
+impl KeepAwake {
+    #[cfg(windows)]
+    pub fn new() -> Result<Self> {
+        use ffi::*;
+        // SAFETY: only sets flags of the calling thread
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } == 0 {
+            return Err(anyhow::anyhow!("SetThreadExecutionState failed"));
+        }
+        Ok(Self {})
+    }
+
+    #[cfg(not(windows))]
+    pub fn new() -> Result<Self> {
+        let mut cmd = if cfg!(target_os = "macos") {
+            let mut cmd = Command::new("caffeinate");
+            // -i prevents idle sleep, -w ends the assertion if we die
+            cmd.args(["-i", "-w", &std::process::id().to_string()]);
+            cmd
+        } else {
+            let mut cmd = Command::new("systemd-inhibit");
+            cmd.args([
+                "--what=sleep:idle",
+                "--who=apgpk",
+                "--why=Searching PGP keys",
+                "--mode=block",
+                // exits once our end of its stdin closes, even if we crash
+                "cat",
+            ]);
+            cmd
+        };
+        let child = cmd.stdin(Stdio::piped()).stdout(Stdio::null()).spawn()?;
+        Ok(Self { child })
+    }
+}
+
+impl Drop for KeepAwake {
+    #[cfg(windows)]
+    fn drop(&mut self) {
+        // SAFETY: only sets flags of the calling thread
+        unsafe { ffi::SetThreadExecutionState(ffi::ES_CONTINUOUS) };
+    }
+
+    #[cfg(not(windows))]
+    fn drop(&mut self) {
+        drop(self.child.stdin.take());
+        if let Err(e) = self.child.kill().and_then(|_| self.child.wait()) {
+            log::warn!("Failed to release the sleep inhibitor: {}", e);
+        }
+    }
+}
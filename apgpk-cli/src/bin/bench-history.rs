@@ -0,0 +1,166 @@
+//! Record key generation benchmarks over time and show their trend.
+//!
+//! Every run appends one row (time, commit, backend, CPU, threads, keys/s) to
+//! a CSV file, so performance changes can be compared across commits on the
+//! same machine.
+
+use anyhow::{anyhow, Result};
+use apgpk_lib::{config::KeyConfig, estimate};
+use clap::Parser;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+const HEADER: &str = "timestamp,commit,backend,cpu,threads,keys_per_sec";
+
+#[derive(Parser, Clone, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// CSV file the results are appended to
+    #[arg(short, long, value_name = "PATH", default_value = "bench_history.csv")]
+    file: PathBuf,
+    /// Seconds spent measuring the speed
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    secs: u64,
+    /// Numbers of threads to calculate, default value is the cores of cpu
+    #[arg(short, long, default_value_t = default_thread_num())]
+    threads: usize,
+    /// Label of the key generation backend being measured
+    #[arg(long, default_value = "cpu")]
+    backend: String,
+    /// Don't measure, only plot the recorded history
+    #[arg(long)]
+    plot: bool,
+}
+
+fn default_thread_num() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn cpu_model() -> String {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|l| l.starts_with("model name"))
+                .and_then(|l| l.split_once(':'))
+                .map(|(_, model)| model.trim().to_string())
+        })
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+fn git_commit() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn record(cli: &Cli) -> Result<()> {
+    let rate = estimate::calibrate(
+        &KeyConfig::default(),
+        cli.threads,
+        Duration::from_secs(cli.secs),
+    )?;
+    let row = [
+        chrono::Utc::now().to_rfc3339(),
+        git_commit(),
+        cli.backend.clone(),
+        cpu_model(),
+        cli.threads.to_string(),
+        format!("{:.2}", rate),
+    ]
+    .iter()
+    .map(|f| csv_field(f))
+    .collect::<Vec<_>>()
+    .join(",");
+
+    let new_file = !cli.file.exists();
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&cli.file)?;
+    if new_file {
+        writeln!(f, "{}", HEADER)?;
+    }
+    writeln!(f, "{}", row)?;
+    println!("{}", row);
+    Ok(())
+}
+
+fn plot(cli: &Cli) -> Result<()> {
+    let content = fs::read_to_string(&cli.file)?;
+    let rows: Vec<Vec<String>> = content
+        .lines()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(parse_csv_line)
+        .collect();
+    let rates = rows
+        .iter()
+        .map(|r| {
+            r.get(5)
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| anyhow!("Malformed row in `{}`: {:?}", cli.file.display(), r))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let max = rates.iter().cloned().fold(0.0, f64::max);
+    let width = 50.0;
+    for (r, rate) in rows.iter().zip(rates) {
+        let bar = "#".repeat((rate / max * width).round() as usize);
+        println!(
+            "{:<10} {:<8} {:<6} {:>3}t {:>12.2} key/s {}",
+            &r[0][..r[0].len().min(10)],
+            r[1],
+            r[2],
+            r[4],
+            rate,
+            bar
+        );
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if cli.plot {
+        plot(&cli)
+    } else {
+        record(&cli)
+    }
+}
@@ -0,0 +1,265 @@
+//! `apgpk-cli doctor`, checks of the environment a search runs in.
+
+use crate::{load_patterns, pattern_options, Cli};
+use anyhow::Result;
+use apgpk_lib::{config, gpu};
+use chrono::{TimeZone, Utc};
+use std::{fs, path::Path, process::Command};
+
+/// Free space below which the output directory is reported, keys take
+/// about 1 KiB each
+const MIN_FREE_BYTES: u64 = 64 * 1024 * 1024;
+
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Outcome of a check, with the fix for anything not ok
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            ..Self::warn(name, detail, fix)
+        }
+    }
+}
+
+/// Run all checks and print them, failing with exit code 1 if any failed
+pub fn run(cli: &Cli) -> Result<u8> {
+    let checks = [
+        output_dir(&cli.output),
+        patterns(cli),
+        clock(cli.max_backshift_days),
+        threads(cli.threads),
+        cpu_features(),
+        gpu(cli),
+        gpg(),
+    ];
+    let mut failed = false;
+    for check in &checks {
+        let tag = match check.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => {
+                failed = true;
+                "FAIL"
+            }
+        };
+        println!("[{:>4}] {}: {}", tag, check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            println!("       fix: {}", fix);
+        }
+    }
+    Ok(failed.into())
+}
+
+fn output_dir(dir: &Path) -> Check {
+    const NAME: &str = "output directory";
+    if dir.is_file() {
+        return Check::fail(
+            NAME,
+            format!("`{}` is a file", dir.display()),
+            "pass another directory with `--output`",
+        );
+    }
+    // the search creates a missing directory, so its parent must be writable
+    let existing = dir
+        .ancestors()
+        .find(|d| d.is_dir())
+        .unwrap_or(Path::new("."));
+    let probe = existing.join(".apgpk-doctor");
+    if let Err(e) = fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)) {
+        return Check::fail(
+            NAME,
+            format!("`{}` isn't writable: {}", existing.display(), e),
+            "fix the permissions or pass another directory with `--output`",
+        );
+    }
+    match free_bytes(existing) {
+        Some(free) if free < MIN_FREE_BYTES => Check::warn(
+            NAME,
+            format!(
+                "`{}` has only {} MiB free",
+                dir.display(),
+                free / 1024 / 1024
+            ),
+            "free up space, or cap the output with `--max-output-bytes`",
+        ),
+        Some(free) => Check::ok(
+            NAME,
+            format!(
+                "`{}` is writable, {} MiB free",
+                dir.display(),
+                free / 1024 / 1024
+            ),
+        ),
+        None => Check::ok(
+            NAME,
+            format!("`{}` is writable, free space unknown", dir.display()),
+        ),
+    }
+}
+
+/// Free bytes of the file system holding `dir`, from `df`
+fn free_bytes(dir: &Path) -> Option<u64> {
+    if cfg!(windows) {
+        return None;
+    }
+    let out = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    let text = String::from_utf8(out.stdout).ok()?;
+    // Filesystem 1024-blocks Used Available Capacity Mounted on
+    let kib: u64 = text
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+fn patterns(cli: &Cli) -> Check {
+    const NAME: &str = "patterns";
+    if cli.pattern.is_none() && cli.wordlist.is_none() {
+        return Check::ok(NAME, "no `--pattern` or `--wordlist` given, not checked");
+    }
+    match load_patterns(cli, pattern_options(cli)) {
+        Ok(specs) => Check::ok(NAME, format!("{} patterns parsed", specs.len())),
+        Err(e) => Check::fail(
+            NAME,
+            e.to_string(),
+            "correct the reported patterns, see `--help` for the syntax",
+        ),
+    }
+}
+
+fn clock(max_backshift_days: u16) -> Check {
+    const NAME: &str = "clock";
+    let now = Utc::now();
+    // this version was released later, an earlier time means a wrong clock
+    let release = Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap();
+    if now < release {
+        return Check::fail(
+            NAME,
+            format!("system time {} is in the past", now.to_rfc3339()),
+            "synchronize the clock, e.g. with NTP, found keys carry its creation time",
+        );
+    }
+    if let Err(e) = config::validate_backshift(max_backshift_days) {
+        return Check::fail(NAME, e.to_string(), "lower `--max-backshift-days`");
+    }
+    Check::ok(NAME, format!("system time {}", now.to_rfc3339()))
+}
+
+fn threads(threads: usize) -> Check {
+    const NAME: &str = "threads";
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if threads > cores {
+        return Check::warn(
+            NAME,
+            format!("{} threads on {} cores", threads, cores),
+            format!(
+                "pass `--threads {}` or less, extra threads only add overhead",
+                cores
+            ),
+        );
+    }
+    Check::ok(NAME, format!("{} threads on {} cores", threads, cores))
+}
+
+fn cpu_features() -> Check {
+    const NAME: &str = "cpu features";
+    let mut found: Vec<&str> = vec![];
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        for (name, detected) in [
+            ("sha", is_x86_feature_detected!("sha")),
+            ("avx2", is_x86_feature_detected!("avx2")),
+            ("sse4.1", is_x86_feature_detected!("sse4.1")),
+        ] {
+            if detected {
+                found.push(name);
+            }
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            found.push("sha2");
+        }
+    }
+    // only the SHA extensions are used, for re-dated fingerprints, so
+    // missing features only mean less headroom
+    if found.is_empty() {
+        return Check::ok(NAME, "no SHA or SIMD extensions detected");
+    }
+    Check::ok(NAME, found.join(", "))
+}
+
+fn gpu(cli: &Cli) -> Check {
+    const NAME: &str = "gpu";
+    let Some(api) = cli.backend.api() else {
+        return Check::ok(NAME, "not used, searching on the CPU");
+    };
+    match gpu::devices(api) {
+        Ok(devices) if cli.gpu_device < devices.len() => Check::ok(
+            NAME,
+            format!(
+                "using #{} {} of {:?}",
+                cli.gpu_device, devices[cli.gpu_device], devices
+            ),
+        ),
+        Ok(devices) => Check::fail(
+            NAME,
+            format!("no device #{}, found {:?}", cli.gpu_device, devices),
+            "pick one of the devices found with `--gpu-device`",
+        ),
+        Err(e) => Check::fail(
+            NAME,
+            e.to_string(),
+            "install the GPU driver, or build with the feature of `--backend`",
+        ),
+    }
+}
+
+fn gpg() -> Check {
+    const NAME: &str = "gpg";
+    match Command::new("gpg").arg("--version").output() {
+        Ok(out) if out.status.success() => {
+            let version = String::from_utf8_lossy(&out.stdout);
+            Check::ok(NAME, version.lines().next().unwrap_or("found").to_string())
+        }
+        _ => Check::warn(
+            NAME,
+            "`gpg` not found",
+            "install GnuPG to import the found keys and verify `--campaign-key` signatures",
+        ),
+    }
+}
@@ -1,26 +1,101 @@
 use anyhow::{anyhow, Context, Result};
-use apgpk_lib::{core, utils};
-use clap::Parser;
+use apgpk_lib::{
+    clock::SystemClock,
+    collision::KeyIdMatcher,
+    config::{
+        self, CreationRange, KeyConfig, KeyType, KeyVersion, MatchKey, SearchConfig, TimeSlice,
+        WeightedKeyType,
+    },
+    core::{self, AnyMatcher, FpMask, Matcher, PatternSpec},
+    detect::{self, Detector},
+    estimate,
+    filename::NamePolicy,
+    finalize, fingerprint, gpu,
+    hook::ExecHook,
+    keygen::{KeyGenerator, RetimedKeyGenerator},
+    notation::Notation,
+    output::{self, OutputDir},
+    pool::{Done, PoolConfig, Worker, WorkerPool},
+    prefs::Preferences,
+    progress::{
+        BarReporter, JsonReporter, LogReporter, Progress, ProgressReporter, PrometheusReporter,
+    },
+    revoker::Revoker,
+    score::{ScoreWeights, Scorer},
+    seed::Seed,
+    ssh::{self, SshPattern},
+    stop::{StopReason, StopSignal},
+    uid::UserIdSpec,
+    utils, verify,
+    watchdog::Heartbeat,
+};
+use chrono::{TimeZone, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+mod awake;
+mod doctor;
+#[cfg(windows)]
+mod service;
+
 use std::{
-    path::PathBuf,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        mpsc::{RecvTimeoutError, Sender},
         Arc,
     },
-    thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Parser, Clone, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Path of the pattern file, one pattern per line.
-    #[arg(short, long, value_name = "PATH")]
-    pattern: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path of the pattern file, one pattern per line, or `-` for stdin.
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        required_unless_present_any = ["imitate", "wordlist", "detect", "collide_key_id"]
+    )]
+    pattern: Option<PathBuf>,
+    /// Reload the pattern file whenever it changes while searching
+    #[arg(long, requires = "pattern")]
+    watch: bool,
+    /// Path of a plain word list, words with a hex look-alike (e.g. coffee ->
+    /// C0FFEE) are searched as patterns
+    #[arg(long, value_name = "PATH")]
+    wordlist: Option<PathBuf>,
+    /// Treat every line of the pattern file as a regular expression.
+    ///
+    /// Without this flag only lines prefixed with `re:` are regular expressions.
+    #[arg(long)]
+    regex: bool,
+    /// Keep literal patterns shorter than 5 digits, which match so many keys
+    /// that they can fill up the output directory quickly
+    #[arg(long)]
+    allow_short_patterns: bool,
+    /// Where literal patterns are anchored in the fingerprint: prefix, suffix, both or anywhere
+    #[arg(long = "match", value_name = "MODE", default_value = "suffix")]
+    match_mode: core::MatchMode,
+    /// Only accept literal patterns starting on one of the 4-digit blocks
+    /// GnuPG prints fingerprints in
+    #[arg(long)]
+    align_blocks: bool,
+    /// Also accept fingerprints up to this many digit substitutions off plain
+    /// literal patterns, for long patterns no exact match is feasible for
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_distance: usize,
+    /// Part of the fingerprint to match: fingerprint, keyid (last 16 digits)
+    /// or shortid (last 8 digits)
+    #[arg(long, value_name = "TARGET", default_value = "fingerprint")]
+    target: core::Target,
     /// Directory to save the key
     #[arg(short, long, value_name = "PATH", default_value = "./key_output")]
     output: PathBuf,
-    /// Numbers of threads to calculate, default value is the cores of cpu 
+    /// Numbers of threads to calculate, default value is the cores of cpu
     #[arg(short, long, default_value_t = default_thread_num())]
     threads: usize,
     /// The max backshift days when calculating keys.
@@ -28,13 +103,427 @@ struct Cli {
     /// Changing this default value is not recommended.
     #[arg(long, default_value_t = 30)]
     max_backshift_days: u16,
-    /// Default uid
-    #[arg(long, default_value_t = String::from("apgpk"))]
-    uid: String,
+    /// Only create keys dated within these UTC days, both included, e.g.
+    /// `2021-01-01..2021-12-31`, instead of backshifting from now
+    #[arg(long, value_name = "FROM..TO")]
+    created_between: Option<CreationRange>,
+    /// Also date keys up to SECS seconds in the future, at most 600. Off by
+    /// default, as some software rejects keys created in the future until
+    /// that time has passed
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 0,
+        conflicts_with = "created_between"
+    )]
+    future_secs: u32,
+    /// Default uid, `apgpk` if neither it nor its parts are given.
+    /// Repeatable, the first one is the primary user ID and the others are
+    /// bound as well, e.g. a work and a personal email address
+    #[arg(long)]
+    uid: Vec<String>,
+    #[command(flatten)]
+    uid_parts: UidParts,
+    /// Algorithm of the primary key: eddsa, p256, p384, rsa2048, rsa3072 or
+    /// rsa4096.
+    ///
+    /// An RSA keypair is re-dated to many creation times before a fresh one
+    /// is generated, as generating it costs far more than fingerprinting.
+    /// Repeatable as `TYPE[:WEIGHT]` to search several types at once, the
+    /// threads being shared in proportion to the weights, e.g.
+    /// `--key-type eddsa:3 --key-type rsa3072`
+    #[arg(long, value_name = "TYPE", default_value = "eddsa")]
+    key_type: Vec<WeightedKeyType>,
+    /// Expiration of the generated keys like GnuPG takes it: days, or weeks,
+    /// months or years with a `w`, `m` or `y` suffix, e.g. `2y`. 0 or `never`
+    /// for keys which don't expire
+    #[arg(long, value_name = "DURATION", default_value = "never", value_parser = config::parse_expiration)]
+    expires: u16,
+    /// Capabilities of the primary key, comma separated like GnuPG names
+    /// them, e.g. `auth` for an SSH key used through gpg-agent
+    #[arg(
+        long,
+        value_name = "USAGE",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "cert,sign"
+    )]
+    usage: Vec<Usage>,
+    /// Preferred algorithms written into the self signature like GnuPG's
+    /// `setpref`, e.g. "AES256 AES SHA512 SHA256 ZLIB"
+    #[arg(long, value_name = "ALGOS")]
+    preferences: Option<Preferences>,
+    /// Creation times tried per keypair before fresh key material is
+    /// generated, trading key freshness for speed. By default 4096, or 65536
+    /// for RSA keys
+    #[arg(long, value_name = "N", conflicts_with = "shared_key")]
+    timestamps_per_key: Option<u32>,
+    /// Add an ECDH encryption subkey to the generated keys
+    #[arg(long)]
+    encryption_subkey: bool,
+    /// Add a Cv25519 encryption subkey to the found keys only, when saving
+    /// them, so the search isn't slowed down by generating subkeys
+    #[arg(long, conflicts_with = "encryption_subkey")]
+    with_encryption_subkey: bool,
+    /// Fingerprint the patterns are matched against: primary, subkey or either
+    #[arg(
+        long,
+        value_name = "KEY",
+        default_value = "primary",
+        requires = "encryption_subkey"
+    )]
+    match_key: MatchKey,
+    /// OpenPGP key version: 4, or 6 (RFC 9580) with SHA-256 fingerprints
+    #[arg(long, value_name = "VERSION", default_value = "4")]
+    key_version: KeyVersion,
+    /// Match the patterns against the GnuPG keygrip of the primary key, as
+    /// shown by gpg-agent and smartcard tools, instead of the fingerprint
+    #[arg(long, conflicts_with_all = ["shared_key", "collide_key_id"])]
+    match_keygrip: bool,
+    /// Also require the OpenSSH SHA256 fingerprint of the key to match, given
+    /// as base64 text with an optional mode prefix, e.g. `prefix:apgpk`.
+    /// Repeatable, any of them may match
+    #[arg(long, value_name = "PATTERN", conflicts_with = "shared_key")]
+    ssh_pattern: Vec<String>,
+    /// Seconds a thread may go without reporting progress before it is
+    /// considered stuck, 0 disables the watchdog
+    #[arg(long, value_name = "SECS", default_value_t = 120)]
+    watchdog_timeout: u64,
+    /// Replace threads reported as stuck by the watchdog with fresh ones,
+    /// which skip the creation times already swept with the same keypair
+    #[arg(long)]
+    watchdog_restart: bool,
+    /// Command run through the shell for every found key.
+    ///
+    /// Key details are passed in the environment variables `APGPK_KEY_PATH`,
+    /// `APGPK_FINGERPRINT`, `APGPK_KEY_ID` and `APGPK_UID`.
+    #[arg(long, value_name = "CMD")]
+    on_found_exec: Option<String>,
+    /// Seconds after which a running `--on-found-exec` command is killed
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    on_found_timeout: u64,
+    /// Max number of `--on-found-exec` commands running at the same time
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    on_found_jobs: usize,
+    /// Where the progress is reported, comma separated: log, json (lines on
+    /// stdout), bar (on stderr) or prometheus (metrics file)
+    #[arg(
+        long,
+        value_name = "REPORTERS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "log"
+    )]
+    progress: Vec<ProgressKind>,
+    /// File the `prometheus` progress reporter rewrites, e.g. in the node
+    /// exporter textfile directory
+    #[arg(long, value_name = "PATH")]
+    prometheus_file: Option<PathBuf>,
+    /// Seconds between two `heartbeat tried=... rate=... found=... uptime=...`
+    /// log lines for external supervision, 0 disables them
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    heartbeat_interval: u64,
+    /// Let every thread match only its own share of the patterns, which pays
+    /// off for huge pattern sets only
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = Sharding::Auto)]
+    shard_patterns: Sharding,
+    /// Send the found keys with the progress reports of the workers instead
+    /// of one by one, delaying them and `--stop-on` by up to a second
+    #[arg(long, hide = true)]
+    batch_results: bool,
+    /// Once a key of a pattern is found, stop searching the patterns of
+    /// lower `priority` in the TOML pattern file
+    #[arg(long)]
+    preempt: bool,
+    /// Re-date one keypair shared by all threads, each sweeping its own
+    /// slice of the backshift window, instead of generating a keypair for
+    /// every candidate. Only the primary key fingerprint is matched
+    #[arg(long)]
+    shared_key: bool,
+    /// Where the fingerprints are hashed. With a GPU backend thread 0 drives
+    /// the GPU, sweeping the backshift window with one fresh keypair at a
+    /// time, while the other threads search on the CPU. A GPU only searches
+    /// literal patterns against the primary key fingerprint
+    #[arg(
+        long,
+        value_name = "BACKEND",
+        value_enum,
+        default_value_t = Backend::Cpu,
+        conflicts_with_all = ["shared_key", "seed", "redate", "watch"]
+    )]
+    backend: Backend,
+    /// Number of the GPU used by `--backend`, counting the devices of all
+    /// platforms
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    gpu_device: usize,
+    /// Derive the key material from this seed, 64 hex digits or any text, so
+    /// the search can be repeated and a found key re-derived from its
+    /// creation time. Each thread sweeps its slice of the backshift window
+    /// once. Anyone knowing the seed knows the keys
+    #[arg(long, value_name = "SEED", conflicts_with = "shared_key")]
+    seed: Option<Seed>,
+    /// Re-date this unprotected armored secret key instead of generating
+    /// keys: its key material and subkeys are kept, and the threads sweep
+    /// the backshift window once for a creation time whose primary key
+    /// fingerprint matches. Its user IDs are kept unless `--uid` is given
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["shared_key", "seed", "timestamps_per_key"]
+    )]
+    redate: Option<PathBuf>,
+    /// Unix time a seeded search sweeps back from, now by default
+    #[arg(
+        long,
+        value_name = "UNIX_TIME",
+        requires = "seed",
+        conflicts_with = "created_between"
+    )]
+    seed_start: Option<i64>,
+    /// Before searching, measure for SECS each the rate of fresh keypairs
+    /// and of re-dating one keypair as `--shared-key` does, and report both
+    #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "3")]
+    baseline: Option<u64>,
+    /// Prevent the system from sleeping while searching
+    #[arg(long)]
+    keep_awake: bool,
+    /// Stop after this many keys have been found
+    #[arg(long, value_name = "N")]
+    max_keys: Option<usize>,
+    /// Stop as soon as a key of one of these patterns has been found, e.g.
+    /// `--stop-on CAFEBABE`
+    #[arg(long, value_name = "PATTERN", value_delimiter = ',')]
+    stop_on: Vec<String>,
+    /// Save the keys of every pattern into its own subdirectory of the
+    /// output directory, e.g. `key_output/CAFE1234/`
+    #[arg(long)]
+    split_by_pattern: bool,
+    /// Replace non-ASCII characters of the subdirectory names taken from
+    /// pattern labels, for file systems or archives with another encoding
+    #[arg(long)]
+    ascii_file_names: bool,
+    /// Max number of keys saved into the output directory
+    #[arg(long, value_name = "N")]
+    max_output_keys: Option<usize>,
+    /// Max total size in bytes of the keys saved into the output directory
+    #[arg(long, value_name = "BYTES")]
+    max_output_bytes: Option<u64>,
+    /// Once an output quota is hit, continue in a new directory
+    /// `<output>.1`, `<output>.2`, ... instead of stopping
+    #[arg(long)]
+    rotate_output: bool,
+    /// Unprotected armored secret key signing the metadata of every found
+    /// key into `<FINGERPRINT>.json.asc`, to authenticate results copied from
+    /// other machines
+    #[arg(long, value_name = "PATH")]
+    campaign_key: Option<PathBuf>,
+    /// Save a revocation certificate `<FINGERPRINT>.rev` next to every key,
+    /// like `gpg --gen-revoke` makes it
+    #[arg(long)]
+    with_revocation_cert: bool,
+    /// Key allowed to revoke the saved keys, as `ALGO:FINGERPRINT` with
+    /// ALGO one of rsa, dsa, ecdsa or eddsa, e.g. an escrow key. Repeatable
+    #[arg(long, value_name = "ALGO:FPR")]
+    revoker: Vec<Revoker>,
+    /// Which exports of every key are saved: full, minimal (the primary key
+    /// and user ID only, for constrained environments) or both, the minimal
+    /// one as `<FINGERPRINT>.min.asc`
+    #[arg(long, value_name = "EXPORT", default_value = "full")]
+    export: utils::Export,
+    /// Notation data added to the self signature of every saved key, e.g.
+    /// `vanity@apgpk=pattern:CAFE`. Repeatable
+    #[arg(long, value_name = "NAME=VALUE")]
+    notation: Vec<Notation>,
+    /// Ask on the terminal for a passphrase protecting the saved keys
+    #[arg(long)]
+    ask_passphrase: bool,
+    /// Environment variable holding the passphrase protecting the saved keys
+    #[arg(long, value_name = "VAR", conflicts_with = "ask_passphrase")]
+    passphrase_env: Option<String>,
+    /// File descriptor the passphrase protecting the saved keys is read from,
+    /// up to the first newline, like `gpg --passphrase-fd`
+    #[arg(long, value_name = "FD", conflicts_with_all = ["ask_passphrase", "passphrase_env"])]
+    passphrase_fd: Option<i32>,
+    /// Stop after searching for this many seconds
+    #[arg(long, value_name = "SECS")]
+    max_duration: Option<u64>,
+    /// Search keys sharing the last `--imitate-len` digits with this existing
+    /// fingerprint, for research on fingerprint suffix spoofing.
+    ///
+    /// Requires `--i-understand-collisions`.
+    #[arg(long, value_name = "FPR")]
+    imitate: Option<String>,
+    /// Number of trailing hex digits of `--imitate` to reproduce
+    #[arg(long, value_name = "N", default_value_t = 8, requires = "imitate")]
+    imitate_len: usize,
+    /// Search a key with the same 16-digit long key ID as an existing key,
+    /// given by its key ID or fingerprint, for research on key ID collisions.
+    ///
+    /// Requires `--i-understand-collisions`.
+    #[arg(
+        long,
+        value_name = "KEYID",
+        conflicts_with_all = ["pattern", "wordlist", "imitate", "detect", "score_weights"]
+    )]
+    collide_key_id: Option<String>,
+    /// Acknowledge that keys produced by `--imitate` or `--collide-key-id`
+    /// can be used to impersonate the owner of the imitated key
+    #[arg(long)]
+    i_understand_collisions: bool,
+    /// Keep keys by score instead of by pattern match, given as weights of
+    /// the fingerprint features, e.g. `suffix=1,tail_run=2,leading_zeros=1`.
+    ///
+    /// Features: `suffix` (digits shared with the end of a literal pattern),
+    /// `tail_run` (repeated digits at the end), `leading_zeros` and
+    /// `longest_run` (longest run of the same digit anywhere).
+    #[arg(long, value_name = "WEIGHTS")]
+    score_weights: Option<ScoreWeights>,
+    /// Minimal score of the keys kept with `--score-weights`
+    #[arg(
+        long,
+        value_name = "SCORE",
+        default_value_t = 10.0,
+        requires = "score_weights"
+    )]
+    score_threshold: f64,
+    /// Also keep interesting keys matching none of the patterns, comma
+    /// separated: repeats (8 identical digits), runs (8 ascending or
+    /// descending digits), palindromes (palindromic last 12 digits), words
+    /// (ending with a dictionary word of 6+ letters)
+    #[arg(long, value_name = "DETECTORS", value_delimiter = ',')]
+    detect: Vec<Detector>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Usage {
+    /// Certify other keys and user IDs
+    Cert,
+    /// Sign data
+    Sign,
+    /// Authenticate, e.g. SSH logins
+    Auth,
+    /// Encrypt, RSA keys only
+    Encr,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ProgressKind {
+    /// Log lines with the speed, the progress and the ETA of each pattern
+    Log,
+    /// JSON lines on stdout
+    Json,
+    /// Progress bar on stderr
+    Bar,
+    /// Prometheus metrics written to `--prometheus-file`
+    Prometheus,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    /// The CPU threads
+    Cpu,
+    /// A GPU through OpenCL, in builds with the `gpu-opencl` feature
+    Opencl,
+    /// An NVIDIA GPU through CUDA, in builds with the `gpu-cuda` feature
+    Cuda,
+}
+
+impl Backend {
+    fn api(self) -> Option<gpu::Api> {
+        match self {
+            Backend::Cpu => None,
+            Backend::Opencl => Some(gpu::Api::OpenCl),
+            Backend::Cuda => Some(gpu::Api::Cuda),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Sharding {
+    /// Shard when the estimator expects it to be faster
+    Auto,
+    On,
+    Off,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Measure the key generation speed of this machine and estimate how long
+    /// each pattern takes to find, without searching
+    Estimate {
+        /// Seconds spent measuring the speed
+        #[arg(long, value_name = "SECS", default_value_t = 5)]
+        calibrate_secs: u64,
+    },
+    /// Check the output directory, the patterns, the clock and the tools
+    /// around a search, printing how to fix what's wrong
+    Doctor,
+    /// Check an armored public key against the patterns, e.g. to validate a
+    /// vanity key someone claims, exiting with 1 if nothing matches
+    MatchCheck {
+        /// Armored public key file, `-` for stdin
+        #[arg(value_name = "KEY")]
+        key: PathBuf,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Generate N keys without matching any pattern, with the key options
+    /// given before `generate`
+    Generate {
+        /// Number of keys to generate
+        #[arg(long, value_name = "N")]
+        count: usize,
+    },
+    /// Prepare a found key for real use, with the expiration, preferences,
+    /// capabilities and passphrase options given before `finalize`. The
+    /// fingerprint stays the same
+    Finalize {
+        /// Unprotected armored secret key file, `-` for stdin
+        #[arg(value_name = "KEY")]
+        key: PathBuf,
+        /// User ID replacing the ones of the key, repeatable, the first one
+        /// being the primary user ID
+        #[arg(long, required_unless_present_any = ["uid_name", "uid_email"])]
+        uid: Vec<String>,
+        #[command(flatten)]
+        uid_parts: UidParts,
+        /// Keep the user IDs of the key after the new ones
+        #[arg(long)]
+        keep_uids: bool,
+        /// Don't add a Cv25519 encryption subkey to a key without subkeys
+        #[arg(long)]
+        no_encryption_subkey: bool,
+        /// File the finalized key is written to instead of stdout
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+    /// Run the search as a Windows service
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[cfg(windows)]
+#[derive(Subcommand, Clone, Debug)]
+enum ServiceAction {
+    /// Register a service searching with the options given before `service`,
+    /// e.g. `apgpk-cli -p patterns.txt service install`
+    Install,
+    /// Entry point of the service, started by the service control manager
+    Run {
+        /// Directory relative paths of the options are resolved against
+        #[arg(long, value_name = "PATH")]
+        dir: PathBuf,
+    },
+    /// Stop and remove the service
+    Uninstall,
 }
 
 fn default_thread_num() -> usize {
-    std::thread::available_parallelism().unwrap().get()
+    std::thread::available_parallelism().map_or(1, |n| n.get())
 }
 
 fn log_init() {
@@ -43,93 +532,1344 @@ fn log_init() {
     log::debug!("Log engine is initialized");
 }
 
-fn main() -> Result<()> {
+/// What the workers search with besides the patterns
+#[derive(Debug, Clone, Default)]
+struct WorkerSetup {
+    /// Match this key ID instead of the patterns
+    collide: Option<KeyIdMatcher>,
+    /// Keep every key instead of matching, see `generate`
+    generate: Option<AnyMatcher>,
+    /// Key type of every thread when searching several, see `--key-type`
+    key_types: Vec<KeyType>,
+    /// Keypair re-dated by all workers, see `--shared-key`
+    shared_key: Option<RetimedKeyGenerator>,
+    /// Sweep the window once, workers done with their time slice return
+    /// [`Done::Exhausted`]
+    sweep_once: bool,
+    /// GPU driven by thread 0, see `--backend`
+    gpu: Option<GpuSetup>,
+}
+
+#[derive(Debug, Clone)]
+struct GpuSetup {
+    api: gpu::Api,
+    device: usize,
+    masks: Arc<Vec<FpMask>>,
+}
+
+/// Body of a worker thread
+fn run_worker(
+    worker: Worker<core::Msg>,
+    search: &SearchConfig,
+    pattern: &core::PatternSet,
+    setup: &WorkerSetup,
+) -> Result<Done> {
+    // thread i matches shard i and sweeps time slice i
+    let mut search = worker.chunk(search);
+    let Worker {
+        index: i,
+        exit,
+        tx,
+        heartbeat,
+        ..
+    } = worker;
+    let thread_exit: &StopSignal = &exit;
+    // with key type i
+    if let Some(key_type) = setup.key_types.get(i) {
+        search.key.key_type = *key_type;
+    }
+    let mut setup = setup.clone();
+
+    // opened on the first sweep, kept for the next ones
+    let mut device: Option<Box<dyn gpu::SweepDevice>> = None;
+    let done = loop {
+        // moved into the sweep, which uses it up
+        let shared_key = setup.shared_key.take();
+        let shared = shared_key.is_some();
+        match (&setup.collide, &setup.generate, &setup.gpu) {
+            (Some(m), _, _) => sweep(&search, shared_key, m.clone(), thread_exit, &tx, &heartbeat)?,
+            (None, Some(m), _) => {
+                sweep(&search, shared_key, m.clone(), thread_exit, &tx, &heartbeat)?
+            }
+            (None, None, Some(gpu_setup)) if i == 0 => gpu_sweep(
+                &search,
+                gpu_setup,
+                &mut device,
+                pattern.matcher(search.shard)?,
+                thread_exit,
+                &tx,
+                &heartbeat,
+            )?,
+            (None, None, _) => sweep(
+                &search,
+                shared_key,
+                pattern.matcher(search.shard)?,
+                thread_exit,
+                &tx,
+                &heartbeat,
+            )?,
+        }
+
+        if thread_exit.is_stopped() {
+            drop(tx);
+            break Done::Stopped;
+        }
+        if setup.sweep_once {
+            log::debug!("Thread {} swept its time slice of the seeded search", i);
+            break Done::Exhausted;
+        }
+        // the shared keypair is used up, go on with one of this thread
+        if shared {
+            log::debug!("Thread {} swept its time slice, taking a new keypair", i);
+            setup.shared_key = Some(RetimedKeyGenerator::new(&search.key, Utc::now()));
+        }
+    };
+    log::debug!("Thread {} complete", i);
+    Ok(done)
+}
+
+/// One sweep of the backshift window, re-dating `shared_key` if given
+fn sweep<M: Matcher>(
+    search: &SearchConfig,
+    shared_key: Option<RetimedKeyGenerator>,
+    matcher: M,
+    exit: &StopSignal,
+    tx: &Sender<core::Msg>,
+    heartbeat: &Heartbeat,
+) -> Result<(), apgpk_lib::error::ApgpkError> {
+    match shared_key {
+        Some(generator) => core::search_with(
+            search,
+            generator,
+            matcher,
+            exit,
+            tx,
+            heartbeat,
+            &SystemClock,
+        ),
+        None => core::task(search, matcher, exit, tx, heartbeat, &SystemClock),
+    }
+}
+
+/// One sweep of the backshift window on the GPU, opening `device` first if
+/// it isn't yet
+fn gpu_sweep<M: Matcher>(
+    search: &SearchConfig,
+    setup: &GpuSetup,
+    device: &mut Option<Box<dyn gpu::SweepDevice>>,
+    matcher: M,
+    exit: &StopSignal,
+    tx: &Sender<core::Msg>,
+    heartbeat: &Heartbeat,
+) -> Result<(), apgpk_lib::error::ApgpkError> {
+    let d = match device {
+        Some(d) => d,
+        None => {
+            let d = gpu::open(setup.api, setup.device, &setup.masks)?;
+            log::info!("Sweeping on {}", d.name());
+            device.insert(d)
+        }
+    };
+    gpu::search(
+        d.as_mut(),
+        search,
+        matcher,
+        exit,
+        tx,
+        heartbeat,
+        &SystemClock,
+    )
+}
+
+/// Patterns of the pattern file and the word list
+fn pattern_options(cli: &Cli) -> utils::PatternOptions {
+    utils::PatternOptions {
+        regex: cli.regex,
+        mode: cli.match_mode,
+        target: cli.target,
+        allow_short: cli.allow_short_patterns,
+        align_blocks: cli.align_blocks,
+        max_distance: cli.max_distance,
+    }
+}
+
+fn load_patterns(cli: &Cli, opts: utils::PatternOptions) -> Result<Vec<PatternSpec>> {
+    let mut pattern = match &cli.pattern {
+        Some(path) => utils::parse_pattern(path, opts)?,
+        None => vec![],
+    };
+    if let Some(path) = &cli.wordlist {
+        pattern.extend(
+            utils::parse_wordlist(path, opts)?
+                .into_iter()
+                .map(PatternSpec::new),
+        );
+    }
+    Ok(pattern)
+}
+
+fn patterns_of(specs: &[PatternSpec]) -> Vec<core::Pattern> {
+    specs.iter().map(|s| s.pattern.clone()).collect()
+}
+
+fn quota_met(spec: &PatternSpec, found_by_pattern: &HashMap<String, usize>) -> bool {
+    spec.quota
+        .is_some_and(|n| found_by_pattern.get(&spec.name()).copied().unwrap_or(0) >= n)
+}
+
+/// Whether `--preempt` dropped the pattern, being below `min_priority`.
+/// Exclusions always stay.
+fn preempted(spec: &PatternSpec, min_priority: Option<i32>) -> bool {
+    !matches!(spec.pattern, core::Pattern::Exclude(_))
+        && min_priority.is_some_and(|p| spec.priority < p)
+}
+
+/// Patterns still searched, those whose quota is met or which were
+/// preempted are left out
+fn active_patterns(
+    specs: &[PatternSpec],
+    found_by_pattern: &HashMap<String, usize>,
+    min_priority: Option<i32>,
+) -> Vec<core::Pattern> {
+    specs
+        .iter()
+        .filter(|s| !quota_met(s, found_by_pattern) && !preempted(s, min_priority))
+        .map(|s| s.pattern.clone())
+        .collect()
+}
+
+/// Spec of a found pattern, which may be gone after a reload
+fn spec_of(specs: &[PatternSpec], p: &core::Pattern) -> PatternSpec {
+    let shown = p.to_string();
+    specs
+        .iter()
+        .find(|s| s.pattern.to_string() == shown)
+        .cloned()
+        .unwrap_or_else(|| PatternSpec::new(p.clone()))
+}
+
+fn reporters(cli: &Cli) -> Result<Vec<Box<dyn ProgressReporter>>> {
+    cli.progress
+        .iter()
+        .map(|kind| -> Result<Box<dyn ProgressReporter>> {
+            Ok(match kind {
+                ProgressKind::Log => Box::new(LogReporter),
+                ProgressKind::Json => Box::new(JsonReporter::new(std::io::stdout())),
+                ProgressKind::Bar => Box::new(BarReporter),
+                ProgressKind::Prometheus => {
+                    let path = cli.prometheus_file.clone().ok_or_else(|| {
+                        anyhow!("`--progress prometheus` needs `--prometheus-file`")
+                    })?;
+                    Box::new(PrometheusReporter::new(path))
+                }
+            })
+        })
+        .collect()
+}
+
+/// Call every reporter, logging the failures
+fn report_all(
+    reporters: &mut [Box<dyn ProgressReporter>],
+    f: impl Fn(&mut dyn ProgressReporter) -> Result<(), apgpk_lib::error::ApgpkError>,
+) {
+    for r in reporters {
+        if let Err(e) = f(r.as_mut()) {
+            log::warn!("Failed to report progress: {}", e);
+        }
+    }
+}
+
+fn snapshot(
+    cli: &Cli,
+    tried: u64,
+    skipped: u64,
+    rate: f64,
+    found: usize,
+    started: Instant,
+    pattern: &core::PatternSet,
+) -> Progress {
+    Progress {
+        tried,
+        skipped,
+        rate,
+        found,
+        threads: cli.threads,
+        uptime_secs: started.elapsed().as_secs(),
+        p50_tries: estimate::p50_tries(&pattern.load().1),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether `p` is one of the `--stop-on` patterns, given either as shown in
+/// the log (`*CAFEBABE`) or as its directory name (`CAFEBABE`)
+fn is_stop_pattern(stop_on: &[String], p: &core::Pattern) -> bool {
+    let (shown, name) = (p.to_string(), utils::pattern_dir_name(p));
+    stop_on
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(&shown) || s.eq_ignore_ascii_case(&name))
+}
+
+/// Max number of patterns whose ETA is logged with the speed
+const MAX_ETA_LINES: usize = 10;
+
+/// Log how much of the expected work of each pattern is covered by `tried`
+/// keys and the expected remaining time at `rate` keys per second.
+///
+/// Since every key is an independent try, the expected remaining time
+/// doesn't shrink with the work already done.
+fn log_pattern_eta(pattern: &[core::Pattern], tried: f64, rate: f64) {
+    let mut lines = pattern
+        .iter()
+        .filter_map(|p| estimate::expected_tries(p).map(|tries| (p, tries)));
+    for (par, tries) in lines.by_ref().take(MAX_ETA_LINES) {
+        log::info!(
+            "  {}: {:.1}% of the expected work covered, ~{} expected to go",
+            par,
+            100.0 * tried / tries,
+            estimate::human_duration(estimate::expected_duration(tries, rate))
+        );
+    }
+    let more = lines.count();
+    if more > 0 {
+        log::info!("  ... and {} more patterns", more);
+    }
+}
+
+/// Warn about patterns which are not expected to be found within a year at
+/// `rate` keys per second and suggest feasible alternatives.
+fn advise_infeasible(pattern: &[core::Pattern], rate: f64) {
+    let budget = Duration::from_secs(365 * 24 * 60 * 60);
+    for par in pattern {
+        let core::Pattern::Literal(l) = par else {
+            continue;
+        };
+        let suggestions = estimate::advise(l, rate, budget);
+        if suggestions.is_empty() {
+            continue;
+        }
+        let tries = 1.0 / l.probability();
+        log::warn!(
+            "Pattern `{}` is expected to take {} to find, consider instead:",
+            par,
+            estimate::human_duration(estimate::expected_duration(tries, rate))
+        );
+        for s in suggestions {
+            log::warn!(
+                "  - {} (~{})",
+                s,
+                estimate::human_duration(estimate::expected_duration(s.tries(), rate))
+            );
+        }
+    }
+}
+
+fn run_estimate(
+    cli: &Cli,
+    key: &KeyConfig,
+    pattern: &[core::Pattern],
+    calibrate_secs: u64,
+) -> Result<u8> {
+    log::info!(
+        "Measuring the speed with {} threads for {}s...",
+        cli.threads,
+        calibrate_secs
+    );
+    let rate = estimate::calibrate(key, cli.threads, Duration::from_secs(calibrate_secs))?;
+    println!("Speed ({} threads): {:.2} key/s", cli.threads, rate);
+    println!();
+    println!(
+        "{:<24} {:>16} {:>16} {:>16}",
+        "Pattern", "Expected tries", "ETA (mean)", "ETA (50%)"
+    );
+    for par in pattern {
+        match estimate::expected_tries(par) {
+            Some(tries) => {
+                let mean = estimate::expected_duration(tries, rate);
+                let median = estimate::expected_duration(tries * std::f64::consts::LN_2, rate);
+                println!(
+                    "{:<24} {:>16.3e} {:>16} {:>16}",
+                    par.to_string(),
+                    tries,
+                    estimate::human_duration(mean),
+                    estimate::human_duration(median)
+                );
+            }
+            None => println!(
+                "{:<24} {:>16} {:>16} {:>16}",
+                par.to_string(),
+                "?",
+                "?",
+                "?"
+            ),
+        }
+    }
+    advise_infeasible(pattern, rate);
+    if let Some(secs) = cli.baseline {
+        run_baseline(cli, key, secs)?;
+    }
+    Ok(0)
+}
+
+/// Log the rates of fresh and re-dated keypairs and which one pays off
+fn run_baseline(cli: &Cli, key: &KeyConfig, secs: u64) -> Result<()> {
+    log::info!(
+        "Measuring fresh and re-dated keypairs with {} threads for {}s each...",
+        cli.threads,
+        secs
+    );
+    let b = estimate::baseline(key, cli.threads, Duration::from_secs(secs))?;
+    log::info!("Fresh keypair per candidate: {:.2} key/s", b.fresh);
+    log::info!(
+        "Re-dated keypair (--shared-key): {:.2} key/s, {:.2}x",
+        b.retimed,
+        b.speedup()
+    );
+    match (b.prefer_retimed(), cli.shared_key) {
+        (true, false) => log::info!("Re-dating pays off here, consider `--shared-key`"),
+        (false, true) => {
+            log::info!("Re-dating doesn't pay off here, consider dropping `--shared-key`")
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
 
     log_init();
 
-    let pattern = utils::parse_pattern(&cli.pattern)?;
+    #[cfg(windows)]
+    if let Some(Command::Service { action }) = &cli.command {
+        return service_command(cli.clone(), action).map(ExitCode::from);
+    }
+
+    if let Some(Command::Doctor) = &cli.command {
+        return doctor::run(&cli).map(ExitCode::from);
+    }
+    if let Some(Command::MatchCheck { key, json }) = &cli.command {
+        return match_check(&cli, key, *json).map(ExitCode::from);
+    }
+    if let Some(Command::Finalize { .. }) = &cli.command {
+        return finalize_key(&cli).map(ExitCode::from);
+    }
+
+    let thread_exit = Arc::new(StopSignal::new());
+    if matches!(cli.command, None | Some(Command::Generate { .. })) {
+        let exit = thread_exit.clone();
+        // Setup ctrlc signal
+        ctrlc::set_handler(move || {
+            log::warn!("SIGNINT received, waiting all threads to exit...");
+            exit.stop(StopReason::Signal);
+        })
+        .with_context(|| {
+            log::error!("Error setting Ctrl-C handler");
+            anyhow!("")
+        })?;
+    }
+    search(&cli, thread_exit).map(ExitCode::from)
+}
+
+#[cfg(windows)]
+fn service_command(cli: Cli, action: &ServiceAction) -> Result<u8> {
+    match action {
+        ServiceAction::Install => {
+            check_pattern_source(&cli)?;
+            // relaunch with the same options, `service install` becoming
+            // `service run` from the current directory
+            let mut args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+            let at = args
+                .iter()
+                .position(|a| a == "service")
+                .ok_or_else(|| anyhow!("Options must be given before `service install`"))?;
+            args.truncate(at);
+            args.extend(["service", "run", "--dir"].map(std::ffi::OsString::from));
+            args.push(std::env::current_dir()?.into_os_string());
+            service::install(args)?;
+            log::info!("Service `{}` installed", service::SERVICE_NAME);
+        }
+        ServiceAction::Run { dir } => {
+            std::env::set_current_dir(dir)?;
+            service::run(Box::new(move |stop| search(&cli, stop)))?;
+        }
+        ServiceAction::Uninstall => {
+            service::uninstall()?;
+            log::info!("Service `{}` uninstalled", service::SERVICE_NAME);
+        }
+    }
+    Ok(0)
+}
+
+/// Check the key at `path` against the patterns, 1 if it matches none
+fn match_check(cli: &Cli, path: &Path, json: bool) -> Result<u8> {
+    let armored = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))?
+    };
+    let specs = load_patterns(cli, pattern_options(cli))?;
+    if specs.is_empty() {
+        return Err(anyhow!("`match-check` needs `--pattern` or `--wordlist`"));
+    }
+    let report = verify::check_public_key(&armored, &specs)?;
+    if json {
+        println!("{}", report.to_json());
+    } else if !report.is_match() {
+        println!("{} matches no pattern", report.fingerprint);
+    } else {
+        for m in &report.matches {
+            let key = if m.subkey { "subkey" } else { "primary key" };
+            let name = m.label.as_deref().unwrap_or(&m.pattern);
+            match m.distance {
+                Some(d) => println!("{} {} matches `{}` within {}", key, m.fingerprint, name, d),
+                None => println!("{} {} matches `{}`", key, m.fingerprint, name),
+            }
+        }
+    }
+    Ok((!report.is_match()).into())
+}
+
+/// Parts of the primary user ID, as an alternative to a free-form `--uid`
+#[derive(Args, Clone, Debug)]
+struct UidParts {
+    /// Name of the primary user ID, assembled with `--uid-email` and
+    /// `--uid-comment` into `Name (Comment) <email>`. A `--uid` given too is
+    /// bound as a further user ID
+    #[arg(long, value_name = "NAME")]
+    uid_name: Option<String>,
+    /// E-mail address of the primary user ID
+    #[arg(long, value_name = "EMAIL")]
+    uid_email: Option<String>,
+    /// Comment of the primary user ID, e.g. `work`
+    #[arg(long, value_name = "COMMENT", requires = "uid_name")]
+    uid_comment: Option<String>,
+}
+
+impl UidParts {
+    fn spec(&self) -> Option<UserIdSpec> {
+        if self.uid_name.is_none() && self.uid_email.is_none() {
+            return None;
+        }
+        Some(UserIdSpec {
+            name: self.uid_name.clone(),
+            email: self.uid_email.clone(),
+            comment: self.uid_comment.clone(),
+        })
+    }
+}
+
+/// User IDs of the key, the primary one first: the one assembled from
+/// `parts`, then `uid`, `apgpk` if there is neither
+fn user_ids(parts: &UidParts, uid: &[String]) -> Result<Vec<String>> {
+    let mut ids = vec![];
+    if let Some(spec) = parts.spec() {
+        ids.push(spec.to_uid()?);
+    }
+    ids.extend(uid.iter().cloned());
+    if ids.is_empty() {
+        ids.push("apgpk".to_string());
+    }
+    Ok(ids)
+}
+
+/// Write the found key of `finalize` with its real identity
+fn finalize_key(cli: &Cli) -> Result<u8> {
+    let Some(Command::Finalize {
+        key: path,
+        uid,
+        uid_parts,
+        keep_uids,
+        no_encryption_subkey,
+        out,
+    }) = &cli.command
+    else {
+        unreachable!("only called for `finalize`");
+    };
+    let armored = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))?
+    };
+    let found = finalize::load_key(&armored)?;
+    let uid = user_ids(uid_parts, uid)?;
+    let mut user_ids = uid[1..].to_vec();
+    if *keep_uids {
+        for id in finalize::user_ids(&found) {
+            if !uid.contains(&id) && !user_ids.contains(&id) {
+                user_ids.push(id);
+            }
+        }
+    }
+    let key = KeyConfig {
+        user_ids,
+        can_certify: cli.usage.contains(&Usage::Cert),
+        can_sign: cli.usage.contains(&Usage::Sign),
+        can_authenticate: cli.usage.contains(&Usage::Auth),
+        can_encrypt: cli.usage.contains(&Usage::Encr),
+        preferences: cli.preferences.clone(),
+        expiration_days: Some(cli.expires).filter(|&days| days > 0),
+        encryption_subkey: !no_encryption_subkey,
+        ..KeyConfig::new(uid[0].clone())
+    };
+    let k = finalize::finalize(&found, &key)?;
+    let opts = utils::SaveOptions {
+        passphrase: read_passphrase(cli)?,
+        revokers: cli.revoker.clone(),
+        export: cli.export,
+        notations: cli.notation.clone(),
+        ..Default::default()
+    };
+    let armored = utils::export_key_with(&k, &opts)?;
+    match out {
+        Some(out) => fs::write(out, armored)
+            .with_context(|| format!("Failed to write `{}`", out.display()))?,
+        None => print!("{}", armored),
+    }
+    log::info!("Finalized key {}", utils::key2hex(&k));
+    Ok(0)
+}
+
+/// Generator re-dating the key of `--redate`, with its own user IDs unless
+/// others are given, its expiration and subkeys, and the capabilities and
+/// preferences of `key`
+fn redate_generator(cli: &Cli, path: &Path, key: &KeyConfig) -> Result<RetimedKeyGenerator> {
+    let armored =
+        fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let existing = finalize::load_key(&armored)?;
+    let mut uid = finalize::user_ids(&existing);
+    if !cli.uid.is_empty() || cli.uid_parts.spec().is_some() || uid.is_empty() {
+        uid = std::iter::once(key.uid.clone())
+            .chain(key.user_ids.iter().cloned())
+            .collect();
+    }
+    let key = KeyConfig {
+        uid: uid[0].clone(),
+        user_ids: uid[1..].to_vec(),
+        ..key.clone()
+    };
+    log::info!("Re-dating the key of `{}`", path.display());
+    Ok(finalize::redate(&existing, &key)?)
+}
+
+/// Error unless patterns come from at least one source, which clap can't
+/// require when a subcommand is given
+fn check_pattern_source(cli: &Cli) -> Result<()> {
+    if cli.pattern.is_none()
+        && cli.imitate.is_none()
+        && cli.collide_key_id.is_none()
+        && cli.wordlist.is_none()
+        && cli.detect.is_empty()
+    {
+        return Err(anyhow!(
+            "One of `--pattern`, `--imitate`, `--collide-key-id`, `--wordlist` or `--detect` is required"
+        ));
+    }
+    Ok(())
+}
+
+/// Error if patterns are given to `generate`, which keeps every key
+fn check_no_pattern_source(cli: &Cli) -> Result<()> {
+    if cli.pattern.is_some()
+        || cli.imitate.is_some()
+        || cli.collide_key_id.is_some()
+        || cli.wordlist.is_some()
+        || !cli.detect.is_empty()
+        || cli.score_weights.is_some()
+    {
+        return Err(anyhow!(
+            "`generate` keeps every key, drop the patterns, detectors and scoring"
+        ));
+    }
+    Ok(())
+}
+
+/// Passphrase protecting the saved keys from `--ask-passphrase`,
+/// `--passphrase-env` or `--passphrase-fd`
+fn read_passphrase(cli: &Cli) -> Result<Option<String>> {
+    let passphrase = if cli.ask_passphrase {
+        let first = rpassword::prompt_password("Passphrase for the found keys: ")?;
+        if rpassword::prompt_password("Repeat the passphrase: ")? != first {
+            return Err(anyhow!("The passphrases don't match"));
+        }
+        first
+    } else if let Some(var) = &cli.passphrase_env {
+        std::env::var(var)
+            .with_context(|| format!("Failed to read the passphrase from `${}`", var))?
+    } else if let Some(fd) = cli.passphrase_fd {
+        read_fd_line(fd).with_context(|| format!("Failed to read the passphrase from fd {}", fd))?
+    } else {
+        return Ok(None);
+    };
+    if passphrase.is_empty() {
+        return Err(anyhow!("The passphrase is empty"));
+    }
+    Ok(Some(passphrase))
+}
+
+/// First line of the inherited file descriptor `fd`, which is closed after
+#[cfg(unix)]
+fn read_fd_line(fd: i32) -> Result<String> {
+    use std::{
+        io::{BufRead, BufReader},
+        os::fd::FromRawFd,
+    };
+    // SAFETY: the descriptor is handed over by the caller for reading the
+    // passphrase, like to `gpg --passphrase-fd`, and isn't used elsewhere
+    let file = unsafe { fs::File::from_raw_fd(fd) };
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+#[cfg(not(unix))]
+fn read_fd_line(_fd: i32) -> Result<String> {
+    Err(anyhow!("`--passphrase-fd` is only supported on Unix"))
+}
+
+/// Search until `thread_exit` is stopped and return the exit code
+fn search(cli: &Cli, thread_exit: Arc<StopSignal>) -> Result<u8> {
+    let generate = match &cli.command {
+        Some(Command::Generate { count }) => Some(*count),
+        _ => None,
+    };
+    match generate {
+        Some(0) => return Err(anyhow!("`generate --count` must be at least 1")),
+        Some(_) => check_no_pattern_source(cli)?,
+        None => check_pattern_source(cli)?,
+    }
+
+    let uid = user_ids(&cli.uid_parts, &cli.uid)?;
+    let key = KeyConfig {
+        key_type: cli.key_type[0].key_type,
+        can_certify: cli.usage.contains(&Usage::Cert),
+        can_sign: cli.usage.contains(&Usage::Sign),
+        can_authenticate: cli.usage.contains(&Usage::Auth),
+        can_encrypt: cli.usage.contains(&Usage::Encr),
+        preferences: cli.preferences.clone(),
+        seed: cli.seed.clone(),
+        timestamps_per_key: cli.timestamps_per_key,
+        expiration_days: Some(cli.expires).filter(|&days| days > 0),
+        encryption_subkey: cli.encryption_subkey,
+        match_key: cli.match_key,
+        version: cli.key_version,
+        match_keygrip: cli.match_keygrip,
+        ssh_patterns: cli
+            .ssh_pattern
+            .iter()
+            .map(|p| SshPattern::parse(p, cli.match_mode))
+            .collect::<Result<_, _>>()?,
+        user_ids: uid[1..].to_vec(),
+        ..KeyConfig::new(uid[0].clone())
+    };
+    key.validate()?;
+    match cli.created_between {
+        Some(range) => range.validate(Utc::now())?,
+        None => config::validate_backshift(cli.max_backshift_days)?,
+    }
+
+    let opts = pattern_options(cli);
+    if cli.watch && cli.pattern.as_deref() == Some(Path::new("-")) {
+        return Err(anyhow!("`--watch` needs a pattern file, not stdin"));
+    }
+    let mut specs = load_patterns(cli, opts)?;
+    let mut imitation = None;
+    if let Some(fpr) = &cli.imitate {
+        if !cli.i_understand_collisions {
+            return Err(anyhow!(
+                "`--imitate` produces keys that can impersonate an existing key, pass `--i-understand-collisions` to proceed"
+            ));
+        }
+        let imitated = utils::imitation_pattern(fpr, cli.imitate_len)?;
+        log::warn!(
+            "!!! Imitating the fingerprint suffix of an existing key: `{}`",
+            imitated
+        );
+        log::warn!("!!! Keys found this way can be mistaken for the original key by anyone checking only the short or long key ID / fingerprint suffix.");
+        log::warn!("!!! Use them for research and demonstration only, never publish them as someone else's key.");
+        imitation = Some(imitated);
+    }
+    specs.extend(imitation.clone().map(PatternSpec::new));
+    let mut setup = WorkerSetup::default();
+    if cli.key_type.len() > 1 {
+        if cli.shared_key || cli.seed.is_some() || cli.redate.is_some() {
+            return Err(anyhow!(
+                "Several `--key-type` can't be combined with `--shared-key`, `--seed` or `--redate`"
+            ));
+        }
+        for t in &cli.key_type {
+            KeyConfig {
+                key_type: t.key_type,
+                ..key.clone()
+            }
+            .validate()?;
+        }
+        setup.key_types = config::allocate_threads(&cli.key_type, cli.threads)?;
+        let shares: Vec<_> = cli
+            .key_type
+            .iter()
+            .map(|t| {
+                let n = setup.key_types.iter().filter(|k| **k == t.key_type).count();
+                format!("{} on {} threads", config::key_type_id(&t.key_type), n)
+            })
+            .collect();
+        log::info!("Searching {}", shares.join(", "));
+    }
+    if let Some(key_id) = &cli.collide_key_id {
+        if !cli.i_understand_collisions {
+            return Err(anyhow!(
+                "`--collide-key-id` produces keys that can impersonate an existing key, pass `--i-understand-collisions` to proceed"
+            ));
+        }
+        let m = KeyIdMatcher::new(key_id)?;
+        log::warn!(
+            "!!! Searching a key colliding with the long key ID {} of an existing key",
+            m.key_id()
+        );
+        log::warn!("!!! Such a key passes for the original wherever keys are looked up or trusted by key ID.");
+        log::warn!("!!! Keys found get the user ID `{}` and are marked as collision demo keys in their metadata, use them for research and demonstration only.", m.demo_uid());
+        specs.push(PatternSpec {
+            uid: Some(m.demo_uid()),
+            ..PatternSpec::new(m.pattern().clone())
+        });
+        setup.collide = Some(m);
+    }
+    if let Some(count) = generate {
+        let m = AnyMatcher::default();
+        specs.push(PatternSpec {
+            label: Some("generated".to_string()),
+            quota: Some(count),
+            ..PatternSpec::new(m.pattern().clone())
+        });
+        setup.generate = Some(m);
+    }
+    let pattern = patterns_of(&specs);
     log::info!("Runing with {} threads", cli.threads);
-    log::info!("Find key by pattern {:?}", pattern);
+    log::info!(
+        "Hashing re-dated fingerprints with {} SHA-1",
+        fingerprint::backend()
+    );
+    match generate {
+        Some(count) => log::info!("Generating {} keys without matching", count),
+        None => log::info!(
+            "Find key by pattern {:?}",
+            pattern.iter().map(|p| p.to_string()).collect::<Vec<_>>()
+        ),
+    }
+    if !key.ssh_patterns.is_empty() {
+        log::info!(
+            "Only keys whose SSH fingerprint matches {:?}",
+            key.ssh_patterns
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    if let Some(Command::Estimate { calibrate_secs }) = &cli.command {
+        return run_estimate(cli, &key, &pattern, *calibrate_secs);
+    }
+    advise_infeasible(
+        &pattern,
+        estimate::DEFAULT_RATE_PER_THREAD * cli.threads as f64,
+    );
+    if let Some(secs) = cli.baseline {
+        run_baseline(cli, &key, secs)?;
+    }
 
     utils::check_output_dir(cli.output.clone())?;
+    let campaign_key = cli
+        .campaign_key
+        .as_deref()
+        .map(output::load_campaign_key)
+        .transpose()
+        .context("Failed to load the campaign key")?;
+    let passphrase = read_passphrase(cli)?;
+
+    let mut search = SearchConfig::new(key, cli.max_backshift_days);
+    search.detectors = cli.detect.clone();
+    search.batch_results = cli.batch_results;
+    search.created_between = cli.created_between;
+    search.future_secs = cli.future_secs;
+    search.validate()?;
+    if search.future_secs > 0 {
+        log::warn!(
+            "Keys may be dated up to {} seconds in the future, some software rejects them until then",
+            search.future_secs
+        );
+    }
+    if let Some(range) = cli.created_between {
+        log::info!(
+            "Creating keys dated from {} to {}",
+            Utc.timestamp_opt(range.earliest, 0).unwrap(),
+            Utc.timestamp_opt(range.latest, 0).unwrap()
+        );
+    }
+    // the latest creation time swept
+    let latest = search.latest(Utc::now());
+    let sharded = match cli.shard_patterns {
+        Sharding::Auto => estimate::prefer_sharding(&pattern, cli.threads),
+        Sharding::On => true,
+        Sharding::Off => false,
+    };
+    if sharded {
+        log::info!(
+            "Sharding {} patterns across {} threads",
+            pattern.len(),
+            cli.threads
+        );
+        // split across the threads by the pool, see `Worker::chunk`
+        search.shard = Some((0, 1));
+    }
+    if let Some(weights) = cli.score_weights {
+        log::info!(
+            "Keep keys scoring at least {} with {:?}",
+            cli.score_threshold,
+            weights
+        );
+        search.scorer = Some(Scorer::new(weights, cli.score_threshold, &pattern));
+    }
+    if cli.shared_key {
+        if cli.match_key != MatchKey::Primary {
+            return Err(anyhow!(
+                "`--shared-key` re-dates the primary key only, drop `--match-key`"
+            ));
+        }
+        let now = Utc.timestamp_opt(latest, 0).unwrap();
+        log::info!(
+            "Sharing one keypair, each thread sweeps 1/{} of the backshift window",
+            cli.threads
+        );
+        // split across the threads by the pool, see `Worker::chunk`
+        search.time_slice = Some(TimeSlice {
+            start: latest,
+            index: 0,
+            count: 1,
+        });
+        setup.shared_key = Some(RetimedKeyGenerator::new(&search.key, now));
+    }
+    if let Some(path) = &cli.redate {
+        if cli.match_key != MatchKey::Primary {
+            return Err(anyhow!(
+                "`--redate` re-dates the primary key only, drop `--match-key`"
+            ));
+        }
+        let generator = redate_generator(cli, path, &search.key)?;
+        log::info!(
+            "Each thread sweeps 1/{} of the backshift window once",
+            cli.threads
+        );
+        search.time_slice = Some(TimeSlice {
+            start: latest,
+            index: 0,
+            count: 1,
+        });
+        setup.shared_key = Some(generator);
+        setup.sweep_once = true;
+    }
+    if cli.seed.is_some() {
+        let start = cli.seed_start.unwrap_or(latest);
+        if cli.created_between.is_some() {
+            log::info!(
+                "Seeded search of the creation range, each thread sweeps 1/{} of it once",
+                cli.threads
+            );
+        } else {
+            log::info!(
+                "Seeded search back from {}, each thread sweeps 1/{} of the backshift window once. Pass `--seed-start {}` to repeat it",
+                start,
+                cli.threads,
+                start
+            );
+        }
+        search.time_slice = Some(TimeSlice {
+            start,
+            index: 0,
+            count: 1,
+        });
+        setup.sweep_once = true;
+    }
+    if let Some(api) = cli.backend.api() {
+        if setup.collide.is_some() || setup.generate.is_some() || cli.key_type.len() > 1 {
+            return Err(anyhow!(
+                "A GPU `--backend` can't be combined with `--collide-key-id`, `generate` or several `--key-type`"
+            ));
+        }
+        let masks = gpu::masks(&search, &pattern)?;
+        log::info!(
+            "Thread 0 drives GPU #{} with {} pattern masks",
+            cli.gpu_device,
+            masks.len()
+        );
+        setup.gpu = Some(GpuSetup {
+            api,
+            device: cli.gpu_device,
+            masks: Arc::new(masks),
+        });
+    }
 
     let (msg_tx, msg_rx) = std::sync::mpsc::channel::<core::Msg>();
-    let thread_exit = Arc::new(AtomicBool::new(false));
-
-    let exit = thread_exit.clone();
-
-    // Setup ctrlc signal
-    ctrlc::set_handler(move || {
-        log::warn!("SIGNINT received, waiting all threads to exit...");
-        exit.store(true, Ordering::Relaxed);
-    })
-    .with_context(|| {
-        log::error!("Error setting Ctrl-C handler");
-        anyhow!("")
-    })?;
-
-    let handles: Vec<_> = (0..cli.threads)
-        .map(|i| {
-            let cli = cli.clone();
-            let pattern = pattern.clone();
-            let tx = msg_tx.clone();
-            let thread_exit = thread_exit.clone();
-
-            thread::spawn(move || -> Result<()> {
-                log::debug!("Thread {} has been created", i);
-                loop {
-                    core::task(
-                        cli.uid.clone(),
-                        cli.max_backshift_days,
-                        &pattern,
-                        &thread_exit,
-                        &tx,
-                    )?;
-
-                    if thread_exit.load(Ordering::Relaxed) {
-                        drop(tx);
-                        break;
-                    }
-                }
-                log::debug!("Thread {} complete", i);
-                Ok(())
-            })
-        })
-        .collect();
 
-    // drop original tx
-    drop(msg_tx);
+    let pattern = Arc::new(core::PatternSet::new(pattern));
+    let mut pool = {
+        let config = PoolConfig {
+            watchdog_timeout: (cli.watchdog_timeout > 0)
+                .then(|| Duration::from_secs(cli.watchdog_timeout)),
+            restart_stalled: cli.watchdog_restart,
+            ..PoolConfig::new(cli.threads)
+        };
+        let search = search.clone();
+        let pattern = pattern.clone();
+        let setup = setup.clone();
+        WorkerPool::start(config, thread_exit.clone(), msg_tx, move |worker| {
+            run_worker(worker, &search, &pattern, &setup)
+        })?
+    };
 
+    let hook = cli.on_found_exec.clone().map(|cmd| {
+        ExecHook::new(
+            cmd,
+            Duration::from_secs(cli.on_found_timeout),
+            cli.on_found_jobs,
+        )
+    });
+
+    let mut output = OutputDir::new(
+        cli.output.clone(),
+        cli.max_output_keys,
+        cli.max_output_bytes,
+        cli.rotate_output,
+    );
+    output.set_name_policy(NamePolicy {
+        ascii_only: cli.ascii_file_names,
+        ..Default::default()
+    });
+    if let Some(key) = campaign_key {
+        output.set_campaign_key(key);
+    }
+    if let Some(passphrase) = passphrase {
+        output.set_passphrase(passphrase);
+    }
+    output.set_revocation_cert(cli.with_revocation_cert);
+    output.set_revokers(cli.revoker.clone());
+    output.set_export(cli.export);
+    output.set_notations(cli.notation.clone());
+    if let Some(m) = &setup.collide {
+        output.set_collision_of(m.key_id());
+    }
+    let mut reporters = reporters(cli)?;
+    let started = Instant::now();
+    let mut found = 0;
     let mut last_show = Instant::now();
     let mut avrg_speed = 0.0;
     let show_speed_interval = Duration::from_secs(15);
-    for msg in msg_rx {
+    let mut last_check = Instant::now();
+    let check_interval = Duration::from_secs(1);
+    let mut last_reload_check = Instant::now();
+    let mut tried: u64 = 0;
+    let mut last_heartbeat = Instant::now();
+    let mut pattern_mtime = cli.pattern.as_deref().and_then(modified);
+    let mut found_by_pattern: HashMap<String, usize> = HashMap::new();
+    // priority below which `--preempt` dropped the patterns
+    let mut min_priority = None;
+    let mut keep_awake = if cli.keep_awake {
+        awake::KeepAwake::new()
+            .map_err(|e| log::warn!("Failed to keep the system awake: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    loop {
+        let msg = msg_rx.recv_timeout(check_interval);
+        let spec = match &msg {
+            Ok(core::Msg::Key(_, p)) => Some(spec_of(&specs, p)),
+            _ => None,
+        };
+        let subdir = spec
+            .as_ref()
+            .filter(|_| cli.split_by_pattern)
+            .map(|s| s.name());
+        let stop_hit = matches!(&msg, Ok(core::Msg::Key(_, p)) if is_stop_pattern(&cli.stop_on, p));
+        let distance = match &msg {
+            Ok(core::Msg::Key(k, p)) => utils::match_distance(k, &search.key, p),
+            _ => None,
+        };
+        let note = match &msg {
+            Ok(core::Msg::Key(k, _)) => {
+                let label = spec.as_ref().and_then(|s| s.label.clone());
+                let distance = distance.map(|d| format!("distance {}", d));
+                let keygrip = utils::keygrip_hex(k)
+                    .filter(|_| cli.match_keygrip)
+                    .map(|grip| format!("keygrip {}", grip));
+                let ssh = ssh::key_fingerprint(k)
+                    .filter(|_| !cli.ssh_pattern.is_empty())
+                    .map(|fp| format!("ssh SHA256:{}", fp));
+                let algorithm = utils::algorithm(k).filter(|_| cli.key_type.len() > 1);
+                let notes: Vec<_> = label
+                    .into_iter()
+                    .chain(algorithm)
+                    .chain(distance)
+                    .chain(keygrip)
+                    .chain(ssh)
+                    .collect();
+                if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", notes.join(", "))
+                }
+            }
+            Ok(core::Msg::Scored(_, score)) => format!(" (score {:.2})", score),
+            Ok(core::Msg::Detected(k, Detector::Words)) => {
+                match detect::spelled_word(&utils::key2hex(k)) {
+                    Some(word) => format!(" (detector: words, {})", word),
+                    None => " (detector: words)".to_string(),
+                }
+            }
+            Ok(core::Msg::Detected(_, detector)) => format!(" (detector: {})", detector),
+            _ => String::new(),
+        };
         match msg {
-            core::Msg::Key(k) => {
-                log::info!("Find key: {}", utils::key2hex(&k));
-                utils::save_key(&k, cli.output.clone())?;
+            Ok(core::Msg::Key(k, _) | core::Msg::Scored(k, _) | core::Msg::Detected(k, _)) => {
+                if cli.max_keys.is_some_and(|n| found >= n) {
+                    continue;
+                }
+                if let Some(spec) = &spec {
+                    if quota_met(spec, &found_by_pattern) {
+                        log::debug!("Quota of `{}` reached, skipping key", spec.name());
+                        continue;
+                    }
+                    if preempted(spec, min_priority) {
+                        log::debug!("`{}` was preempted, skipping key", spec.name());
+                        continue;
+                    }
+                }
+                let uid = spec.as_ref().and_then(|s| s.uid.clone());
+                let k = match &uid {
+                    Some(uid) => Box::new(utils::with_uid(*k, uid)),
+                    None => k,
+                };
+                let k = if cli.with_encryption_subkey {
+                    Box::new(utils::with_encryption_subkey(*k))
+                } else {
+                    k
+                };
+                log::info!("Find key: {}{}", utils::key2hex(&k), note);
+                let found_key = match output.save_match(&k, subdir.as_deref(), distance) {
+                    Ok(Some(found_key)) => found_key,
+                    Ok(None) => {
+                        if thread_exit.stop(StopReason::QuotaReached) {
+                            log::warn!("Output quota reached, waiting all threads to exit...");
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to save key: {}", e);
+                        thread_exit.stop(StopReason::SinkError);
+                        continue;
+                    }
+                };
+                if let Some(e) = &found_key.self_check_error {
+                    log::error!(
+                        "!!! Key {} failed its self-check, don't use it: {}",
+                        found_key.fingerprint,
+                        e
+                    );
+                }
+                found += 1;
+                report_all(&mut reporters, |r| r.found(&found_key.fingerprint));
+                if let Some(spec) = &spec {
+                    *found_by_pattern.entry(spec.name()).or_default() += 1;
+                    let met = quota_met(spec, &found_by_pattern);
+                    if met {
+                        log::info!("Quota of `{}` met, no longer searching it", spec.name());
+                    }
+                    let dropped = specs
+                        .iter()
+                        .filter(|s| {
+                            !preempted(s, min_priority) && preempted(s, Some(spec.priority))
+                        })
+                        .count();
+                    let preempt = cli.preempt && dropped > 0;
+                    if preempt {
+                        log::info!(
+                            "`{}` found with priority {}, dropping {} patterns of lower priority",
+                            spec.name(),
+                            spec.priority,
+                            dropped
+                        );
+                        min_priority = Some(spec.priority);
+                    }
+                    if met || preempt {
+                        let active = active_patterns(&specs, &found_by_pattern, min_priority);
+                        let searching = active
+                            .iter()
+                            .any(|p| !matches!(p, core::Pattern::Exclude(_)))
+                            || !cli.detect.is_empty()
+                            || cli.score_weights.is_some();
+                        pattern.replace(active);
+                        if !searching && thread_exit.stop(StopReason::CountReached) {
+                            log::info!("No pattern left to search, waiting all threads to exit...");
+                        }
+                    }
+                }
+                if cli.max_keys.is_some_and(|n| found >= n)
+                    && thread_exit.stop(StopReason::CountReached)
+                {
+                    log::info!("{} keys found, waiting all threads to exit...", found);
+                }
+                if stop_hit && thread_exit.stop(StopReason::PatternFound) {
+                    log::info!("Stop pattern found, waiting all threads to exit...");
+                }
+                if let Some(hook) = &hook {
+                    let envs = vec![
+                        ("APGPK_KEY_PATH", found_key.path.display().to_string()),
+                        ("APGPK_KEY_ID", found_key.key_id),
+                        ("APGPK_FINGERPRINT", found_key.fingerprint),
+                        ("APGPK_UID", uid.unwrap_or_else(|| search.key.uid.clone())),
+                    ];
+                    if let Err(e) = hook.run(envs) {
+                        log::error!("Failed to run `--on-found-exec` command: {}", e);
+                    }
+                }
             }
-            core::Msg::Speed(current_speed) => {
+            Ok(core::Msg::Speed(keys, current_speed)) => {
+                tried += keys;
                 let now = Instant::now();
                 avrg_speed = (2.0 * avrg_speed + current_speed) / 3.0;
                 if (now - last_show) > show_speed_interval {
-                    log::info!(
-                        "Current speed estimated ({} threads) {:.2} key/s",
-                        cli.threads,
-                        avrg_speed * cli.threads as f64
-                    );
+                    let rate = avrg_speed * cli.threads as f64;
+                    let skipped = pool.skipped();
+                    let progress = snapshot(cli, tried, skipped, rate, found, started, &pattern);
+                    report_all(&mut reporters, |r| r.report(&progress));
+                    if cli.progress.contains(&ProgressKind::Log) {
+                        // with sharding every pattern is only tried by one thread
+                        let share = if sharded { 1 } else { cli.threads };
+                        log_pattern_eta(
+                            &pattern.load().1,
+                            tried as f64 * share as f64 / cli.threads as f64,
+                            avrg_speed * share as f64,
+                        );
+                    }
                     last_show = now;
                 }
             }
+            Err(RecvTimeoutError::Timeout) => {
+                // the channel is drained and no live worker can send anymore
+                if thread_exit.is_stopped() && pool.all_finished() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if thread_exit.is_stopped() && keep_awake.take().is_some() {
+            log::debug!("System may sleep again");
         }
+
+        // workers only return once stopped, unless they failed or swept
+        // their slice of a seeded search
+        if pool.failed() > 0 && thread_exit.stop(StopReason::WorkerFailed) {
+            log::error!("A worker thread exited unexpectedly, waiting all threads to exit...");
+        }
+        if pool.exhausted() == cli.threads && thread_exit.stop(StopReason::Exhausted) {
+            log::info!("Every creation time of the seeded search has been tried, exiting...");
+        }
+
+        if cli
+            .max_duration
+            .is_some_and(|secs| started.elapsed() >= Duration::from_secs(secs))
+            && thread_exit.stop(StopReason::Deadline)
+        {
+            log::warn!("Time limit reached, waiting all threads to exit...");
+        }
+
+        if cli.heartbeat_interval > 0
+            && last_heartbeat.elapsed() >= Duration::from_secs(cli.heartbeat_interval)
+        {
+            last_heartbeat = Instant::now();
+            log::info!(
+                "heartbeat tried={} rate={:.2} found={} uptime={}",
+                tried,
+                avrg_speed * cli.threads as f64,
+                found,
+                started.elapsed().as_secs()
+            );
+        }
+
+        if cli.watch && last_reload_check.elapsed() >= check_interval {
+            last_reload_check = Instant::now();
+            let mtime = cli.pattern.as_deref().and_then(modified);
+            if mtime != pattern_mtime {
+                pattern_mtime = mtime;
+                match load_patterns(cli, opts) {
+                    Ok(mut reloaded) => {
+                        reloaded.extend(imitation.clone().map(PatternSpec::new));
+                        log::info!(
+                            "Pattern file changed, find key by pattern {:?}",
+                            reloaded
+                                .iter()
+                                .map(|s| s.pattern.to_string())
+                                .collect::<Vec<_>>()
+                        );
+                        pattern.replace(active_patterns(
+                            &reloaded,
+                            &found_by_pattern,
+                            min_priority,
+                        ));
+                        specs = reloaded;
+                    }
+                    Err(e) => log::error!("Failed to reload patterns, keeping the old ones: {}", e),
+                }
+            }
+        }
+
+        if last_check.elapsed() < check_interval {
+            continue;
+        }
+        last_check = Instant::now();
+        pool.check()?;
     }
 
-    handles.into_iter().for_each(|h| {
-        h.join().unwrap().unwrap();
-    });
+    let skipped = pool.skipped();
+    if skipped > 0 {
+        log::info!(
+            "{} creation times already swept by a replaced thread with the same keypair were skipped",
+            skipped
+        );
+    }
+    let abandoned = pool.join();
+    if abandoned > 0 {
+        log::warn!(
+            "{} stuck threads were abandoned and never returned",
+            abandoned
+        );
+    }
 
-    log::info!("Shutdown");
+    let progress = snapshot(
+        cli,
+        tried,
+        skipped,
+        avrg_speed * cli.threads as f64,
+        found,
+        started,
+        &pattern,
+    );
+    report_all(&mut reporters, |r| r.finish(&progress));
 
-    Ok(())
+    if let Some(hook) = &hook {
+        hook.wait_idle();
+    }
+
+    match thread_exit.reason() {
+        Some(reason) => {
+            log::info!("Shutdown: {}", reason);
+            Ok(reason.exit_code())
+        }
+        None => {
+            log::info!("Shutdown");
+            Ok(0)
+        }
+    }
 }
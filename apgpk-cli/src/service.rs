@@ -0,0 +1,127 @@
+//! Run the search as a Windows service, stopped gracefully by the service
+//! control manager like by Ctrl-C.
+
+use anyhow::{anyhow, Result};
+use apgpk_lib::stop::{StopReason, StopSignal};
+use std::{
+    ffi::OsString,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+pub const SERVICE_NAME: &str = "apgpk";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// Search run by the service until the stop signal, returning the exit code
+pub type Search = Box<dyn FnOnce(Arc<StopSignal>) -> Result<u8> + Send>;
+
+// the service entry point is a plain function, so it picks the search up here
+static SEARCH: Mutex<Option<Search>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Register the service, started at boot with `args`
+pub fn install(args: Vec<OsString>) -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: "apgpk".into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: args,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description("Find an awesome PGP key")?;
+    Ok(())
+}
+
+/// Stop the service if running and remove it
+pub fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+    Ok(())
+}
+
+/// Hand the process over to the service control manager, blocking until the
+/// service stopped.
+pub fn run(search: Search) -> Result<()> {
+    *SEARCH.lock().unwrap_or_else(PoisonError::into_inner) = Some(search);
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        log::error!("Service failed: {}", e);
+    }
+}
+
+fn run_service() -> Result<()> {
+    let stop = Arc::new(StopSignal::new());
+    let exit = stop.clone();
+    let handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            log::warn!("Service stop requested, waiting all threads to exit...");
+            exit.stop(StopReason::Signal);
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let status = |current_state, exit_code| ServiceStatus {
+        service_type: SERVICE_TYPE,
+        current_state,
+        controls_accepted: match current_state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        },
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+    handle.set_service_status(status(ServiceState::Running, ServiceExitCode::Win32(0)))?;
+
+    let search = SEARCH
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+        .ok_or_else(|| anyhow!("Service started twice"))?;
+    let exit_code = match search(stop.clone()) {
+        // a stop requested by the service control manager isn't a failure
+        Ok(_) if stop.reason() == Some(StopReason::Signal) => ServiceExitCode::Win32(0),
+        Ok(0) => ServiceExitCode::Win32(0),
+        Ok(code) => ServiceExitCode::ServiceSpecific(code.into()),
+        Err(e) => {
+            log::error!("{}", e);
+            ServiceExitCode::ServiceSpecific(1)
+        }
+    };
+    handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+    Ok(())
+}
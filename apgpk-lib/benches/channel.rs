@@ -0,0 +1,50 @@
+use apgpk_lib::{
+    clock::SystemClock,
+    config::{KeyConfig, SearchConfig},
+    core::{search, MatchMode, Pattern, PatternSet},
+    keygen::MockKeyGenerator,
+    stop::StopSignal,
+    watchdog::Heartbeat,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{sync::mpsc, thread};
+
+/// A day of mock keys with one in 16 matching, sending every match through
+/// the channel right away or with the progress reports.
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("channel");
+    group.sample_size(10);
+    let pars = PatternSet::new(vec![Pattern::literal("A", MatchMode::Suffix)]);
+    for batch_results in [false, true] {
+        let config = SearchConfig {
+            batch_results,
+            ..SearchConfig::new(KeyConfig::default(), 1)
+        };
+        let name = if batch_results {
+            "batched"
+        } else {
+            "per_candidate"
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let (tx, rx) = mpsc::channel();
+                // drained on another thread like the receiver of the cli
+                let receiver = thread::spawn(move || rx.into_iter().count());
+                search::<MockKeyGenerator, _>(
+                    &config,
+                    pars.matcher(None).unwrap(),
+                    &StopSignal::new(),
+                    &tx,
+                    &Heartbeat::default(),
+                    &SystemClock,
+                )
+                .unwrap();
+                drop(tx);
+                receiver.join().unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use apgpk_lib::core::{MatchMode, Pattern, PatternMatcher};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Cheap deterministic pseudo random bytes
+fn fingerprints(n: usize) -> Vec<Vec<u8>> {
+    let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+    (0..n)
+        .map(|_| {
+            (0..20)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    x as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Pattern number i of a set
+type PatternOf = fn(u32) -> String;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let fps = fingerprints(1024);
+    let mut group = c.benchmark_group("matcher");
+    // the same number of suffixes, ending with one byte (pre-filtered) or with
+    // all possible bytes
+    let sets: [(&str, PatternOf); 2] = [
+        ("suffix_one_last_byte", |i| format!("{:05X}EF", i)),
+        ("suffix_any_last_byte", |i| {
+            format!("{:07X}", i.wrapping_mul(7919))
+        }),
+    ];
+    for (name, pattern) in sets {
+        let pars: Vec<Pattern> = (0..1000)
+            .map(|i| Pattern::literal(&pattern(i), MatchMode::Suffix))
+            .collect();
+        let matcher = PatternMatcher::new(&pars).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for fp in &fps {
+                    black_box(matcher.find(black_box(fp)));
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
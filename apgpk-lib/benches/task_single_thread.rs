@@ -1,23 +1,43 @@
-use apgpk_lib::core::{task, Msg};
+use apgpk_lib::{
+    clock::SystemClock,
+    config::{KeyConfig, SearchConfig},
+    core::{task, MatchMode, Msg, Pattern, PatternSet},
+    stop::StopSignal,
+    watchdog::Heartbeat,
+};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use std::sync::{atomic::AtomicBool, Arc};
 
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("task_single_thread");
     group.sample_size(10);
-    group.bench_function("task_single_thread", |b| {
-        b.iter(|| {
-            let exit = Arc::new(AtomicBool::new(false));
-            let (tx, _rx) = std::sync::mpsc::channel::<Msg>();
-            task(
-                "test".to_string(),
-                black_box(1),
-                &["AAAAAAAA".to_string(), "BBBBBBBB".to_string()],
-                &exit,
-                &tx,
-            )
-        })
-    });
+    let pars = PatternSet::new(vec![
+        Pattern::literal("AAAAAAAA", MatchMode::Suffix),
+        Pattern::literal("BBBBBBBB", MatchMode::Suffix),
+    ]);
+    // re-dated keypairs, and a fresh one for every creation time
+    for (name, timestamps_per_key) in [
+        ("task_single_thread", None),
+        ("task_single_thread_fresh_keys", Some(1)),
+    ] {
+        let key = KeyConfig {
+            timestamps_per_key,
+            ..KeyConfig::new("test".to_string())
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let exit = StopSignal::new();
+                let (tx, _rx) = std::sync::mpsc::channel::<Msg>();
+                task(
+                    &SearchConfig::new(key.clone(), black_box(1)),
+                    pars.matcher(None).unwrap(),
+                    &exit,
+                    &tx,
+                    &Heartbeat::default(),
+                    &SystemClock,
+                )
+            })
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
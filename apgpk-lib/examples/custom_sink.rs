@@ -0,0 +1,65 @@
+//! Save found keys through [`OutputDir`] and print their metadata as JSON
+//! lines instead of logging them, e.g. to feed another program.
+//!
+//! `cargo run --release --example custom_sink -- ./keys`
+
+use apgpk_lib::{
+    clock::SystemClock,
+    config::{KeyConfig, SearchConfig},
+    core::{self, MatchMode, Msg, Pattern, PatternSet},
+    output::OutputDir,
+    pool::{PoolConfig, Worker, WorkerPool},
+    stop::{StopReason, StopSignal},
+    utils,
+};
+use std::sync::{mpsc, Arc};
+
+fn main() {
+    let dir = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./key_output".to_string());
+    utils::check_output_dir(&dir).unwrap();
+    // keep at most 3 keys
+    let mut output = OutputDir::new(dir, Some(3), None, false);
+
+    let search = SearchConfig::new(KeyConfig::default(), 1);
+    let patterns = PatternSet::new(vec![Pattern::literal("FFFFF", MatchMode::Suffix)]);
+    let stop = Arc::new(StopSignal::new());
+    let (tx, rx) = mpsc::channel();
+
+    let pool = WorkerPool::start(
+        PoolConfig::new(1),
+        stop.clone(),
+        tx,
+        move |worker: Worker<Msg>| {
+            patterns.matcher(search.shard).and_then(|matcher| {
+                core::task(
+                    &search,
+                    matcher,
+                    &worker.exit,
+                    &worker.tx,
+                    &worker.heartbeat,
+                    &SystemClock,
+                )
+            })
+        },
+    )
+    .unwrap();
+
+    for msg in rx {
+        let Msg::Key(k, _) = msg else {
+            continue;
+        };
+        match output.save(&k, None) {
+            Ok(Some(found)) => println!("{}", serde_json::to_string(&found).unwrap()),
+            Ok(None) => {
+                stop.stop(StopReason::QuotaReached);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                stop.stop(StopReason::SinkError);
+            }
+        }
+    }
+    pool.join();
+}
@@ -0,0 +1,60 @@
+//! Run the search inside an application and stop at the first match.
+//!
+//! `cargo run --release --example embed -- 4BEEF`
+
+use apgpk_lib::{
+    clock::SystemClock,
+    config::{KeyConfig, SearchConfig},
+    core::{self, MatchMode, Msg, Pattern, PatternSet},
+    pool::{PoolConfig, Worker, WorkerPool},
+    stop::{StopReason, StopSignal},
+    utils,
+};
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+};
+
+fn main() {
+    let suffix = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "4BEEF".to_string());
+    let search = SearchConfig::new(
+        KeyConfig::new("Example <example@example.org>".to_string()),
+        1,
+    );
+    search.key.validate().unwrap();
+    let patterns = PatternSet::new(vec![Pattern::literal(&suffix, MatchMode::Suffix)]);
+    let stop = Arc::new(StopSignal::new());
+    let (tx, rx) = mpsc::channel();
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let pool = WorkerPool::start(
+        PoolConfig::new(threads),
+        stop.clone(),
+        tx,
+        move |worker: Worker<Msg>| {
+            let search = worker.chunk(&search);
+            patterns.matcher(search.shard).and_then(|matcher| {
+                core::task(
+                    &search,
+                    matcher,
+                    &worker.exit,
+                    &worker.tx,
+                    &worker.heartbeat,
+                    &SystemClock,
+                )
+            })
+        },
+    )
+    .unwrap();
+
+    // until every worker is done and has dropped its sender
+    for msg in rx {
+        if let Msg::Key(k, _) = msg {
+            println!("{}", utils::key2hex(&k));
+            stop.stop(StopReason::CountReached);
+        }
+    }
+    pool.join();
+}
@@ -0,0 +1,32 @@
+//! Match fingerprints coming from elsewhere, e.g. a list of existing keys,
+//! against a pattern file without generating any key.
+//!
+//! `gpg --with-colons --fingerprint | awk -F: '/^fpr/ {print $10}' | cargo run --example matcher -- patterns.txt`
+
+use apgpk_lib::{core::PatternMatcher, utils};
+use std::io::{self, BufRead};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: matcher <PATTERN FILE>");
+    let pattern: Vec<_> = utils::parse_pattern(path, utils::PatternOptions::default())
+        .unwrap()
+        .into_iter()
+        .map(|spec| spec.pattern)
+        .collect();
+    let matcher = PatternMatcher::new(&pattern).unwrap();
+
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap();
+        let Ok(fp) = hex::decode(line.trim()) else {
+            continue;
+        };
+        if fp.len() != 20 {
+            continue;
+        }
+        if let Some(i) = matcher.find(&fp) {
+            println!("{} {}", line.trim(), pattern[i]);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Search a key sharing the 64-bit long key ID of an existing key, for
+//! research on key ID collisions.
+//!
+//! Such a key can be mistaken for the original by anyone identifying keys by
+//! their key ID, so the keys found are given a user ID and metadata marking
+//! them as demonstration keys.
+
+use crate::{
+    core::{Literal, MatchInfo, MatchMode, Matcher, Pattern, Target, FP_HEX_LEN, FP_LEN},
+    error::ApgpkError,
+};
+
+/// Hex digits of a long key ID
+pub const KEY_ID_HEX_LEN: usize = 16;
+
+/// [`Matcher`] comparing the last 8 bytes of the fingerprint to a key ID
+#[derive(Debug, Clone)]
+pub struct KeyIdMatcher {
+    key_id: [u8; KEY_ID_HEX_LEN / 2],
+    pattern: Pattern,
+}
+
+impl KeyIdMatcher {
+    /// Matcher of a long key ID, or of the key ID of a fingerprint, as
+    /// printed by GnuPG with or without spaces and `0x`
+    pub fn new(key_id: &str) -> Result<Self, ApgpkError> {
+        let hex: String = key_id
+            .trim()
+            .trim_start_matches("0x")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        // the key ID is the end of the fingerprint
+        let hex = match hex.len() {
+            FP_HEX_LEN if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                hex[FP_HEX_LEN - KEY_ID_HEX_LEN..].to_string()
+            }
+            _ => hex,
+        };
+        let mut id = [0; KEY_ID_HEX_LEN / 2];
+        hex::decode_to_slice(&hex, &mut id).map_err(|_| {
+            ApgpkError::Other(format!(
+                "`{}` isn't a {}-digit long key ID or a fingerprint",
+                key_id, KEY_ID_HEX_LEN
+            ))
+        })?;
+        Ok(Self {
+            key_id: id,
+            pattern: Pattern::Literal(Literal::new(&hex, MatchMode::Suffix, Target::KeyId)),
+        })
+    }
+
+    /// The key ID in upper case hex
+    pub fn key_id(&self) -> String {
+        hex::encode_upper(self.key_id)
+    }
+
+    /// The key ID as pattern, sent along with the keys found
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// User ID given to the keys found, so they can't pass for the original
+    pub fn demo_uid(&self) -> String {
+        format!(
+            "apgpk key ID collision demo of {}, not its owner",
+            self.key_id()
+        )
+    }
+}
+
+impl Matcher for KeyIdMatcher {
+    fn matches(&self, fingerprint: &[u8]) -> Option<MatchInfo> {
+        (fingerprint[FP_LEN - self.key_id.len()..] == self.key_id).then(|| MatchInfo {
+            pattern: self.pattern.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_id_matcher() {
+        let fp = hex::decode("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF").unwrap();
+        let m = KeyIdMatcher::new("0xE5F1D9C7A2B4BEEF").unwrap();
+        assert_eq!(m.key_id(), "E5F1D9C7A2B4BEEF");
+        assert!(m.matches(&fp).is_some());
+        assert!(m.pattern().is_match(&fp));
+        let from_fpr =
+            KeyIdMatcher::new("0000 0000 0000 0000 0000  A8B3 E5F1 D9C7 A2B4 BEEF").unwrap();
+        assert!(from_fpr.matches(&fp).is_some());
+        assert!(KeyIdMatcher::new("e5f1d9c7a2b4beee")
+            .unwrap()
+            .matches(&fp)
+            .is_none());
+        assert!(KeyIdMatcher::new("A2B4BEEF").is_err());
+        assert!(KeyIdMatcher::new("E5F1D9C7A2B4BEEG").is_err());
+    }
+}
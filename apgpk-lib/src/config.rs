@@ -0,0 +1,931 @@
+use crate::{
+    core::FP_LEN, coverage::Coverage, detect::Detector, error::ApgpkError, prefs::Preferences,
+    score::Scorer, seed::Seed, ssh::SshPattern,
+};
+use chrono::prelude::*;
+pub use pgp::composed::KeyType;
+use pgp::{
+    crypto::ecc_curve::ECCCurve,
+    types::{EcdsaPublicParams, PublicParams},
+};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+
+/// Parameters of the generated keys, checked once by [`KeyConfig::validate`]
+/// before any worker is started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    /// Primary user ID
+    pub uid: String,
+    /// Further user IDs bound to the key, e.g. other email addresses
+    pub user_ids: Vec<String>,
+    #[serde(with = "key_type_serde")]
+    pub key_type: KeyType,
+    pub can_certify: bool,
+    pub can_sign: bool,
+    pub can_encrypt: bool,
+    /// Authentication, e.g. SSH logins through gpg-agent
+    pub can_authenticate: bool,
+    /// Add an ECDH encryption subkey
+    pub encryption_subkey: bool,
+    /// Which fingerprint the patterns are matched against
+    pub match_key: MatchKey,
+    /// OpenPGP key packet version, which decides the fingerprint algorithm
+    pub version: KeyVersion,
+    /// Match the GnuPG keygrip of the primary key instead of fingerprints,
+    /// see [`crate::keygrip`]
+    pub match_keygrip: bool,
+    /// Only keys whose OpenSSH fingerprint matches one of these are matched
+    /// against the patterns, see [`crate::ssh`]
+    pub ssh_patterns: Vec<SshPattern>,
+    /// Days the key is valid after its creation, never expiring if `None`
+    pub expiration_days: Option<u16>,
+    /// Algorithm preferences of the self signature, the backend defaults if
+    /// `None`
+    pub preferences: Option<Preferences>,
+    /// Derive the key material from this seed, see [`crate::seed`]
+    pub seed: Option<Seed>,
+    /// Creation times tried per keypair, by key type if `None`, see
+    /// [`KeyConfig::timestamps_per_key`]
+    pub timestamps_per_key: Option<u32>,
+}
+
+/// OpenPGP key version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum KeyVersion {
+    /// RFC 4880 keys with SHA-1 fingerprints
+    #[default]
+    V4,
+    /// RFC 9580 keys with SHA-256 fingerprints
+    V6,
+}
+
+impl KeyVersion {
+    /// Fingerprint length in bytes
+    pub fn fingerprint_len(self) -> usize {
+        match self {
+            KeyVersion::V4 => FP_LEN,
+            KeyVersion::V6 => 32,
+        }
+    }
+}
+
+impl TryFrom<u8> for KeyVersion {
+    type Error = ApgpkError;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            4 => Ok(KeyVersion::V4),
+            6 => Ok(KeyVersion::V6),
+            _ => Err(invalid(format!(
+                "Unknown key version `{}`, expect 4 or 6",
+                v
+            ))),
+        }
+    }
+}
+
+impl From<KeyVersion> for u8 {
+    fn from(v: KeyVersion) -> Self {
+        match v {
+            KeyVersion::V4 => 4,
+            KeyVersion::V6 => 6,
+        }
+    }
+}
+
+impl FromStr for KeyVersion {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let v = s
+            .trim()
+            .trim_start_matches(['v', 'V'])
+            .parse::<u8>()
+            .map_err(|_| invalid(format!("Unknown key version `{}`, expect 4 or 6", s)))?;
+        KeyVersion::try_from(v)
+    }
+}
+
+/// Key whose fingerprint is matched when an encryption subkey is generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKey {
+    #[default]
+    Primary,
+    Subkey,
+    /// Either the primary key or the subkey
+    Either,
+}
+
+impl FromStr for MatchKey {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "primary" => Ok(MatchKey::Primary),
+            "subkey" => Ok(MatchKey::Subkey),
+            "either" => Ok(MatchKey::Either),
+            _ => Err(ApgpkError::Other(format!(
+                "Unknown key `{}`, expect one of primary, subkey, either",
+                s
+            ))),
+        }
+    }
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            uid: String::from("apgpk"),
+            user_ids: vec![],
+            key_type: KeyType::EdDSA,
+            can_certify: true,
+            can_sign: true,
+            can_encrypt: false,
+            can_authenticate: false,
+            encryption_subkey: false,
+            match_key: MatchKey::Primary,
+            version: KeyVersion::V4,
+            match_keygrip: false,
+            ssh_patterns: vec![],
+            expiration_days: None,
+            preferences: None,
+            seed: None,
+            timestamps_per_key: None,
+        }
+    }
+}
+
+impl KeyConfig {
+    pub fn new(uid: String) -> Self {
+        Self {
+            uid,
+            ..Default::default()
+        }
+    }
+
+    /// Creation times tried per generated keypair by re-dating it before a
+    /// fresh one is generated, see [`crate::keygen::SweepKeyGenerator`].
+    ///
+    /// The configured number, or by default about as many as generating a
+    /// keypair costs in fingerprints. Always 1 when something besides the
+    /// primary key fingerprint is matched, as the subkeys, the keygrip and
+    /// the SSH fingerprint stay the same while re-dating.
+    pub fn timestamps_per_key(&self) -> u32 {
+        if self.match_key != MatchKey::Primary
+            || self.match_keygrip
+            || !self.ssh_patterns.is_empty()
+        {
+            return 1;
+        }
+        self.timestamps_per_key
+            .unwrap_or(match self.key_type {
+                KeyType::Rsa(_) => RSA_TIMESTAMPS_PER_KEY,
+                KeyType::ECDH | KeyType::EdDSA | KeyType::ECDSA(_) => ECC_TIMESTAMPS_PER_KEY,
+            })
+            .max(1)
+    }
+
+    /// Index of the keypair tried at `created_at`, a fresh one every
+    /// [`KeyConfig::timestamps_per_key`] seconds
+    pub fn keypair_counter(&self, created_at: DateTime<Utc>) -> i64 {
+        created_at
+            .timestamp()
+            .div_euclid(self.timestamps_per_key() as i64)
+    }
+
+    /// Check the combination of options is something the pgp crate can build.
+    pub fn validate(&self) -> Result<(), ApgpkError> {
+        validate_uid(&self.uid)?;
+        for (i, uid) in self.user_ids.iter().enumerate() {
+            validate_uid(uid)?;
+            if *uid == self.uid || self.user_ids[..i].contains(uid) {
+                return Err(invalid(format!("user ID `{}` is given twice", uid)));
+            }
+        }
+
+        // pgp 0.10 writes and fingerprints v4 key packets only
+        if self.version != KeyVersion::V4 {
+            return Err(invalid(format!(
+                "v{} keys aren't supported by the OpenPGP backend yet, use key version 4",
+                u8::from(self.version)
+            )));
+        }
+
+        let name = key_type_name(&self.key_type);
+        let (sign, encrypt) = capabilities(&self.key_type);
+        if (self.can_sign || self.can_certify || self.can_authenticate) && !sign {
+            return Err(invalid(format!(
+                "{} keys can't sign, certify or authenticate, use an algorithm capable of signing",
+                name
+            )));
+        }
+        if self.can_encrypt && !encrypt {
+            return Err(invalid(format!(
+                "{} keys can't encrypt, drop the encryption capability",
+                name
+            )));
+        }
+        if self.match_key != MatchKey::Primary && !self.encryption_subkey {
+            return Err(invalid(
+                "matching the subkey fingerprint needs an encryption subkey",
+            ));
+        }
+        if self.match_keygrip && self.match_key != MatchKey::Primary {
+            return Err(invalid("keygrips are matched for the primary key only"));
+        }
+        if self.match_keygrip && matches!(self.key_type, KeyType::ECDSA(_)) {
+            return Err(invalid(
+                "keygrips are computed for RSA, Ed25519 and Cv25519 keys only",
+            ));
+        }
+        if !self.ssh_patterns.is_empty() && self.key_type != KeyType::EdDSA {
+            return Err(invalid(format!(
+                "SSH fingerprints are matched for Ed25519 keys only, not {}",
+                name
+            )));
+        }
+        if !(self.can_sign
+            || self.can_certify
+            || self.can_encrypt
+            || self.can_authenticate
+            || self.encryption_subkey)
+        {
+            return Err(invalid("the key must have at least one capability"));
+        }
+        if self.timestamps_per_key == Some(0) {
+            return Err(invalid("at least 1 timestamp must be tried per keypair"));
+        }
+        if self.timestamps_per_key.is_some_and(|n| n > 1)
+            && (self.match_key != MatchKey::Primary
+                || self.match_keygrip
+                || !self.ssh_patterns.is_empty())
+        {
+            return Err(invalid(
+                "re-dating a keypair keeps its subkeys, keygrip and SSH fingerprint, match the primary key fingerprint only to try several timestamps per keypair",
+            ));
+        }
+        if self.expiration_days == Some(0) {
+            return Err(invalid("an expiration of 0 days is already expired"));
+        }
+        if self.expiration_days > Some(MAX_EXPIRATION_DAYS) {
+            return Err(invalid(format!(
+                "an expiration of more than {} days can't be written into the key",
+                MAX_EXPIRATION_DAYS
+            )));
+        }
+        if let KeyType::Rsa(bits) = self.key_type {
+            if !(2048..=4096).contains(&bits) {
+                return Err(invalid(format!(
+                    "RSA key size {} is out of the supported range 2048..=4096",
+                    bits
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creation times swept per RSA keypair, about the cost of generating one
+pub const RSA_TIMESTAMPS_PER_KEY: u32 = 1 << 16;
+
+/// Creation times swept per elliptic curve keypair, Ed25519, Curve25519 or
+/// NIST P-256/P-384
+pub const ECC_TIMESTAMPS_PER_KEY: u32 = 1 << 12;
+
+/// Everything a search worker needs besides its patterns and channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchConfig {
+    pub key: KeyConfig,
+    pub max_backshift_days: u16,
+    /// Keep keys by score rather than by pattern match, every key reaching
+    /// the threshold is sent as [`crate::core::Msg::Scored`]
+    #[serde(default)]
+    pub scorer: Option<Scorer>,
+    /// Keep keys caught by these detectors besides the pattern matches, sent
+    /// as [`crate::core::Msg::Detected`] when they miss every pattern
+    #[serde(default)]
+    pub detectors: Vec<Detector>,
+    /// (index, count) of the shard of the patterns this worker matches,
+    /// exclusions are always matched
+    #[serde(default)]
+    pub shard: Option<(usize, usize)>,
+    /// Keep the found keys in the worker and send them only with its
+    /// progress reports, see [`crate::core::REPORT_INTERVAL`], for candidate
+    /// rates where the channel itself costs. They are sent right before the
+    /// [`crate::core::Msg::Speed`] counting them as tried.
+    #[serde(default)]
+    pub batch_results: bool,
+    /// Part of the backshift window this worker sweeps, when all workers
+    /// share one key. The slice is swept back from its start.
+    #[serde(default)]
+    pub time_slice: Option<TimeSlice>,
+    /// Sweep these creation times back from the latest instead of the
+    /// backshift window back from now
+    #[serde(default)]
+    pub created_between: Option<CreationRange>,
+    /// Start the backshift window this many seconds in the future, at most
+    /// [`MAX_FUTURE_SECS`]. Ignored with `created_between`.
+    #[serde(default)]
+    pub future_secs: u32,
+    /// Creation times this worker already swept, skipped when tried again
+    /// with the same [`crate::keygen::KeyGenerator::keypair_id`] and counted
+    /// there rather than as tried
+    #[serde(skip)]
+    pub coverage: Option<Arc<Coverage>>,
+}
+
+/// Bound of [`SearchConfig::future_secs`]
+pub const MAX_FUTURE_SECS: u32 = 10 * 60;
+
+/// Creation times from `earliest` to `latest`, unix times both included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreationRange {
+    pub earliest: i64,
+    pub latest: i64,
+}
+
+impl CreationRange {
+    /// Number of creation times in the range
+    pub fn len(&self) -> i64 {
+        self.latest - self.earliest + 1
+    }
+
+    /// Whether the range ends before it starts
+    pub fn is_empty(&self) -> bool {
+        self.len() <= 0
+    }
+
+    /// Check the range doesn't reach into the future
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), ApgpkError> {
+        if self.latest > now.timestamp() {
+            return Err(invalid(format!(
+                "creation range ends at {}, in the future",
+                self.latest
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// `FROM..TO` of two `YYYY-MM-DD` UTC dates, both days included
+impl FromStr for CreationRange {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((from, to)) = s.split_once("..") else {
+            return Err(invalid(format!(
+                "Invalid creation range `{}`, expect e.g. 2021-01-01..2021-12-31",
+                s
+            )));
+        };
+        let day = |d: &str| {
+            NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+                .map(|d| {
+                    d.and_hms_opt(0, 0, 0)
+                        .unwrap_or_default()
+                        .and_utc()
+                        .timestamp()
+                })
+                .map_err(|e| invalid(format!("Invalid date `{}` in the creation range: {}", d, e)))
+        };
+        let range = CreationRange {
+            earliest: day(from)?,
+            latest: day(to)? + 24 * 60 * 60 - 1,
+        };
+        if range.earliest < 0 {
+            return Err(invalid("creation range starts before 1970-01-01"));
+        }
+        if range.latest > u32::MAX as i64 {
+            return Err(invalid("creation range ends after 2106-02-07"));
+        }
+        if range.is_empty() {
+            return Err(invalid(format!("creation range `{}` is empty", s)));
+        }
+        Ok(range)
+    }
+}
+
+/// Slice `index` of `count` equal, disjoint slices of the backshift window
+/// starting at the unix time `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSlice {
+    pub start: i64,
+    pub index: usize,
+    pub count: usize,
+}
+
+impl TimeSlice {
+    /// Backshifts in seconds of this slice of a `window` seconds window, the
+    /// last slice takes the remainder
+    pub fn backshifts(&self, window: i64) -> std::ops::Range<i64> {
+        let (index, count) = (self.index as i64, self.count.max(1) as i64);
+        let len = window / count;
+        let end = if index + 1 == count {
+            window
+        } else {
+            (index + 1) * len
+        };
+        index * len..end
+    }
+}
+
+impl SearchConfig {
+    /// Length in seconds of the window of creation times swept
+    pub fn window(&self) -> i64 {
+        match self.created_between {
+            Some(range) => range.len(),
+            None => self.max_backshift_days as i64 * 24 * 60 * 60 + self.future_secs as i64,
+        }
+    }
+
+    /// Latest creation time swept, as unix time, when sweeping back from `now`
+    pub fn latest(&self, now: DateTime<Utc>) -> i64 {
+        match self.created_between {
+            Some(range) => range.latest,
+            None => now.timestamp() + self.future_secs as i64,
+        }
+    }
+
+    /// Check the settings besides the key, which [`KeyConfig::validate`]
+    /// checks
+    pub fn validate(&self) -> Result<(), ApgpkError> {
+        if self.future_secs > MAX_FUTURE_SECS {
+            return Err(invalid(format!(
+                "keys can be dated at most {} seconds in the future, not {}",
+                MAX_FUTURE_SECS, self.future_secs
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn new(key: KeyConfig, max_backshift_days: u16) -> Self {
+        Self {
+            key,
+            max_backshift_days,
+            scorer: None,
+            detectors: vec![],
+            shard: None,
+            batch_results: false,
+            time_slice: None,
+            created_between: None,
+            future_secs: 0,
+            coverage: None,
+        }
+    }
+}
+
+/// Longest user ID in bytes, far above any name and e-mail address
+pub const MAX_UID_LEN: usize = 1024;
+
+/// Check a user ID is text OpenPGP implementations write and show alike:
+/// any UTF-8, names in every script included, but no control characters or
+/// bidirectional overrides, which can make it display as another one.
+pub fn validate_uid(uid: &str) -> Result<(), ApgpkError> {
+    if uid.trim().is_empty() {
+        return Err(invalid("uid must not be empty"));
+    }
+    if uid.len() > MAX_UID_LEN {
+        return Err(invalid(format!(
+            "uid is {} bytes long, the limit is {}",
+            uid.len(),
+            MAX_UID_LEN
+        )));
+    }
+    let bidi = |c: char| matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}');
+    if let Some(c) = uid.chars().find(|c| c.is_control() || bidi(*c)) {
+        return Err(invalid(format!(
+            "uid must not contain control characters such as line breaks, found {:?}",
+            c
+        )));
+    }
+    Ok(())
+}
+
+/// Check the backshift window doesn't reach before the unix epoch, which
+/// can't be represented in the key creation time.
+pub fn validate_backshift(max_backshift_days: u16) -> Result<(), ApgpkError> {
+    let earliest = Utc::now() - chrono::Duration::days(max_backshift_days as i64);
+    if earliest.timestamp() < 0 {
+        return Err(invalid(format!(
+            "max backshift of {} days reaches before 1970-01-01",
+            max_backshift_days
+        )));
+    }
+    Ok(())
+}
+
+/// (can sign, can encrypt) of a primary key algorithm
+fn capabilities(key_type: &KeyType) -> (bool, bool) {
+    match key_type {
+        KeyType::Rsa(_) => (true, true),
+        KeyType::ECDH => (false, true),
+        KeyType::EdDSA | KeyType::ECDSA(_) => (true, false),
+    }
+}
+
+/// Parse a key expiration like GnuPG does into days, `0` or `never` for a key
+/// which doesn't expire: `<n>` days, `<n>w` weeks, `<n>m` months or `<n>y`
+/// years, e.g. `2y`.
+pub fn parse_expiration(s: &str) -> Result<u16, ApgpkError> {
+    let s = s.trim().to_lowercase();
+    if s == "never" {
+        return Ok(0);
+    }
+    let (n, days) = match s.char_indices().last() {
+        Some((i, 'd')) => (&s[..i], 1),
+        Some((i, 'w')) => (&s[..i], 7),
+        Some((i, 'm')) => (&s[..i], 30),
+        Some((i, 'y')) => (&s[..i], 365),
+        _ => (s.as_str(), 1),
+    };
+    n.parse::<u16>()
+        .ok()
+        .and_then(|n| n.checked_mul(days))
+        .filter(|&days| days <= MAX_EXPIRATION_DAYS)
+        .ok_or_else(|| {
+            invalid(format!(
+                "Invalid expiration `{}`, expect e.g. 90, 12w, 6m or 2y, at most {} days",
+                s, MAX_EXPIRATION_DAYS
+            ))
+        })
+}
+
+/// Longest key expiration in days, as the self signatures count it in
+/// seconds on 32 bits
+pub const MAX_EXPIRATION_DAYS: u16 = (u32::MAX / (24 * 60 * 60)) as u16;
+
+/// Key algorithms known to OpenPGP which pgp 0.10 can't generate, as
+/// (accepted names, name shown)
+const UNSUPPORTED_KEY_TYPES: &[(&[&str], &str)] = &[
+    (&["p521", "nistp521"], "ECDSA NIST P-521"),
+    (&["secp256k1", "k256"], "secp256k1"),
+];
+
+/// Parse a key algorithm as written in config files, e.g. `eddsa`, `ecdh`,
+/// `p256` or `rsa4096`.
+pub fn parse_key_type(s: &str) -> Result<KeyType, ApgpkError> {
+    let s = s.trim().to_lowercase();
+    if let Some((_, name)) = UNSUPPORTED_KEY_TYPES
+        .iter()
+        .find(|(names, _)| names.contains(&s.as_str()))
+    {
+        return Err(invalid(format!(
+            "{} keys aren't supported by the OpenPGP backend yet, use eddsa, p256, p384 or rsa<bits>",
+            name
+        )));
+    }
+    match s.as_str() {
+        "eddsa" | "ed25519" => Ok(KeyType::EdDSA),
+        "ecdh" | "cv25519" => Ok(KeyType::ECDH),
+        "p256" | "nistp256" => Ok(KeyType::ECDSA(ECCCurve::P256)),
+        "p384" | "nistp384" => Ok(KeyType::ECDSA(ECCCurve::P384)),
+        _ => s
+            .strip_prefix("rsa")
+            .and_then(|bits| bits.parse().ok())
+            .map(KeyType::Rsa)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Unknown key type `{}`, expect one of eddsa, ecdh, p256, p384, rsa<bits>",
+                    s
+                ))
+            }),
+    }
+}
+
+/// Inverse of [`parse_key_type`]
+pub fn key_type_id(key_type: &KeyType) -> String {
+    match key_type {
+        KeyType::Rsa(bits) => format!("rsa{}", bits),
+        KeyType::ECDH => "ecdh".to_string(),
+        KeyType::EdDSA => "eddsa".to_string(),
+        KeyType::ECDSA(ECCCurve::P256) => "p256".to_string(),
+        KeyType::ECDSA(ECCCurve::P384) => "p384".to_string(),
+        KeyType::ECDSA(curve) => curve.alias().unwrap_or("ecdsa").to_string(),
+    }
+}
+
+/// Key type of a key with these public parameters, if one
+/// [`parse_key_type`] knows
+pub fn key_type_of(params: &PublicParams) -> Option<KeyType> {
+    match params {
+        PublicParams::RSA { n, .. } => Some(KeyType::Rsa(n.as_bytes().len() as u32 * 8)),
+        PublicParams::EdDSA { .. } => Some(KeyType::EdDSA),
+        PublicParams::ECDH { .. } => Some(KeyType::ECDH),
+        PublicParams::ECDSA(EcdsaPublicParams::P256 { .. }) => Some(KeyType::ECDSA(ECCCurve::P256)),
+        PublicParams::ECDSA(EcdsaPublicParams::P384 { .. }) => Some(KeyType::ECDSA(ECCCurve::P384)),
+        _ => None,
+    }
+}
+
+/// Key type searched by a share of the threads proportional to `weight`,
+/// written `TYPE[:WEIGHT]`, e.g. `rsa3072:2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedKeyType {
+    pub key_type: KeyType,
+    pub weight: u32,
+}
+
+impl FromStr for WeightedKeyType {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key_type, weight) = match s.split_once(':') {
+            Some((key_type, weight)) => {
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&w| w > 0)
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "Invalid weight `{}` of key type `{}`, expect a positive integer",
+                            weight, key_type
+                        ))
+                    })?;
+                (key_type, weight)
+            }
+            None => (s, 1),
+        };
+        Ok(WeightedKeyType {
+            key_type: parse_key_type(key_type)?,
+            weight,
+        })
+    }
+}
+
+/// Key type of every one of `threads` threads, each type getting a share
+/// proportional to its weight and at least one thread
+pub fn allocate_threads(
+    types: &[WeightedKeyType],
+    threads: usize,
+) -> Result<Vec<KeyType>, ApgpkError> {
+    if threads < types.len() {
+        return Err(invalid(format!(
+            "{} key types need at least as many threads, not {}",
+            types.len(),
+            threads
+        )));
+    }
+    let total: u64 = types.iter().map(|t| t.weight as u64).sum();
+    let spare = (threads - types.len()) as u64;
+    // one thread each, the spare ones by largest remainder
+    let mut counts: Vec<_> = types
+        .iter()
+        .map(|t| 1 + (spare * t.weight as u64 / total) as usize)
+        .collect();
+    let mut by_remainder: Vec<_> = (0..types.len()).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse(spare * types[i].weight as u64 % total));
+    let assigned: usize = counts.iter().sum();
+    for &i in by_remainder.iter().cycle().take(threads - assigned) {
+        counts[i] += 1;
+    }
+    Ok(types
+        .iter()
+        .zip(counts)
+        .flat_map(|(t, n)| std::iter::repeat_n(t.key_type, n))
+        .collect())
+}
+
+mod key_type_serde {
+    use pgp::composed::KeyType;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key_type: &KeyType, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&super::key_type_id(key_type))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<KeyType, D::Error> {
+        let s = String::deserialize(d)?;
+        super::parse_key_type(&s).map_err(D::Error::custom)
+    }
+}
+
+fn key_type_name(key_type: &KeyType) -> &'static str {
+    match key_type {
+        KeyType::Rsa(_) => "RSA",
+        KeyType::ECDH => "ECDH",
+        KeyType::EdDSA => "EdDSA",
+        KeyType::ECDSA(_) => "ECDSA",
+    }
+}
+
+pub(crate) fn invalid(msg: impl Into<String>) -> ApgpkError {
+    ApgpkError::InvalidConfig(msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(KeyConfig::default().validate().is_ok());
+
+        let encrypt = KeyConfig {
+            can_encrypt: true,
+            ..Default::default()
+        };
+        assert!(encrypt.validate().is_err());
+
+        let ecdh = KeyConfig {
+            key_type: KeyType::ECDH,
+            ..Default::default()
+        };
+        assert!(ecdh.validate().is_err());
+        let auth = KeyConfig {
+            can_certify: false,
+            can_sign: false,
+            can_authenticate: true,
+            ..Default::default()
+        };
+        assert!(auth.validate().is_ok());
+        assert!(KeyConfig {
+            can_authenticate: false,
+            ..auth
+        }
+        .validate()
+        .is_err());
+
+        assert!(KeyConfig::new(" ".to_string()).validate().is_err());
+        let twice = KeyConfig {
+            user_ids: vec!["work".to_string(), "work".to_string()],
+            ..Default::default()
+        };
+        assert!(twice.validate().is_err());
+        for uid in ["Jürgen Müller <j@example.org>", "张伟", "Ελένη 🦀", "محمد"] {
+            assert!(validate_uid(uid).is_ok(), "{}", uid);
+        }
+        for uid in [
+            "a\nb",
+            "a\0b",
+            "a\u{7}b",
+            "evil\u{202E}gro.elpmaxe",
+            &"x".repeat(1025),
+        ] {
+            assert!(validate_uid(uid).is_err(), "{:?}", uid);
+        }
+
+        let subkey = KeyConfig {
+            match_key: MatchKey::Subkey,
+            ..Default::default()
+        };
+        assert!(subkey.validate().is_err());
+        assert!(KeyConfig {
+            encryption_subkey: true,
+            ..subkey
+        }
+        .validate()
+        .is_ok());
+        let v6 = KeyConfig {
+            version: "6".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(v6.version.fingerprint_len(), 32);
+        assert!(v6.validate().is_err());
+        assert!("v5".parse::<KeyVersion>().is_err());
+        assert!(validate_backshift(30).is_ok());
+        assert!(validate_backshift(u16::MAX).is_err());
+    }
+
+    #[test]
+    fn test_time_slice() {
+        let slice = |index| TimeSlice {
+            start: 0,
+            index,
+            count: 3,
+        };
+        assert_eq!(slice(0).backshifts(10), 0..3);
+        assert_eq!(slice(1).backshifts(10), 3..6);
+        assert_eq!(slice(2).backshifts(10), 6..10);
+    }
+
+    #[test]
+    fn test_allocate_threads() {
+        let types: Vec<WeightedKeyType> = ["eddsa:3", "rsa3072"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(types[1].weight, 1);
+        let threads = allocate_threads(&types, 8).unwrap();
+        assert_eq!(threads.len(), 8);
+        let eddsa = threads.iter().filter(|t| **t == KeyType::EdDSA).count();
+        assert_eq!(eddsa, 6);
+        assert_eq!(threads[7], KeyType::Rsa(3072));
+        // every type gets a thread
+        let threads = allocate_threads(&types, 2).unwrap();
+        assert_eq!(threads, [KeyType::EdDSA, KeyType::Rsa(3072)]);
+        assert!(allocate_threads(&types, 1).is_err());
+        assert!("eddsa:0".parse::<WeightedKeyType>().is_err());
+        assert!("p521:2".parse::<WeightedKeyType>().is_err());
+    }
+
+    #[test]
+    fn test_creation_range() {
+        let range: CreationRange = "2021-01-01..2021-12-31".parse().unwrap();
+        assert_eq!(range.earliest, 1_609_459_200);
+        assert_eq!(range.len(), 365 * 24 * 60 * 60);
+        assert!(range.validate(Utc::now()).is_ok());
+        let day: CreationRange = "2021-03-01..2021-03-01".parse().unwrap();
+        assert_eq!(day.len(), 24 * 60 * 60);
+        assert!("2021-12-31..2021-01-01".parse::<CreationRange>().is_err());
+        assert!("1969-12-31..1970-01-31".parse::<CreationRange>().is_err());
+        assert!("2021-01-01".parse::<CreationRange>().is_err());
+        assert!("2021-13-01..2021-12-31".parse::<CreationRange>().is_err());
+        let future: CreationRange = "2021-01-01..2099-12-31".parse().unwrap();
+        assert!(future.validate(Utc::now()).is_err());
+
+        let mut search = SearchConfig::new(KeyConfig::default(), 1);
+        assert_eq!(search.window(), 24 * 60 * 60);
+        search.future_secs = 60;
+        assert_eq!(search.window(), 24 * 60 * 60 + 60);
+        let now = Utc::now();
+        assert_eq!(search.latest(now), now.timestamp() + 60);
+        assert!(search.validate().is_ok());
+        search.created_between = Some(range);
+        assert_eq!(search.window(), range.len());
+        assert_eq!(search.latest(now), range.latest);
+        search.future_secs = MAX_FUTURE_SECS + 1;
+        assert!(search.validate().is_err());
+    }
+
+    #[test]
+    fn test_timestamps_per_key() {
+        assert_eq!(
+            KeyConfig::default().timestamps_per_key(),
+            ECC_TIMESTAMPS_PER_KEY
+        );
+        let rsa = KeyConfig {
+            key_type: parse_key_type("rsa3072").unwrap(),
+            ..Default::default()
+        };
+        assert!(rsa.validate().is_ok());
+        assert_eq!(rsa.timestamps_per_key(), RSA_TIMESTAMPS_PER_KEY);
+        let either = KeyConfig {
+            encryption_subkey: true,
+            match_key: MatchKey::Either,
+            ..rsa
+        };
+        assert_eq!(either.timestamps_per_key(), 1);
+        let fixed = KeyConfig {
+            timestamps_per_key: Some(1000),
+            ..Default::default()
+        };
+        assert!(fixed.validate().is_ok());
+        assert_eq!(fixed.timestamps_per_key(), 1000);
+        assert!(KeyConfig {
+            match_keygrip: true,
+            ..fixed.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(KeyConfig {
+            timestamps_per_key: Some(0),
+            ..fixed
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut search = SearchConfig::new(
+            KeyConfig {
+                key_type: KeyType::Rsa(4096),
+                ..Default::default()
+            },
+            30,
+        );
+        search.detectors = vec![Detector::Runs];
+        let json = serde_json::to_string(&search).unwrap();
+        assert!(json.contains(r#""key_type":"rsa4096""#));
+        let back: SearchConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.key, search.key);
+        assert_eq!(back.detectors, search.detectors);
+
+        let key: KeyConfig = serde_json::from_str(r#"{"uid":"me"}"#).unwrap();
+        assert_eq!(key, KeyConfig::new("me".to_string()));
+        assert!(parse_key_type("dsa").is_err());
+        assert_eq!(parse_expiration("2y").unwrap(), 730);
+        assert_eq!(parse_expiration("12W").unwrap(), 84);
+        assert_eq!(parse_expiration("never").unwrap(), 0);
+        assert!(parse_expiration("1000y").is_err());
+        assert_eq!(parse_expiration("136y").unwrap(), 49640);
+        assert!(parse_expiration("137y").is_err());
+        assert!(parse_expiration("2x").is_err());
+        for (name, curve) in [("P256", ECCCurve::P256), ("nistp384", ECCCurve::P384)] {
+            let key_type = parse_key_type(name).unwrap();
+            assert_eq!(key_type, KeyType::ECDSA(curve));
+            assert_eq!(parse_key_type(&key_type_id(&key_type)).unwrap(), key_type);
+        }
+        let p521 = parse_key_type("nistp521").unwrap_err().to_string();
+        assert!(p521.contains("aren't supported"), "{}", p521);
+        let k256 = parse_key_type("secp256k1").unwrap_err().to_string();
+        assert!(k256.contains("secp256k1 keys"), "{}", k256);
+        assert!(serde_json::from_str::<KeyConfig>(r#"{"version":6}"#).is_ok());
+        assert!(serde_json::from_str::<KeyConfig>(r#"{"version":5}"#).is_err());
+    }
+}
@@ -1,74 +1,1069 @@
-use crate::error::ApgpkError;
+use crate::{
+    clock::Clock,
+    config::{CreationRange, SearchConfig},
+    detect::Detector,
+    error::ApgpkError,
+    keygen::{KeyGenerator, PgpKeyGenerator, SweepKeyGenerator},
+    stop::{StopReason, StopSignal},
+    unsigned::UnsignedKey,
+    utils,
+    watchdog::Heartbeat,
+};
+use aho_corasick::AhoCorasick;
 use chrono::prelude::*;
 use hex::ToHex;
-use pgp::{
-    composed::{
-        key::{SecretKey, SecretKeyParamsBuilder},
-        KeyType,
-    },
-    types::KeyTrait,
-};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicU64, Ordering},
         mpsc::Sender,
-        Arc,
+        Arc, PoisonError, RwLock,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-pub fn task(
-    uid: String,
-    max_backshift_days: u16,
-    pars: &[String],
-    exit_signal: &Arc<AtomicBool>,
+/// Most keys a worker tries before reporting them in a [`Msg::Speed`]
+pub const REPORT_KEYS: u64 = 1 << 24;
+
+/// Longest a worker keeps the count of its tried keys before reporting it in
+/// a [`Msg::Speed`]
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads of the clock per [`REPORT_INTERVAL`] once the speed is known
+const CLOCK_READS: f64 = 8.0;
+
+/// Keys tried by a worker since its last [`Msg::Speed`]. They are counted
+/// locally and reported every [`REPORT_KEYS`] keys or [`REPORT_INTERVAL`],
+/// so the channel sees a few messages per second whatever the thread count.
+#[derive(Debug)]
+struct Tally {
+    since: Instant,
+    tried: u64,
+    // keys between two reads of the clock
+    stride: u64,
+    next_read: u64,
+    measured: bool,
+}
+
+impl Tally {
+    fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            tried: 0,
+            stride: 1,
+            next_read: 1,
+            measured: false,
+        }
+    }
+
+    /// Count a tried key, true if it's time to report
+    fn add(&mut self) -> bool {
+        self.tried += 1;
+        if self.tried < self.next_read {
+            return false;
+        }
+        if self.tried >= REPORT_KEYS || self.since.elapsed() >= REPORT_INTERVAL {
+            return true;
+        }
+        // until the first report tells the speed, read the clock ever less
+        // often
+        if !self.measured {
+            self.stride = (self.stride * 2).min(REPORT_KEYS);
+        }
+        self.next_read = self.tried + self.stride;
+        false
+    }
+
+    /// Report of the keys tried since the last one, starting over
+    fn report<K>(&mut self) -> Msg<K> {
+        let secs = self.since.elapsed().as_secs_f64().max(f64::EPSILON);
+        let speed = self.tried as f64 / secs;
+        let msg = Msg::Speed(self.tried, speed);
+        self.stride =
+            ((speed * REPORT_INTERVAL.as_secs_f64() / CLOCK_READS) as u64).clamp(1, REPORT_KEYS);
+        self.measured = true;
+        self.next_read = self.stride;
+        self.tried = 0;
+        self.since = Instant::now();
+        msg
+    }
+}
+
+/// Search OpenPGP keys with [`search`], re-dating every keypair with
+/// [`SweepKeyGenerator`] or, when that isn't possible, generating one for
+/// every creation time
+pub fn task<M: Matcher>(
+    config: &SearchConfig,
+    matcher: M,
+    exit_signal: &StopSignal,
     msg_tx: &Sender<Msg>,
+    heartbeat: &Heartbeat,
+    clock: &dyn Clock,
 ) -> Result<(), ApgpkError> {
-    let t = Utc::now();
-    let mut speed_cal_begin = Instant::now();
-    let speed_cal_block = 60 * 60 * 12;
-    let max_backshift = max_backshift_days as i64 * 24 * 60 * 60;
-
-    let mut pgp_builder = SecretKeyParamsBuilder::default();
-    pgp_builder
-        .key_type(KeyType::EdDSA)
-        .can_create_certificates(true)
-        .can_sign(true)
-        .primary_user_id(uid)
-        .created_at(t);
-
-    for backshift in 0..max_backshift {
-        pgp_builder.created_at(t - chrono::Duration::seconds(backshift));
-        let k = pgp_builder.build().unwrap().generate().unwrap(); // can't fail
-        let k_fp = k.fingerprint().encode_hex_upper::<String>();
-        for par in pars {
-            if k_fp.ends_with(par) {
-                msg_tx.send(Msg::Key(Box::new(k.clone())))?;
+    if config.key.timestamps_per_key() > 1 {
+        search::<SweepKeyGenerator, M>(config, matcher, exit_signal, msg_tx, heartbeat, clock)
+    } else {
+        search::<PgpKeyGenerator, M>(config, matcher, exit_signal, msg_tx, heartbeat, clock)
+    }
+}
+
+/// Search keys of `G` accepted by `matcher`, sweeping the creation time back
+/// through the window set by `config`. [`PatternSet::matcher`] picks up
+/// patterns replaced while running.
+///
+/// `config.key` is expected to have passed
+/// [`crate::config::KeyConfig::validate`].
+pub fn search<G: KeyGenerator, M: Matcher>(
+    config: &SearchConfig,
+    matcher: M,
+    exit_signal: &StopSignal,
+    msg_tx: &Sender<Msg<G::Key>>,
+    heartbeat: &Heartbeat,
+    clock: &dyn Clock,
+) -> Result<(), ApgpkError> {
+    let generator = G::new(&config.key, start(config, clock));
+    search_with(
+        config,
+        generator,
+        matcher,
+        exit_signal,
+        msg_tx,
+        heartbeat,
+        clock,
+    )
+}
+
+/// [`search`] with an existing generator, e.g. a [`RetimedKeyGenerator`]
+/// shared by all workers
+///
+/// [`RetimedKeyGenerator`]: crate::keygen::RetimedKeyGenerator
+pub fn search_with<G: KeyGenerator, M: Matcher>(
+    config: &SearchConfig,
+    mut generator: G,
+    mut matcher: M,
+    exit_signal: &StopSignal,
+    msg_tx: &Sender<Msg<G::Key>>,
+    heartbeat: &Heartbeat,
+    clock: &dyn Clock,
+) -> Result<(), ApgpkError> {
+    // true if the receiver is gone, which stops the whole pool
+    let send = |msg| {
+        let gone = msg_tx.send(msg).is_err();
+        if gone && exit_signal.stop(StopReason::ReceiverGone) {
+            log::error!("Receiver of the found keys is gone, stopping all workers");
+        }
+        gone
+    };
+    // keys waiting for the end of the block with `batch_results`
+    let mut pending = vec![];
+    let found = |pending: &mut Vec<_>, msg| {
+        if config.batch_results {
+            pending.push(msg);
+            false
+        } else {
+            send(msg)
+        }
+    };
+    let t = start(config, clock);
+    let mut tally = Tally::new();
+    let window = config.window();
+    let backshifts = match config.time_slice {
+        Some(slice) => slice.backshifts(window),
+        None => 0..window,
+    };
+    // backshifts already swept by this worker with the same keypair, in
+    // order
+    let coverage = config.coverage.as_deref().zip(generator.keypair_id());
+    let skips: Vec<_> = coverage
+        .map(|(c, id)| c.swept(id))
+        .unwrap_or_default()
+        .iter()
+        .rev()
+        .map(|swept| {
+            (t.timestamp() - swept.latest).max(backshifts.start)
+                ..(t.timestamp() - swept.earliest + 1).min(backshifts.end)
+        })
+        .filter(|skip| !skip.is_empty())
+        .collect();
+    // the backshifts around the skipped ones
+    let mut todo = vec![];
+    let mut from = backshifts.start;
+    for skip in &skips {
+        todo.push(from..skip.start);
+        from = skip.end;
+    }
+    todo.push(from..backshifts.end);
+    // what was tried so far, skipped creation times included
+    let first = backshifts.start;
+    let mut last = None;
+    let record = |last: Option<i64>| {
+        if let (Some((c, id)), Some(last)) = (coverage, last) {
+            let range = CreationRange {
+                earliest: t.timestamp() - last,
+                latest: t.timestamp() - first,
+            };
+            c.record(id, range);
+        }
+    };
+    heartbeat.beat();
+
+    for backshift in todo.into_iter().flatten() {
+        let created_at = t - chrono::Duration::seconds(backshift);
+        // the key is only built for a hit when the generator fingerprints
+        // candidates without it
+        let mut built = None;
+        let fps = match generator.fingerprints_at(created_at) {
+            Some(fps) => fps,
+            None => {
+                let k = generator.generate(created_at);
+                let fps = generator.fingerprints(&k);
+                built = Some(k);
+                fps
+            }
+        };
+        let mut key = || {
+            Box::new(
+                built
+                    .take()
+                    .unwrap_or_else(|| generator.generate(created_at)),
+            )
+        };
+        if let Some(scorer) = &config.scorer {
+            let best = fps
+                .iter()
+                .filter_map(|fp| scorer.keep(&fp.encode_hex_upper::<String>()))
+                .reduce(f64::max);
+            if let Some(score) = best {
+                if found(&mut pending, Msg::Scored(key(), score)) {
+                    break;
+                }
+            }
+        } else if let Some(m) = fps.iter().find_map(|fp| matcher.matches(fp)) {
+            if found(&mut pending, Msg::Key(key(), m.pattern)) {
+                break;
+            }
+        } else if let Some(d) = config
+            .detectors
+            .iter()
+            .find(|d| fps.iter().any(|fp| d.is_match(fp)))
+        {
+            if found(&mut pending, Msg::Detected(key(), *d)) {
+                break;
             }
         }
-        if exit_signal.load(Ordering::Relaxed) {
+        let report = tally.add();
+        last = Some(backshift);
+        if exit_signal.is_stopped() {
             break;
         }
-        if backshift % speed_cal_block == (speed_cal_block - 1) {
-            let interval = speed_cal_begin.elapsed().as_micros() as f64 / 1_000_000.;
-            msg_tx.send(Msg::Speed(speed_cal_block as f64 / interval))?;
-            speed_cal_begin = Instant::now();
+        matcher.refresh()?;
+        if report {
+            if pending.drain(..).any(&send) || send(tally.report()) {
+                break;
+            }
+            record(last);
+            heartbeat.beat();
         }
     }
+    // what was found and tried since the last report
+    if !pending.into_iter().any(&send) && (tally.tried == 0 || !send(tally.report())) {
+        record(last);
+    }
+    // the skipped creation times the sweep got to
+    let reached = last.map_or(backshifts.start, |last| last + 1);
+    let skipped: i64 = skips
+        .iter()
+        .filter(|skip| skip.start <= reached)
+        .map(|skip| skip.end - skip.start)
+        .sum();
+    if let Some((c, _)) = coverage.filter(|_| skipped > 0) {
+        log::debug!(
+            "Skipped {} creation times already swept with this keypair",
+            skipped
+        );
+        c.skip(skipped as u64);
+    }
 
     Ok(())
 }
 
+/// Time the backshift window starts at
+pub(crate) fn start(config: &SearchConfig, clock: &dyn Clock) -> DateTime<Utc> {
+    let start = match config.time_slice {
+        Some(slice) => slice.start,
+        None => config.latest(clock.now()),
+    };
+    Utc.timestamp_opt(start, 0)
+        .single()
+        .unwrap_or_else(|| clock.now())
+}
+
+/// Patterns of the shard `(index, count)`, all of them without sharding
+fn shard(pars: &[Pattern], shard: Option<(usize, usize)>) -> Vec<Pattern> {
+    let Some((index, count)) = shard else {
+        return pars.to_vec();
+    };
+    let mut included = 0;
+    pars.iter()
+        .filter(|p| {
+            if matches!(p, Pattern::Exclude(_)) {
+                return true;
+            }
+            included += 1;
+            (included - 1) % count == index
+        })
+        .cloned()
+        .collect()
+}
+
+/// Where a literal pattern is anchored in the fingerprint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    Prefix,
+    #[default]
+    Suffix,
+    /// Either the prefix or the suffix
+    Both,
+    /// Anywhere in the fingerprint
+    Anywhere,
+}
+
+impl FromStr for MatchMode {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "prefix" => Ok(MatchMode::Prefix),
+            "suffix" => Ok(MatchMode::Suffix),
+            "both" => Ok(MatchMode::Both),
+            "anywhere" | "contains" => Ok(MatchMode::Anywhere),
+            _ => Err(ApgpkError::Other(format!(
+                "Unknown match mode `{}`, expect one of prefix, suffix, both, anywhere",
+                s
+            ))),
+        }
+    }
+}
+
+/// Length of a v4 fingerprint in bytes
+pub const FP_LEN: usize = 20;
+/// Length of a v4 fingerprint in hex digits
+pub const FP_HEX_LEN: usize = FP_LEN * 2;
+/// Hex digits per group when GnuPG prints a fingerprint
+pub const BLOCK_LEN: usize = 4;
+
+/// Part of the fingerprint patterns are matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// The full 40-digit fingerprint
+    #[default]
+    Fingerprint,
+    /// The 16-digit long key ID
+    KeyId,
+    /// The 8-digit short key ID
+    ShortId,
+}
+
+impl Target {
+    /// Number of hex digits of the target
+    pub fn hex_len(&self) -> usize {
+        match self {
+            Target::Fingerprint => FP_HEX_LEN,
+            Target::KeyId => 16,
+            Target::ShortId => 8,
+        }
+    }
+
+    /// The bytes of the fingerprint covered by the target
+    #[inline]
+    pub fn slice<'a>(&self, fp: &'a [u8]) -> &'a [u8] {
+        &fp[fp.len() - self.hex_len() / 2..]
+    }
+}
+
+impl FromStr for Target {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fingerprint" | "fpr" => Ok(Target::Fingerprint),
+            "keyid" => Ok(Target::KeyId),
+            "shortid" => Ok(Target::ShortId),
+            _ => Err(ApgpkError::Other(format!(
+                "Unknown target `{}`, expect one of fingerprint, keyid, shortid",
+                s
+            ))),
+        }
+    }
+}
+
+/// Masked comparison of the fingerprint against a pattern at one offset
+#[derive(Debug, Clone)]
+struct Placement {
+    /// nibble offset of the pattern in the fingerprint
+    offset: usize,
+    /// byte range of the fingerprint covered by the pattern
+    start: usize,
+    end: usize,
+    mask: [u8; FP_LEN],
+    value: [u8; FP_LEN],
+}
+
+impl Placement {
+    fn new(nibbles: &[Option<u8>], offset: usize) -> Self {
+        let mut mask = [0u8; FP_LEN];
+        let mut value = [0u8; FP_LEN];
+        for (i, nibble) in nibbles.iter().enumerate() {
+            let pos = offset + i;
+            let shift = if pos.is_multiple_of(2) { 4 } else { 0 };
+            if let Some(n) = nibble {
+                mask[pos / 2] |= 0xF << shift;
+                value[pos / 2] |= n << shift;
+            }
+        }
+        Self {
+            offset,
+            start: offset / 2,
+            end: (offset + nibbles.len()).div_ceil(2),
+            mask,
+            value,
+        }
+    }
+
+    #[inline]
+    fn is_match(&self, fp: &[u8]) -> bool {
+        fp[self.start..self.end]
+            .iter()
+            .zip(&self.mask[self.start..self.end])
+            .zip(&self.value[self.start..self.end])
+            .all(|((f, m), v)| f & m == *v)
+    }
+
+    /// Number of nibbles of the fingerprint differing from the pattern
+    fn distance(&self, fp: &[u8]) -> usize {
+        fp[self.start..self.end]
+            .iter()
+            .zip(&self.mask[self.start..self.end])
+            .zip(&self.value[self.start..self.end])
+            .map(|((f, m), v)| {
+                let diff = (f & m) ^ v;
+                (diff >> 4 != 0) as usize + (diff & 0xF != 0) as usize
+            })
+            .sum()
+    }
+}
+
+/// Literal hex pattern, `?` matches any hex digit.
+///
+/// The pattern is compiled into a mask and value for every offset allowed by
+/// its [`MatchMode`] inside its [`Target`], so matching is a handful of byte
+/// compares.
+#[derive(Debug, Clone)]
+pub struct Literal {
+    text: String,
+    mode: MatchMode,
+    target: Target,
+    /// the pattern must start at a multiple of this nibble offset
+    step: usize,
+    placements: Vec<Placement>,
+}
+
+impl Literal {
+    /// A pattern containing characters other than hex digits and `?`, or
+    /// longer than the target, never matches.
+    pub fn new(text: &str, mode: MatchMode, target: Target) -> Self {
+        let text = text.to_uppercase();
+        let nibbles: Option<Vec<Option<u8>>> = text
+            .chars()
+            .map(|c| match c {
+                '?' => Some(None),
+                c => c.to_digit(16).map(|d| Some(d as u8)),
+            })
+            .collect();
+
+        let placements = match nibbles {
+            Some(nibbles) if nibbles.len() <= target.hex_len() => {
+                let first = FP_HEX_LEN - target.hex_len();
+                let last = FP_HEX_LEN - nibbles.len();
+                let offsets: Vec<usize> = match mode {
+                    MatchMode::Prefix => vec![first],
+                    MatchMode::Suffix => vec![last],
+                    MatchMode::Both if last == first => vec![first],
+                    MatchMode::Both => vec![first, last],
+                    MatchMode::Anywhere => (first..=last).collect(),
+                };
+                offsets
+                    .into_iter()
+                    .map(|o| Placement::new(&nibbles, o))
+                    .collect()
+            }
+            _ => vec![],
+        };
+
+        Self {
+            text,
+            mode,
+            target,
+            step: 1,
+            placements,
+        }
+    }
+
+    /// Only accept the pattern starting on one of the 4-digit blocks GnuPG
+    /// prints fingerprints in. Suffixes whose length isn't a multiple of 4
+    /// can never match then.
+    pub fn align_blocks(mut self) -> Self {
+        self.step = BLOCK_LEN;
+        self.placements.retain(|p| p.offset % BLOCK_LEN == 0);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn mode(&self) -> MatchMode {
+        self.mode
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Probability that a random fingerprint matches the pattern
+    pub fn probability(&self) -> f64 {
+        self.probability_within(0)
+    }
+
+    /// Probability that a random fingerprint is at most `max_distance`
+    /// digits off the pattern
+    pub fn probability_within(&self, max_distance: usize) -> f64 {
+        let fixed = self.text.chars().filter(|c| *c != '?').count();
+        // ways to pick the differing digits, each differing in 15 ways
+        let mut ways = 1.0;
+        let mut within = 0.0;
+        for k in 0..=max_distance.min(fixed) {
+            within += ways;
+            ways *= (fixed - k) as f64 / (k + 1) as f64 * 15.0;
+        }
+        let p = self.placements.len() as f64 * within * 16f64.powi(-(fixed as i32));
+        p.min(1.0)
+    }
+
+    /// Whether the pattern is plain hex, without any wildcard
+    fn is_exact(&self) -> bool {
+        !self.placements.is_empty() && !self.text.contains('?')
+    }
+
+    #[inline]
+    pub fn is_match(&self, fp: &[u8]) -> bool {
+        self.placements.iter().any(|p| p.is_match(fp))
+    }
+
+    /// Least number of digit substitutions making the fingerprint match,
+    /// `None` if the pattern can never match
+    pub fn distance(&self, fp: &[u8]) -> Option<usize> {
+        self.placements.iter().map(|p| p.distance(fp)).min()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Literal hex string anchored according to its [`MatchMode`]
+    Literal(Literal),
+    /// Regular expression matched against the target in hex
+    Regex(Regex, Target),
+    /// Fingerprints matching the inner pattern are rejected even if another
+    /// pattern matches them. [`Pattern::is_match`] tells whether the inner
+    /// pattern matches.
+    Exclude(Box<Pattern>),
+    /// Matches if all the patterns match the same fingerprint, e.g. the
+    /// prefix `AAAA` and the suffix `FFFF`
+    All(Vec<Pattern>),
+    /// Matches if any of the patterns matches
+    Any(Vec<Pattern>),
+    /// Matches if the pattern doesn't, see [`crate::patterns::expr`]
+    Not(Box<Pattern>),
+    /// Matches fingerprints at most this many digit substitutions off the
+    /// literal, see [`Literal::distance`]
+    Fuzzy(Literal, usize),
+}
+
+impl Pattern {
+    /// Literal pattern matched against the full fingerprint
+    pub fn literal(text: &str, mode: MatchMode) -> Self {
+        Pattern::Literal(Literal::new(text, mode, Target::Fingerprint))
+    }
+
+    pub fn is_match(&self, fp: &[u8]) -> bool {
+        match self {
+            Pattern::Literal(l) => l.is_match(fp),
+            Pattern::Regex(re, target) => {
+                re.is_match(&target.slice(fp).encode_hex_upper::<String>())
+            }
+            Pattern::Exclude(p) => p.is_match(fp),
+            Pattern::All(pars) => pars.iter().all(|p| p.is_match(fp)),
+            Pattern::Any(pars) => pars.iter().any(|p| p.is_match(fp)),
+            Pattern::Not(p) => !p.is_match(fp),
+            Pattern::Fuzzy(l, max) => l.distance(fp).is_some_and(|d| d <= *max),
+        }
+    }
+
+    /// Digit substitutions between a fingerprint and a [`Pattern::Fuzzy`],
+    /// `None` for the other patterns
+    pub fn distance(&self, fp: &[u8]) -> Option<usize> {
+        match self {
+            Pattern::Fuzzy(l, _) => l.distance(fp),
+            _ => None,
+        }
+    }
+
+    /// Which last bytes of a fingerprint the pattern can match, where
+    /// exclusions match nothing
+    fn last_bytes(&self) -> [bool; 256] {
+        match self {
+            Pattern::Literal(l) => {
+                let mut allowed = [false; 256];
+                for p in &l.placements {
+                    let (m, v) = (p.mask[FP_LEN - 1], p.value[FP_LEN - 1]);
+                    for (b, allowed) in allowed.iter_mut().enumerate() {
+                        *allowed |= p.end < FP_LEN || b as u8 & m == v;
+                    }
+                }
+                allowed
+            }
+            Pattern::Regex(..) | Pattern::Not(_) | Pattern::Fuzzy(..) => [true; 256],
+            Pattern::Exclude(_) => [false; 256],
+            Pattern::All(pars) => pars.iter().fold([true; 256], |mut acc, p| {
+                for (a, b) in acc.iter_mut().zip(p.last_bytes()) {
+                    *a &= b;
+                }
+                acc
+            }),
+            Pattern::Any(pars) => pars.iter().fold([false; 256], |mut acc, p| {
+                for (a, b) in acc.iter_mut().zip(p.last_bytes()) {
+                    *a |= b;
+                }
+                acc
+            }),
+        }
+    }
+
+    /// Masked compares every fingerprint the pattern matches passes one of,
+    /// for prefiltering on a GPU, where exclusions pass nothing. None if
+    /// the pattern can't be expressed that way, e.g. a regex.
+    pub fn masks(&self) -> Option<Vec<FpMask>> {
+        match self {
+            Pattern::Literal(l) => Some(
+                l.placements
+                    .iter()
+                    .map(|p| FpMask {
+                        mask: p.mask,
+                        value: p.value,
+                    })
+                    .collect(),
+            ),
+            Pattern::Regex(..) | Pattern::Not(_) | Pattern::Fuzzy(..) => None,
+            Pattern::Exclude(_) => Some(vec![]),
+            // passing any one of them is necessary, the fewest masks are the
+            // cheapest to check
+            Pattern::All(pars) => pars.iter().filter_map(Pattern::masks).min_by_key(Vec::len),
+            Pattern::Any(pars) => pars
+                .iter()
+                .map(Pattern::masks)
+                .collect::<Option<Vec<_>>>()
+                .map(|masks| masks.concat()),
+        }
+    }
+}
+
+/// Fingerprints `fp` with `fp & mask == value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FpMask {
+    pub mask: [u8; FP_LEN],
+    pub value: [u8; FP_LEN],
+}
+
+impl FpMask {
+    pub fn is_match(&self, fp: &[u8]) -> bool {
+        fp.iter()
+            .zip(&self.mask)
+            .zip(&self.value)
+            .all(|((f, m), v)| f & m == *v)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            MatchMode::Prefix => write!(f, "{}*", self.text),
+            MatchMode::Suffix => write!(f, "*{}", self.text),
+            MatchMode::Both => write!(f, "{0}*|*{0}", self.text),
+            MatchMode::Anywhere => write!(f, "*{}*", self.text),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Literal(l) => write!(f, "{}", l),
+            Pattern::Regex(re, _) => write!(f, "re:{}", re.as_str()),
+            Pattern::Exclude(p) => write!(f, "!{}", p),
+            Pattern::All(pars) => {
+                let shown: Vec<_> = pars.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", shown.join(" & "))
+            }
+            Pattern::Any(pars) => {
+                let shown: Vec<_> = pars.iter().map(|p| p.to_string()).collect();
+                write!(f, "({})", shown.join(" | "))
+            }
+            Pattern::Not(p) if matches!(**p, Pattern::All(_)) => write!(f, "not ({})", p),
+            Pattern::Not(p) => write!(f, "not {}", p),
+            Pattern::Fuzzy(l, max) => write!(f, "{}~{}", l, max),
+        }
+    }
+}
+
+/// A pattern with the settings given to it in a TOML pattern file
+#[derive(Debug, Clone)]
+pub struct PatternSpec {
+    pub pattern: Pattern,
+    /// Name shown in the log and used as output directory instead of the
+    /// pattern itself
+    pub label: Option<String>,
+    /// Number of keys wanted, unlimited if `None`
+    pub quota: Option<usize>,
+    /// User ID of the found keys instead of the default one
+    pub uid: Option<String>,
+    /// Patterns are ordered by descending priority
+    pub priority: i32,
+}
+
+impl PatternSpec {
+    /// Spec with the default settings
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            label: None,
+            quota: None,
+            uid: None,
+            priority: 0,
+        }
+    }
+
+    /// Label of the pattern, or the pattern made fit for a directory name
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => utils::pattern_dir_name(&self.pattern),
+        }
+    }
+}
+
+/// What a [`Matcher`] found in a fingerprint
+#[derive(Debug, Clone)]
+pub struct MatchInfo {
+    /// Pattern sent along with the key in [`Msg::Key`]
+    pub pattern: Pattern,
+}
+
+/// Decides which candidate fingerprints the workers keep, letting downstream
+/// crates match with their own logic.
+pub trait Matcher {
+    /// What the fingerprint matched, if anything
+    fn matches(&self, fingerprint: &[u8]) -> Option<MatchInfo>;
+
+    /// Called after every candidate, e.g. to pick up replaced patterns
+    fn refresh(&mut self) -> Result<(), ApgpkError> {
+        Ok(())
+    }
+}
+
+/// Longest literal which fits the packed lookup keys of [`PatternMatcher`]
+const MAX_PACKED_LEN: usize = 32;
+
+/// Pack `len` nibbles of the fingerprint starting at nibble `offset`
+#[inline]
+fn pack(fp: &[u8], offset: usize, len: usize) -> u128 {
+    (offset..offset + len).fold(0, |acc, pos| {
+        let b = fp[pos / 2];
+        let n = if pos % 2 == 0 { b >> 4 } else { b & 0xF };
+        (acc << 4) | n as u128
+    })
+}
+
+/// Automaton of the literals matching anywhere and (pattern index, first
+/// allowed offset, offset step) of its patterns
+type Anywhere = (AhoCorasick, Vec<(usize, usize, usize)>);
+
+/// Compiled pattern set, built once per [`task`] call by [`PatternSetMatcher`].
+///
+/// Exact literals at fixed offsets are looked up in hash sets keyed by their
+/// (offset, length), exact literals matching anywhere go through a single
+/// Aho-Corasick automaton, and only wildcards, regexes and compound patterns
+/// are checked one by one. The cost per fingerprint hence doesn't grow with
+/// the number of plain patterns.
+///
+/// When all patterns constrain the last byte of the fingerprint (suffixes),
+/// a 256-entry table of the possible last bytes rejects most fingerprints
+/// before any of the above runs.
+#[derive(Debug)]
+pub struct PatternMatcher {
+    /// whether a fingerprint ending with the byte can match, `None` if any can
+    last_byte: Option<Box<[bool; 256]>>,
+    /// (nibble offset, length, packed value -> pattern index)
+    anchored: Vec<(usize, usize, HashMap<u128, usize>)>,
+    anywhere: Option<Anywhere>,
+    rest: Vec<(usize, Pattern)>,
+    /// fingerprints matching any of these are rejected
+    exclude: Vec<Pattern>,
+}
+
+impl PatternMatcher {
+    pub fn new(pars: &[Pattern]) -> Result<Self, ApgpkError> {
+        let mut anchored: Vec<(usize, usize, HashMap<u128, usize>)> = vec![];
+        let mut anywhere = (vec![], vec![]);
+        let mut rest = vec![];
+        let mut exclude = vec![];
+
+        for (i, par) in pars.iter().enumerate() {
+            match par {
+                Pattern::Exclude(p) => exclude.push(p.as_ref().clone()),
+                Pattern::Literal(l) if l.is_exact() && l.mode() == MatchMode::Anywhere => {
+                    anywhere.0.push(l.text().to_string());
+                    anywhere
+                        .1
+                        .push((i, FP_HEX_LEN - l.target().hex_len(), l.step));
+                }
+                Pattern::Literal(l) if l.is_exact() && l.text().len() <= MAX_PACKED_LEN => {
+                    let len = l.text().len();
+                    let value = u128::from_str_radix(l.text(), 16).unwrap(); // exact literal is hex
+                    for p in &l.placements {
+                        let group = match anchored
+                            .iter_mut()
+                            .find(|(o, n, _)| *o == p.offset && *n == len)
+                        {
+                            Some(group) => group,
+                            None => {
+                                anchored.push((p.offset, len, HashMap::new()));
+                                anchored.last_mut().unwrap()
+                            }
+                        };
+                        group.2.entry(value).or_insert(i);
+                    }
+                }
+                _ => rest.push((i, par.clone())),
+            }
+        }
+
+        let mut last_byte = Box::new([false; 256]);
+        for par in pars {
+            for (a, b) in last_byte.iter_mut().zip(par.last_bytes()) {
+                *a |= b;
+            }
+        }
+        let last_byte = (!last_byte.iter().all(|b| *b)).then_some(last_byte);
+
+        let anywhere = if anywhere.0.is_empty() {
+            None
+        } else {
+            Some((AhoCorasick::new(&anywhere.0)?, anywhere.1))
+        };
+
+        Ok(Self {
+            last_byte,
+            anchored,
+            anywhere,
+            rest,
+            exclude,
+        })
+    }
+
+    /// Index of the first, i.e. highest priority, pattern matching the
+    /// fingerprint, if any and the fingerprint isn't excluded
+    pub fn find(&self, fp: &[u8]) -> Option<usize> {
+        let i = self.find_include(fp)?;
+        if self.exclude.iter().any(|p| p.is_match(fp)) {
+            return None;
+        }
+        Some(i)
+    }
+
+    fn find_include(&self, fp: &[u8]) -> Option<usize> {
+        if let Some(table) = &self.last_byte {
+            if !table[fp[FP_LEN - 1] as usize] {
+                return None;
+            }
+        }
+        // every kind of pattern is checked, the lowest index wins
+        let mut best = self
+            .anchored
+            .iter()
+            .filter_map(|(offset, len, values)| values.get(&pack(fp, *offset, *len)))
+            .min()
+            .copied();
+        if let Some((ac, ids)) = &self.anywhere {
+            let fp_hex = fp.encode_hex_upper::<String>();
+            let hit = ac
+                .find_overlapping_iter(&fp_hex)
+                .map(|m| (m.start(), ids[m.pattern().as_usize()]))
+                .filter(|(start, (_, first, step))| start >= first && start % step == 0)
+                .map(|(_, (i, _, _))| i)
+                .min();
+            best = best.into_iter().chain(hit).min();
+        }
+        // kept in index order, so only the ones before the best need a check
+        self.rest
+            .iter()
+            .take_while(|(i, _)| best.is_none_or(|b| *i < b))
+            .find(|(_, par)| par.is_match(fp))
+            .map(|(i, _)| *i)
+            .or(best)
+    }
+}
+
+/// Patterns shared by all workers which can be replaced while they run.
+///
+/// Workers poll [`PatternSet::generation`] through [`PatternSetMatcher`] and
+/// rebuild their [`PatternMatcher`] when it changes.
+#[derive(Debug, Default)]
+pub struct PatternSet {
+    generation: AtomicU64,
+    patterns: RwLock<Arc<Vec<Pattern>>>,
+}
+
+impl PatternSet {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            patterns: RwLock::new(Arc::new(patterns)),
+        }
+    }
+
+    /// Current generation and its patterns
+    pub fn load(&self) -> (u64, Arc<Vec<Pattern>>) {
+        let patterns = self.patterns.read().unwrap_or_else(PoisonError::into_inner);
+        (self.generation(), patterns.clone())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Publish a new pattern set to all workers
+    pub fn replace(&self, patterns: Vec<Pattern>) {
+        let mut current = self
+            .patterns
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *current = Arc::new(patterns);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// [`Matcher`] of the shard `(index, count)` of the current patterns,
+    /// following their replacements
+    pub fn matcher(
+        &self,
+        shard: Option<(usize, usize)>,
+    ) -> Result<PatternSetMatcher<'_>, ApgpkError> {
+        let (generation, all) = self.load();
+        let patterns = self::shard(&all, shard);
+        Ok(PatternSetMatcher {
+            matcher: PatternMatcher::new(&patterns)?,
+            set: self,
+            shard,
+            generation,
+            patterns,
+        })
+    }
+}
+
+/// [`Matcher`] of a [`PatternSet`], built by [`PatternSet::matcher`]
 #[derive(Debug)]
-pub enum Msg {
-    Key(Box<SecretKey>),
-    Speed(f64),
+pub struct PatternSetMatcher<'a> {
+    set: &'a PatternSet,
+    shard: Option<(usize, usize)>,
+    generation: u64,
+    patterns: Vec<Pattern>,
+    matcher: PatternMatcher,
+}
+
+impl Matcher for PatternSetMatcher<'_> {
+    fn matches(&self, fingerprint: &[u8]) -> Option<MatchInfo> {
+        let i = self.matcher.find(fingerprint)?;
+        Some(MatchInfo {
+            pattern: self.patterns[i].clone(),
+        })
+    }
+
+    fn refresh(&mut self) -> Result<(), ApgpkError> {
+        if self.set.generation() != self.generation {
+            *self = self.set.matcher(self.shard)?;
+        }
+        Ok(())
+    }
+}
+
+/// [`Matcher`] keeping every candidate, to generate keys without patterns
+#[derive(Debug, Clone)]
+pub struct AnyMatcher {
+    pattern: Pattern,
+}
+
+impl AnyMatcher {
+    /// The empty conjunction, which every fingerprint matches, sent along
+    /// with the keys
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+}
+
+impl Default for AnyMatcher {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::All(vec![]),
+        }
+    }
+}
+
+impl Matcher for AnyMatcher {
+    fn matches(&self, _fingerprint: &[u8]) -> Option<MatchInfo> {
+        Some(MatchInfo {
+            pattern: self.pattern.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Msg<K = UnsignedKey> {
+    /// Key and the pattern it matched
+    Key(Box<K>, Pattern),
+    /// Key whose score reached the threshold of the [`crate::score::Scorer`]
+    Scored(Box<K>, f64),
+    /// Key caught by one of the built-in detectors
+    Detected(Box<K>, Detector),
+    /// Keys a worker tried since its last report, and their keys per second
+    Speed(u64, f64),
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::{
+        clock::{FixedClock, SystemClock},
+        config::{KeyConfig, TimeSlice},
+        coverage::Coverage,
+        keygen::{MockKey, MockKeyGenerator},
+    };
+    use pgp::types::KeyTrait;
     use std::thread;
 
     #[test]
@@ -76,12 +1071,14 @@ mod tests {
         let (msg_tx, msg_rx) = std::sync::mpsc::channel();
         let tx = msg_tx.clone();
         let handler = thread::spawn(move || -> Result<(), ApgpkError> {
+            let pars = PatternSet::new(vec![Pattern::literal("FFFFFF", MatchMode::Suffix)]);
             task(
-                "test".to_string(),
-                1,
-                &["FFFFFF".to_string()],
-                &Arc::new(AtomicBool::new(false)),
+                &SearchConfig::new(KeyConfig::new("test".to_string()), 1),
+                pars.matcher(None)?,
+                &StopSignal::new(),
                 &tx,
+                &Heartbeat::default(),
+                &SystemClock,
             )
             .unwrap();
             Ok(())
@@ -89,10 +1086,10 @@ mod tests {
         drop(msg_tx);
         for msg in msg_rx {
             match msg {
-                Msg::Key(k) => {
+                Msg::Key(k, _) | Msg::Scored(k, _) | Msg::Detected(k, _) => {
                     println!("key: {}", k.fingerprint().encode_hex_upper::<String>());
                 }
-                Msg::Speed(speed) => {
+                Msg::Speed(_, speed) => {
                     println!("speed: {}", speed);
                 }
             }
@@ -100,6 +1097,569 @@ mod tests {
         handler.join().unwrap().unwrap();
     }
 
+    #[test]
+    fn test_search_mock() {
+        let config = SearchConfig::new(KeyConfig::default(), 1);
+        // about one key in 16
+        let pars = PatternSet::new(vec![Pattern::literal("A", MatchMode::Suffix)]);
+        let clock = FixedClock(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+        let run = |stop: &StopSignal| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let matcher = pars.matcher(None).unwrap();
+            search::<MockKeyGenerator, _>(
+                &config,
+                matcher,
+                stop,
+                &tx,
+                &Heartbeat::default(),
+                &clock,
+            )
+            .unwrap();
+            drop(tx);
+            rx.into_iter().collect::<Vec<_>>()
+        };
+
+        let msgs = run(&StopSignal::new());
+        let keys: Vec<_> = msgs
+            .iter()
+            .filter_map(|m| match m {
+                Msg::Key(k, _) => Some(k.fingerprint),
+                _ => None,
+            })
+            .collect();
+        // a full day of timestamps, and deterministic
+        assert!((4000..7000).contains(&keys.len()));
+        assert!(keys.iter().all(|fp| fp[FP_LEN - 1] & 0x0F == 0x0A));
+        // every key tried is reported, in a few batches
+        let tried: u64 = msgs
+            .iter()
+            .map(|m| match m {
+                Msg::Speed(tried, _) => *tried,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(tried, config.window() as u64);
+        let found = |msgs: &[Msg<_>]| msgs.iter().filter(|m| !matches!(m, Msg::Speed(..))).count();
+        assert_eq!(found(&run(&StopSignal::new())), found(&msgs));
+
+        // the same keys, sent in one go with their report
+        let batched = SearchConfig {
+            batch_results: true,
+            ..config.clone()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        search::<MockKeyGenerator, _>(
+            &batched,
+            pars.matcher(None).unwrap(),
+            &StopSignal::new(),
+            &tx,
+            &Heartbeat::default(),
+            &clock,
+        )
+        .unwrap();
+        drop(tx);
+        let batched: Vec<_> = rx
+            .into_iter()
+            .filter_map(|m| match m {
+                Msg::Key(k, _) => Some(k.fingerprint),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(batched, keys);
+
+        // two slices sweep disjoint halves of the window from the same start
+        let times: Vec<Vec<_>> = (0..2)
+            .map(|index| {
+                let sliced = SearchConfig {
+                    time_slice: Some(TimeSlice {
+                        start: clock.0.timestamp() + 60,
+                        index,
+                        count: 2,
+                    }),
+                    ..config.clone()
+                };
+                let (tx, rx) = std::sync::mpsc::channel();
+                search::<MockKeyGenerator, _>(
+                    &sliced,
+                    pars.matcher(None).unwrap(),
+                    &StopSignal::new(),
+                    &tx,
+                    &Heartbeat::default(),
+                    &clock,
+                )
+                .unwrap();
+                drop(tx);
+                rx.into_iter()
+                    .filter_map(|m| match m {
+                        Msg::Key(k, _) => Some(k.created_at),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let middle = clock.0 + chrono::Duration::seconds(60 - 12 * 60 * 60);
+        assert!(times[0].iter().all(|t| *t > middle));
+        assert!(times[1].iter().all(|t| *t <= middle));
+
+        // a creation range is swept instead of the window back from now
+        let range = CreationRange {
+            earliest: 1_609_459_200,
+            latest: 1_609_459_200 + 999,
+        };
+        let ranged = SearchConfig {
+            created_between: Some(range),
+            ..config.clone()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        search::<MockKeyGenerator, _>(
+            &ranged,
+            pars.matcher(None).unwrap(),
+            &StopSignal::new(),
+            &tx,
+            &Heartbeat::default(),
+            &clock,
+        )
+        .unwrap();
+        drop(tx);
+        let created: Vec<_> = rx
+            .into_iter()
+            .filter_map(|m| match m {
+                Msg::Key(k, _) => Some(k.created_at.timestamp()),
+                _ => None,
+            })
+            .collect();
+        assert!(!created.is_empty());
+        assert!(created
+            .iter()
+            .all(|t| (range.earliest..=range.latest).contains(t)));
+
+        // stopped before the first key, which doesn't match, so only that
+        // one key is reported as tried
+        let stopped = StopSignal::new();
+        stopped.stop(StopReason::Signal);
+        let msgs = run(&stopped);
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0], Msg::Speed(1, _)));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        let gone = StopSignal::new();
+        let matcher = pars.matcher(None).unwrap();
+        search::<MockKeyGenerator, _>(&config, matcher, &gone, &tx, &Heartbeat::default(), &clock)
+            .unwrap();
+        assert_eq!(gone.reason(), Some(StopReason::ReceiverGone));
+    }
+
+    /// Mock keys of a single keypair, the same for a creation time
+    struct MockKeypair;
+
+    impl KeyGenerator for MockKeypair {
+        type Key = MockKey;
+
+        fn new(_key: &KeyConfig, _created_at: DateTime<Utc>) -> Self {
+            Self
+        }
+
+        fn generate(&mut self, created_at: DateTime<Utc>) -> MockKey {
+            MockKeyGenerator::default().generate(created_at)
+        }
+
+        fn fingerprints(&self, key: &MockKey) -> Vec<Vec<u8>> {
+            vec![key.fingerprint.to_vec()]
+        }
+
+        fn keypair_id(&self) -> Option<u64> {
+            Some(1)
+        }
+    }
+
+    #[test]
+    fn test_coverage() {
+        let range = CreationRange {
+            earliest: 1_609_459_200,
+            latest: 1_609_459_200 + 999,
+        };
+        let coverage = Arc::new(Coverage::new());
+        let config = SearchConfig {
+            created_between: Some(range),
+            coverage: Some(coverage.clone()),
+            ..SearchConfig::new(KeyConfig::default(), 1)
+        };
+        let pars = PatternSet::new(vec![Pattern::literal("A", MatchMode::Suffix)]);
+        let clock = FixedClock(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+        // keys tried and creation times of the keys found
+        let run_with = |config: &SearchConfig, stop: &StopSignal| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let matcher = pars.matcher(None).unwrap();
+            search::<MockKeypair, _>(config, matcher, stop, &tx, &Heartbeat::default(), &clock)
+                .unwrap();
+            drop(tx);
+            let (mut tried, mut created) = (0, vec![]);
+            for msg in rx {
+                match msg {
+                    Msg::Speed(n, _) => tried += n,
+                    Msg::Key(k, _) => created.push(k.created_at.timestamp()),
+                    _ => {}
+                }
+            }
+            (tried, created)
+        };
+        let run = |stop: &StopSignal| run_with(&config, stop);
+
+        // stopped after the first key
+        let stopped = StopSignal::new();
+        stopped.stop(StopReason::Signal);
+        assert_eq!(run(&stopped).0, 1);
+        let first = CreationRange {
+            earliest: range.latest,
+            ..range
+        };
+        assert_eq!(coverage.swept(1), [first]);
+
+        // the restarted sweep goes on with the rest of the range
+        let (tried, created) = run(&StopSignal::new());
+        assert_eq!(tried, 999);
+        assert!(!created.is_empty() && !created.contains(&range.latest));
+        assert_eq!(coverage.skipped(), 1);
+        assert_eq!(coverage.swept(1), [range]);
+
+        assert_eq!(run(&StopSignal::new()), (0, vec![]));
+        assert_eq!(coverage.skipped(), 1001);
+
+        // fresh keypairs are new candidates
+        let (tx, rx) = std::sync::mpsc::channel();
+        search::<MockKeyGenerator, _>(
+            &config,
+            pars.matcher(None).unwrap(),
+            &StopSignal::new(),
+            &tx,
+            &Heartbeat::default(),
+            &clock,
+        )
+        .unwrap();
+        drop(tx);
+        let tried: u64 = rx
+            .into_iter()
+            .map(|m| match m {
+                Msg::Speed(n, _) => n,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(tried, 1000);
+        assert_eq!(coverage.skipped(), 1001);
+
+        // stopped before a range swept by a former worker, both are kept
+        let coverage = Arc::new(Coverage::new());
+        let config = SearchConfig {
+            coverage: Some(coverage.clone()),
+            ..config.clone()
+        };
+        let middle = CreationRange {
+            earliest: range.latest - 600,
+            latest: range.latest - 500,
+        };
+        coverage.record(1, middle);
+        assert_eq!(run_with(&config, &stopped).0, 1);
+        assert_eq!(coverage.swept(1), [middle, first]);
+        let (tried, created) = run_with(&config, &StopSignal::new());
+        assert_eq!(tried, 1000 - 1 - 101);
+        assert!(!created
+            .iter()
+            .any(|c| *c == range.latest || (middle.earliest..=middle.latest).contains(c)));
+        assert_eq!(coverage.skipped(), 102);
+        assert_eq!(coverage.swept(1), [range]);
+    }
+
+    #[test]
+    fn test_custom_matcher() {
+        /// Fingerprints whose bytes sum up to a multiple of 256
+        struct ZeroSum;
+
+        impl Matcher for ZeroSum {
+            fn matches(&self, fingerprint: &[u8]) -> Option<MatchInfo> {
+                let sum = fingerprint.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+                (sum == 0).then(|| MatchInfo {
+                    pattern: Pattern::Regex(Regex::new("zero sum").unwrap(), Target::Fingerprint),
+                })
+            }
+        }
+
+        let config = SearchConfig::new(KeyConfig::default(), 1);
+        let clock = FixedClock(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        search::<MockKeyGenerator, _>(
+            &config,
+            ZeroSum,
+            &StopSignal::new(),
+            &tx,
+            &Heartbeat::default(),
+            &clock,
+        )
+        .unwrap();
+        drop(tx);
+        let keys: Vec<_> = rx
+            .into_iter()
+            .filter_map(|m| match m {
+                Msg::Key(k, _) => Some(k.fingerprint),
+                _ => None,
+            })
+            .collect();
+        assert!(!keys.is_empty());
+        assert!(keys
+            .iter()
+            .all(|fp| fp.iter().fold(0u8, |a, b| a.wrapping_add(*b)) == 0));
+
+        let any = AnyMatcher::default();
+        assert!(any.matches(&[0; FP_LEN]).is_some());
+        assert!(any.pattern().is_match(&[0xAB; FP_LEN]));
+    }
+
+    #[test]
+    fn test_pattern_match() {
+        let fp = hex::decode("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF").unwrap();
+        let lit = |s, mode| Pattern::literal(s, mode).is_match(&fp);
+        assert!(lit("4BEEF", MatchMode::Suffix));
+        assert!(!lit("DEAD5", MatchMode::Suffix));
+        assert!(lit("DEAD5", MatchMode::Prefix));
+        assert!(lit("DEAD5", MatchMode::Both));
+        assert!(lit("4BEEF", MatchMode::Both));
+        assert!(lit("9B1E0F", MatchMode::Anywhere));
+        assert!(lit("A9B1E0", MatchMode::Anywhere));
+        assert!(!lit("9B1E0F", MatchMode::Both));
+        assert!(lit("b4beef", MatchMode::Suffix));
+        assert!(lit("B4??EF", MatchMode::Suffix));
+        assert!(lit("D?AD?C", MatchMode::Prefix));
+        assert!(!lit("4B??EE", MatchMode::Suffix));
+        assert!(!lit("HELLO", MatchMode::Anywhere));
+        assert!(
+            Pattern::Literal(Literal::new("E5F1", MatchMode::Prefix, Target::KeyId)).is_match(&fp)
+        );
+        assert!(
+            !Pattern::Literal(Literal::new("5F1D", MatchMode::Prefix, Target::KeyId)).is_match(&fp)
+        );
+        assert!(
+            Pattern::Literal(Literal::new("A2B4", MatchMode::Prefix, Target::ShortId))
+                .is_match(&fp)
+        );
+        assert!(
+            !Pattern::Literal(Literal::new("E5F1", MatchMode::Anywhere, Target::ShortId))
+                .is_match(&fp)
+        );
+
+        let re = |s| Regex::new(s).unwrap();
+        assert!(Pattern::Regex(re("^DEAD.*BEEF$"), Target::Fingerprint).is_match(&fp));
+        assert!(!Pattern::Regex(re("^BEEF"), Target::Fingerprint).is_match(&fp));
+        assert!(Pattern::Regex(re("^E5F1.*BEEF$"), Target::KeyId).is_match(&fp));
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let fp = hex::decode("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF").unwrap();
+        let l = |s, mode| Literal::new(s, mode, Target::Fingerprint);
+        assert_eq!(l("A2C4BEEE", MatchMode::Suffix).distance(&fp), Some(2));
+        assert_eq!(l("A2?4BEEE", MatchMode::Suffix).distance(&fp), Some(1));
+        assert_eq!(l("9B1E0000", MatchMode::Anywhere).distance(&fp), Some(3));
+        assert_eq!(l("HELLO", MatchMode::Suffix).distance(&fp), None);
+
+        let fuzzy = Pattern::Fuzzy(l("A2C4BEEE", MatchMode::Suffix), 2);
+        assert_eq!(fuzzy.to_string(), "*A2C4BEEE~2");
+        assert!(fuzzy.is_match(&fp));
+        assert_eq!(fuzzy.distance(&fp), Some(2));
+        assert!(!Pattern::Fuzzy(l("A2C4BEEE", MatchMode::Suffix), 1).is_match(&fp));
+        assert!(PatternMatcher::new(&[fuzzy]).unwrap().find(&fp).is_some());
+
+        // 1 + 2 * 15 of the 16^2 values of two digits
+        let p = l("AB", MatchMode::Suffix).probability_within(1);
+        assert!((p - 31.0 / 256.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_matcher() {
+        let pars = [
+            Pattern::literal("ABCDE", MatchMode::Suffix),
+            Pattern::literal("4BEEF", MatchMode::Suffix),
+            Pattern::literal("DEAD5", MatchMode::Both),
+            Pattern::literal("A9B1E0", MatchMode::Anywhere),
+            Pattern::literal("B4??EF", MatchMode::Suffix),
+            Pattern::Literal(Literal::new("9B1E0", MatchMode::Anywhere, Target::KeyId)),
+        ];
+        let matcher = PatternMatcher::new(&pars).unwrap();
+        let fp = |s| hex::decode(s).unwrap();
+
+        assert_eq!(
+            matcher.find(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            Some(1)
+        );
+        assert_eq!(
+            matcher.find(&fp("DEAD5C3A0B1E0F7D24C6A8B3E5F1D9C7A2B4BEEE")),
+            Some(2)
+        );
+        assert_eq!(
+            matcher.find(&fp("0EAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEE")),
+            Some(3)
+        );
+        assert_eq!(
+            matcher.find(&fp("0EAD5C3A0B1E0F7D24C6A8B3E5F1D9C7A2B4FFEF")),
+            Some(4)
+        );
+        assert_eq!(
+            matcher.find(&fp("0EAD5C3A0B1E0F7D24C6A8B3E5F1D9C7A2B4FFEE")),
+            None
+        );
+        assert_eq!(
+            matcher.find(&fp("0000000000000000000000009B1E000000000000")),
+            Some(5)
+        );
+        assert_eq!(
+            matcher.find(&fp("000000000000000000009B1E0000000000000000")),
+            None
+        );
+        assert!(matcher.last_byte.is_none());
+
+        let aligned = PatternMatcher::new(&[
+            Pattern::Literal(
+                Literal::new("9B1E0", MatchMode::Anywhere, Target::Fingerprint).align_blocks(),
+            ),
+            Pattern::Literal(
+                Literal::new("1E0F", MatchMode::Anywhere, Target::Fingerprint).align_blocks(),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            aligned.find(&fp("DEAD5C39B1E0AF7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            None
+        );
+        assert_eq!(
+            aligned.find(&fp("DEAD1E0F5C3A7D24C6A8B3E5F1D9C7A2B4BEEF00")),
+            Some(1)
+        );
+        assert_eq!(
+            aligned.find(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            Some(0)
+        );
+
+        let excluding = PatternMatcher::new(&[
+            Pattern::literal("4BEEF", MatchMode::Suffix),
+            Pattern::Exclude(Box::new(Pattern::literal("DEAD", MatchMode::Anywhere))),
+        ])
+        .unwrap();
+        assert_eq!(
+            excluding.find(&fp("0EAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            Some(0)
+        );
+        assert_eq!(
+            excluding.find(&fp("0EAD5C3A9B1E0F7D24C6A8B3E5F1DEAD72B4BEEF")),
+            None
+        );
+
+        let suffixes = PatternMatcher::new(&[
+            Pattern::literal("4BEEF", MatchMode::Suffix),
+            Pattern::literal("CAFE?", MatchMode::Suffix),
+        ])
+        .unwrap();
+        let table = suffixes.last_byte.as_ref().unwrap();
+        assert_eq!(table.iter().filter(|b| **b).count(), 16);
+        assert!(table[0xEF] && table[0xE0] && table[0xEA] && !table[0xFE]);
+        assert_eq!(
+            suffixes.find(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            Some(0)
+        );
+        assert_eq!(
+            suffixes.find(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2BCAFE1")),
+            Some(1)
+        );
+
+        let compound = PatternMatcher::new(&[Pattern::All(vec![
+            Pattern::literal("AAAA", MatchMode::Prefix),
+            Pattern::literal("FFFF", MatchMode::Suffix),
+        ])])
+        .unwrap();
+        assert_eq!(
+            compound
+                .last_byte
+                .as_ref()
+                .unwrap()
+                .iter()
+                .filter(|b| **b)
+                .count(),
+            1
+        );
+        assert_eq!(
+            compound.find(&fp("AAAA5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4FFFF")),
+            Some(0)
+        );
+        assert_eq!(
+            compound.find(&fp("AAAA5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")),
+            None
+        );
+        assert_eq!(
+            compound.find(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4FFFF")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_matcher_priority() {
+        let fp = hex::decode("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF").unwrap();
+        let anywhere_first = PatternMatcher::new(&[
+            Pattern::literal("A9B1E0", MatchMode::Anywhere),
+            Pattern::literal("4BEEF", MatchMode::Suffix),
+        ])
+        .unwrap();
+        assert_eq!(anywhere_first.find(&fp), Some(0));
+
+        let wildcard_first = PatternMatcher::new(&[
+            Pattern::literal("B4??EF", MatchMode::Suffix),
+            Pattern::literal("A9B1E0", MatchMode::Anywhere),
+            Pattern::literal("DEAD5", MatchMode::Prefix),
+        ])
+        .unwrap();
+        assert_eq!(wildcard_first.find(&fp), Some(0));
+
+        let anchored_first = PatternMatcher::new(&[
+            Pattern::literal("DEAD5", MatchMode::Prefix),
+            Pattern::literal("B4??EF", MatchMode::Suffix),
+            Pattern::literal("A9B1E0", MatchMode::Anywhere),
+        ])
+        .unwrap();
+        assert_eq!(anchored_first.find(&fp), Some(0));
+    }
+
+    #[test]
+    fn test_shard() {
+        let pars = [
+            Pattern::literal("AAAAA", MatchMode::Suffix),
+            Pattern::Exclude(Box::new(Pattern::literal("DEAD", MatchMode::Anywhere))),
+            Pattern::literal("BBBBB", MatchMode::Suffix),
+        ];
+        let shown = |s| -> Vec<String> { shard(&pars, s).iter().map(|p| p.to_string()).collect() };
+        assert_eq!(shown(None).len(), 3);
+        assert_eq!(shown(Some((0, 2))), ["*AAAAA", "!*DEAD*"]);
+        assert_eq!(shown(Some((1, 2))), ["!*DEAD*", "*BBBBB"]);
+    }
+
+    #[test]
+    fn test_pattern_set() {
+        let set = PatternSet::new(vec![Pattern::literal("ABCDE", MatchMode::Suffix)]);
+        let (generation, patterns) = set.load();
+        set.replace(vec![]);
+        assert_ne!(set.generation(), generation);
+        assert_eq!(patterns.len(), 1);
+        assert!(set.load().1.is_empty());
+
+        // a worker panicking while holding the lock doesn't take the set down
+        let set = Arc::new(set);
+        let poisoner = set.clone();
+        std::thread::spawn(move || {
+            let _guard = poisoner.patterns.write().unwrap();
+            panic!("worker bug");
+        })
+        .join()
+        .unwrap_err();
+        set.replace(vec![Pattern::literal("ABCDE", MatchMode::Suffix)]);
+        assert_eq!(set.load().1.len(), 1);
+    }
+
     #[test]
     fn test_test() {
         for i in (0..=2).map(|i| i * 10) {
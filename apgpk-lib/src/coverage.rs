@@ -0,0 +1,106 @@
+//! Creation times already swept by a worker, so that its replacement doesn't
+//! try them again.
+//!
+//! A restarted sweep re-anchors its window and goes over creation times the
+//! worker tried before. With fresh random keypairs those are new candidates,
+//! but with the same keypair, shared or derived from a seed, they are the
+//! very same keys. [`crate::core::search_with`] skips them when the
+//! generator has a [`crate::keygen::KeyGenerator::keypair_id`].
+
+use crate::config::CreationRange;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, PoisonError,
+};
+
+/// Creation times swept by one worker, kept across its restarts by
+/// [`crate::pool::WorkerPool`]
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// keypair id and the disjoint ranges swept with it, from the earliest
+    swept: Mutex<Option<(u64, Vec<CreationRange>)>>,
+    skipped: AtomicU64,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creation times already swept with the keypair `id`, as disjoint
+    /// ranges from the earliest
+    pub fn swept(&self, id: u64) -> Vec<CreationRange> {
+        let swept = self.swept.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*swept {
+            Some((i, ranges)) if *i == id => ranges.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// Record `range` as swept with the keypair `id`, merged with the ranges
+    /// already recorded with it. The ranges of another keypair are dropped.
+    pub fn record(&self, id: u64, range: CreationRange) {
+        let mut swept = self.swept.lock().unwrap_or_else(PoisonError::into_inner);
+        let ranges = match &mut *swept {
+            Some((i, ranges)) if *i == id => ranges,
+            _ => &mut swept.insert((id, vec![])).1,
+        };
+        let mut merged = range;
+        ranges.retain(|r| {
+            let touches = r.earliest <= merged.latest + 1 && merged.earliest <= r.latest + 1;
+            if touches {
+                merged.earliest = merged.earliest.min(r.earliest);
+                merged.latest = merged.latest.max(r.latest);
+            }
+            !touches
+        });
+        let at = ranges.partition_point(|r| r.latest < merged.earliest);
+        ranges.insert(at, merged);
+    }
+
+    /// Count `n` candidates skipped as already swept
+    pub fn skip(&self, n: u64) {
+        self.skipped.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Candidates skipped so far
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let range = |earliest, latest| CreationRange { earliest, latest };
+        let coverage = Coverage::new();
+        assert_eq!(coverage.swept(1), []);
+
+        coverage.record(1, range(100, 200));
+        coverage.record(1, range(201, 300));
+        assert_eq!(coverage.swept(1), [range(100, 300)]);
+        assert_eq!(coverage.swept(2), []);
+
+        // a gap, both ranges are kept
+        coverage.record(1, range(400, 500));
+        assert_eq!(coverage.swept(1), [range(100, 300), range(400, 500)]);
+        coverage.record(1, range(0, 50));
+        assert_eq!(
+            coverage.swept(1),
+            [range(0, 50), range(100, 300), range(400, 500)]
+        );
+        // filling the gaps merges them
+        coverage.record(1, range(51, 450));
+        assert_eq!(coverage.swept(1), [range(0, 500)]);
+        coverage.record(2, range(450, 460));
+        assert_eq!(coverage.swept(1), []);
+        assert_eq!(coverage.swept(2), [range(450, 460)]);
+
+        coverage.skip(3);
+        coverage.skip(4);
+        assert_eq!(coverage.skipped(), 7);
+    }
+}
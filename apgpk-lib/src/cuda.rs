@@ -0,0 +1,169 @@
+//! [`SweepDevice`] on CUDA, behind the `gpu-cuda` feature. The kernel of
+//! `sweep.cl` is compiled with NVRTC, a few defines mapping its OpenCL
+//! names to CUDA.
+
+use crate::{
+    core::FpMask,
+    error::ApgpkError,
+    fingerprint::TimestampSweep,
+    gpu::{mask_words, SweepDevice},
+};
+use cudarc::{
+    driver::{
+        result, sys::CUdevice_attribute, CudaDevice, CudaSlice, DriverError, LaunchAsync,
+        LaunchConfig,
+    },
+    nvrtc::compile_ptx,
+};
+use std::sync::Arc;
+
+const PRELUDE: &str = r#"
+typedef unsigned int uint;
+#define __kernel extern "C" __global__
+#define __global
+#define __constant __constant__
+#define get_global_id(dim) (blockIdx.x * blockDim.x + threadIdx.x)
+#define rotate(x, n) __funnelshift_l((x), (x), (n))
+#define atomic_inc(p) atomicAdd((uint *)(p), 1u)
+"#;
+const KERNEL: &str = include_str!("sweep.cl");
+
+/// Threads per block
+const BLOCK_SIZE: u32 = 256;
+
+/// Creation times per thread of the device in one call, enough to keep it
+/// busy for a few milliseconds
+const TIMES_PER_THREAD: u32 = 64;
+
+/// Most hits read back per call, more mean the masks are far too loose
+const MAX_HITS: u32 = 1 << 16;
+
+/// One CUDA device with the masks uploaded
+#[derive(Debug)]
+pub struct CudaSweepDevice {
+    device: Arc<CudaDevice>,
+    ordinal: usize,
+    chunk: u32,
+    masks: CudaSlice<u32>,
+    mask_count: u32,
+    hits: CudaSlice<u32>,
+    hit_count: CudaSlice<u32>,
+}
+
+impl CudaSweepDevice {
+    /// Device `ordinal` of [`devices`] filtering with `masks`
+    pub fn new(ordinal: usize, masks: &[FpMask]) -> Result<Self, ApgpkError> {
+        let device = CudaDevice::new(ordinal).map_err(error)?;
+        let ptx = compile_ptx(format!("{}{}", PRELUDE, KERNEL))
+            .map_err(|e| ApgpkError::Other(format!("CUDA kernel: {}", e)))?;
+        device.load_ptx(ptx, "apgpk", &["sweep"]).map_err(error)?;
+
+        // as many creation times as the device has threads in flight
+        let attribute = |attr| unsafe { result::device::get_attribute(*device.cu_device(), attr) };
+        let sms = attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)
+            .map_err(error)?;
+        let threads =
+            attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR)
+                .map_err(error)?;
+        let chunk = (sms.max(1) as u32)
+            .saturating_mul(threads.max(BLOCK_SIZE as i32) as u32)
+            .saturating_mul(TIMES_PER_THREAD);
+
+        let words = mask_words(masks);
+        Ok(Self {
+            masks: device.htod_copy(words).map_err(error)?,
+            mask_count: masks.len() as u32,
+            hits: device.alloc_zeros(MAX_HITS as usize).map_err(error)?,
+            hit_count: device.alloc_zeros(1).map_err(error)?,
+            device,
+            ordinal,
+            chunk,
+        })
+    }
+
+    fn run(
+        &mut self,
+        sweep: &TimestampSweep,
+        first: u32,
+        count: u32,
+    ) -> Result<Vec<u32>, DriverError> {
+        let words: Vec<u32> = sweep.blocks().iter().flatten().copied().collect();
+        let blocks = self.device.htod_copy(words)?;
+        self.device.memset_zeros(&mut self.hit_count)?;
+        let kernel = self
+            .device
+            .get_func("apgpk", "sweep")
+            .expect("loaded with the device");
+        let config = LaunchConfig {
+            grid_dim: (count.div_ceil(BLOCK_SIZE), 1, 1),
+            block_dim: (BLOCK_SIZE, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        // SAFETY: the arguments match the kernel, which writes `hits` and
+        // `hit_count` only, within `max_hits`
+        unsafe {
+            kernel.launch(
+                config,
+                (
+                    &blocks,
+                    sweep.blocks().len() as u32,
+                    first,
+                    count,
+                    &self.masks,
+                    self.mask_count,
+                    &mut self.hits,
+                    &mut self.hit_count,
+                    MAX_HITS,
+                ),
+            )
+        }?;
+
+        let hit_count = self.device.dtoh_sync_copy(&self.hit_count)?[0];
+        if hit_count > MAX_HITS {
+            log::warn!(
+                "{} creation times passed the masks of the GPU, only {} are checked",
+                hit_count,
+                MAX_HITS
+            );
+        }
+        if hit_count == 0 {
+            return Ok(vec![]);
+        }
+        let mut hits = self.device.dtoh_sync_copy(&self.hits)?;
+        hits.truncate(hit_count.min(MAX_HITS) as usize);
+        Ok(hits)
+    }
+}
+
+impl SweepDevice for CudaSweepDevice {
+    fn name(&self) -> String {
+        format!("CUDA device #{}", self.ordinal)
+    }
+
+    fn chunk(&self) -> u32 {
+        self.chunk
+    }
+
+    fn sweep(
+        &mut self,
+        sweep: &TimestampSweep,
+        first: u32,
+        count: u32,
+    ) -> Result<Vec<u32>, ApgpkError> {
+        self.run(sweep, first, count).map_err(error)
+    }
+}
+
+/// Names of the CUDA devices, in the order [`CudaSweepDevice::new`] numbers
+/// them
+pub fn devices() -> Result<Vec<String>, ApgpkError> {
+    result::init().map_err(error)?;
+    let count = result::device::get_count().map_err(error)?;
+    Ok((0..count.max(0))
+        .map(|ordinal| format!("CUDA device #{}", ordinal))
+        .collect())
+}
+
+fn error(e: DriverError) -> ApgpkError {
+    ApgpkError::Other(format!("CUDA: {}", e))
+}
@@ -0,0 +1,153 @@
+//! Detect aesthetically interesting fingerprints without explicit patterns.
+
+use crate::{core::FP_HEX_LEN, error::ApgpkError, patterns};
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr, sync::OnceLock};
+
+/// Min number of identical hex digits in a row for [`Detector::Repeats`]
+pub const MIN_REPEATS: usize = 8;
+/// Min length of an ascending or descending run for [`Detector::Runs`]
+pub const MIN_RUN: usize = 8;
+/// Min length of a palindromic suffix for [`Detector::Palindromes`]
+pub const MIN_PALINDROME: usize = 12;
+/// Min length of a dictionary word spelled by [`Detector::Words`]
+pub const MIN_WORD: usize = 6;
+
+/// English words with a hex look-alike, one per line
+const DICTIONARY: &str = include_str!("../data/words.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detector {
+    /// Identical digits anywhere, e.g. `77777777`
+    Repeats,
+    /// Ascending or descending digits anywhere, e.g. `3456789A` or `FEDCBA98`
+    Runs,
+    /// Fingerprint ending with a palindrome, e.g. `...C0FFEEFF0C`
+    Palindromes,
+    /// Fingerprint ending with an English word of the embedded dictionary,
+    /// e.g. `...C0FFEE` for coffee
+    Words,
+}
+
+impl Detector {
+    pub fn is_match(&self, fp: &[u8]) -> bool {
+        let n = nibbles(fp);
+        match self {
+            Detector::Repeats => longest_run_by(&n, |a, b| a == b) >= MIN_REPEATS,
+            Detector::Runs => {
+                longest_run_by(&n, |a, b| a + 1 == b) >= MIN_RUN
+                    || longest_run_by(&n, |a, b| a == b + 1) >= MIN_RUN
+            }
+            Detector::Palindromes => {
+                let suffix = &n[FP_HEX_LEN - MIN_PALINDROME..];
+                suffix.iter().eq(suffix.iter().rev())
+            }
+            Detector::Words => spelled_word(&fp.encode_hex_upper::<String>()).is_some(),
+        }
+    }
+}
+
+/// Hex look-alike -> word of the dictionary
+fn dictionary() -> &'static HashMap<String, &'static str> {
+    static WORDS: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        DICTIONARY
+            .lines()
+            .filter_map(|w| patterns::leet(w).map(|hex| (hex, w)))
+            .filter(|(hex, _)| hex.len() >= MIN_WORD)
+            .collect()
+    })
+}
+
+/// Longest dictionary word the upper case hex fingerprint ends with, see
+/// [`Detector::Words`]
+pub fn spelled_word(hex: &str) -> Option<&'static str> {
+    let words = dictionary();
+    let longest = words.keys().map(|w| w.len()).max().unwrap_or(0);
+    (MIN_WORD..=longest.min(hex.len()))
+        .rev()
+        .find_map(|len| words.get(&hex[hex.len() - len..]).copied())
+}
+
+impl FromStr for Detector {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "repeats" => Ok(Detector::Repeats),
+            "runs" => Ok(Detector::Runs),
+            "palindromes" => Ok(Detector::Palindromes),
+            "words" => Ok(Detector::Words),
+            _ => Err(ApgpkError::Other(format!(
+                "Unknown detector `{}`, expect one of repeats, runs, palindromes, words",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Detector::Repeats => "repeats",
+            Detector::Runs => "runs",
+            Detector::Palindromes => "palindromes",
+            Detector::Words => "words",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn nibbles(fp: &[u8]) -> [u8; FP_HEX_LEN] {
+    let mut n = [0; FP_HEX_LEN];
+    for (i, b) in fp.iter().take(FP_HEX_LEN / 2).enumerate() {
+        n[2 * i] = b >> 4;
+        n[2 * i + 1] = b & 0x0f;
+    }
+    n
+}
+
+/// Length of the longest run where every digit follows the previous by `next`
+fn longest_run_by(n: &[u8], next: impl Fn(u8, u8) -> bool) -> usize {
+    let mut longest = 1;
+    let mut run = 1;
+    for w in n.windows(2) {
+        run = if next(w[0], w[1]) { run + 1 } else { 1 };
+        longest = longest.max(run);
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let fp = |s: &str| hex::decode(s).unwrap();
+        let repeats = fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C777777777");
+        let runs = fp("DEAD5C3A9B1E0F7D2FEDCBA98E5F1D9C7A2B4BEE");
+        let palindrome = fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1C0FFEEEEFF0C");
+        assert!(Detector::Repeats.is_match(&repeats));
+        assert!(!Detector::Repeats.is_match(&runs));
+        assert!(Detector::Runs.is_match(&runs));
+        assert!(!Detector::Runs.is_match(&repeats));
+        assert!(Detector::Palindromes.is_match(&palindrome));
+        assert!(!Detector::Palindromes.is_match(&repeats));
+        assert_eq!("Runs".parse::<Detector>().unwrap(), Detector::Runs);
+
+        let words = fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2C0FFEE");
+        assert!(Detector::Words.is_match(&words));
+        assert!(!Detector::Words.is_match(&repeats));
+        assert_eq!(
+            spelled_word("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2C0FFEE"),
+            Some("coffee")
+        );
+        assert_eq!(
+            spelled_word("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7AC0FFEE5"),
+            Some("coffees")
+        );
+    }
+}
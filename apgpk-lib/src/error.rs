@@ -8,8 +8,14 @@ pub enum ApgpkError {
     PgpLibError(#[from] pgp::errors::Error),
     #[error("IO Error")]
     IoError(#[from] std::io::Error),
+    #[error("Regex Error")]
+    RegexError(#[from] regex::Error),
+    #[error("Aho-Corasick Error")]
+    AhoCorasickError(#[from] aho_corasick::BuildError),
     #[error("MPSC Error")]
     MpscError(#[from] std::sync::mpsc::SendError<Msg>),
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
     #[error("Other Error: {0}")]
     Other(String),
 }
@@ -0,0 +1,327 @@
+use crate::{
+    config::KeyConfig,
+    core::{Literal, MatchMode, Pattern},
+    error::ApgpkError,
+    keygen::{KeyGenerator, PgpKeyGenerator, RetimedKeyGenerator},
+    pool::{PoolConfig, Worker, WorkerPool},
+    stop::{StopReason, StopSignal},
+};
+use chrono::prelude::*;
+use std::{
+    fmt,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+/// Keys per second of one thread, used when no measured rate is available
+pub const DEFAULT_RATE_PER_THREAD: f64 = 20_000.0;
+
+/// Expected number of keys to generate until the pattern matches, `None` for
+/// patterns whose probability can't be computed (regex).
+pub fn expected_tries(par: &Pattern) -> Option<f64> {
+    probability(par).map(|p| 1.0 / p)
+}
+
+/// Probability that a random fingerprint matches the pattern, `None` if it
+/// can't be computed (regex).
+///
+/// The parts of compound patterns are taken as independent, which holds as
+/// long as they don't overlap.
+pub fn probability(par: &Pattern) -> Option<f64> {
+    match par {
+        Pattern::Literal(l) => Some(l.probability()),
+        Pattern::Regex(..) | Pattern::Exclude(_) => None,
+        Pattern::All(pars) => pars.iter().map(probability).product(),
+        Pattern::Any(pars) => pars
+            .iter()
+            .map(|p| probability(p).map(|p| 1.0 - p))
+            .product::<Option<f64>>()
+            .map(|none| 1.0 - none),
+        Pattern::Not(p) => probability(p).map(|p| 1.0 - p),
+        Pattern::Fuzzy(l, max) => Some(l.probability_within(*max)),
+    }
+}
+
+/// Number of keys after which any of the patterns has matched with a 50%
+/// chance, `None` if no probability can be computed.
+pub fn p50_tries(pars: &[Pattern]) -> Option<f64> {
+    let p: f64 = pars
+        .iter()
+        .filter_map(expected_tries)
+        .map(|tries| 1.0 / tries)
+        .sum();
+    (p > 0.0).then(|| std::f64::consts::LN_2 / p)
+}
+
+/// Chance that any of the patterns matched within `tried` keys, given the
+/// [`p50_tries`] of the patterns
+pub fn match_chance(tried: f64, p50: f64) -> f64 {
+    1.0 - (-tried * std::f64::consts::LN_2 / p50).exp()
+}
+
+/// Number of patterns from which every worker keeping its own copy of the
+/// matcher costs more in memory and cache misses than sharding saves.
+pub const SHARD_THRESHOLD: usize = 1_000_000;
+
+/// Whether `threads` workers should each match a shard of the patterns
+/// instead of all of them.
+///
+/// With a matcher cost linear in the number of patterns, sharding divides
+/// the matching work but also the candidates tried per pattern by the same
+/// factor, so it only pays off once the per-worker matchers stop fitting in
+/// cache.
+pub fn prefer_sharding(pars: &[Pattern], threads: usize) -> bool {
+    threads > 1 && pars.len() >= SHARD_THRESHOLD
+}
+
+/// Measure how many keys per second `threads` threads generate in total,
+/// running a burst of about `duration`.
+pub fn calibrate(key: &KeyConfig, threads: usize, duration: Duration) -> Result<f64, ApgpkError> {
+    calibrate_with::<PgpKeyGenerator>(key, threads, duration)
+}
+
+/// [`calibrate`] with keys of `G`
+pub fn calibrate_with<G: KeyGenerator + 'static>(
+    key: &KeyConfig,
+    threads: usize,
+    duration: Duration,
+) -> Result<f64, ApgpkError> {
+    let stop = Arc::new(StopSignal::new());
+    let (tx, rx) = mpsc::channel();
+    let key = key.clone();
+    let pool = WorkerPool::start(
+        PoolConfig::new(threads),
+        stop.clone(),
+        tx,
+        move |worker: Worker<f64>| {
+            let rate = calibrate_thread::<G>(&key, duration)?;
+            let _ = worker.tx.send(rate);
+            Ok::<_, ApgpkError>(())
+        },
+    )?;
+    // until every worker is done and has dropped its sender
+    let rate = rx.iter().sum();
+    pool.join();
+    match stop.reason() {
+        Some(StopReason::WorkerFailed) => Err(ApgpkError::Other(
+            "a calibration thread failed, see the log".to_string(),
+        )),
+        _ => Ok(rate),
+    }
+}
+
+fn calibrate_thread<G: KeyGenerator>(
+    key: &KeyConfig,
+    duration: Duration,
+) -> Result<f64, ApgpkError> {
+    let t = Utc::now();
+    let mut generator = G::new(key, t);
+    let begin = Instant::now();
+    let mut n: i64 = 0;
+    while begin.elapsed() < duration {
+        let created_at = t - chrono::Duration::seconds(n);
+        if let Some(fps) = generator.fingerprints_at(created_at) {
+            std::hint::black_box(fps);
+        } else {
+            let k = generator.generate(created_at);
+            std::hint::black_box(generator.fingerprints(&k));
+        }
+        n += 1;
+    }
+    Ok(n as f64 / begin.elapsed().as_secs_f64())
+}
+
+/// Rates of a fresh keypair for every candidate and of one keypair re-dated
+/// across the backshift window, in keys per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub fresh: f64,
+    pub retimed: f64,
+}
+
+impl Baseline {
+    /// Speed-up of re-dating a keypair over generating fresh ones
+    pub fn speedup(&self) -> f64 {
+        self.retimed / self.fresh
+    }
+
+    /// Whether re-dating, i.e. `--shared-key`, is worth it on this machine,
+    /// allowing for the noise of short measurements
+    pub fn prefer_retimed(&self) -> bool {
+        self.speedup() > 1.1
+    }
+}
+
+/// Measure both rates of [`Baseline`] for about `duration` each
+pub fn baseline(
+    key: &KeyConfig,
+    threads: usize,
+    duration: Duration,
+) -> Result<Baseline, ApgpkError> {
+    Ok(Baseline {
+        fresh: calibrate_with::<PgpKeyGenerator>(key, threads, duration)?,
+        retimed: calibrate_with::<RetimedKeyGenerator>(key, threads, duration)?,
+    })
+}
+
+/// Expected time to generate `tries` keys at `rate` keys per second
+pub fn expected_duration(tries: f64, rate: f64) -> Duration {
+    Duration::from_secs_f64((tries / rate).min(u64::MAX as f64))
+}
+
+/// Format a duration with the largest sensible unit, e.g. `3.2 days`
+pub fn human_duration(d: Duration) -> String {
+    let secs = d.as_secs_f64();
+    let units = [
+        (365.0 * 24.0 * 3600.0, "years"),
+        (24.0 * 3600.0, "days"),
+        (3600.0, "hours"),
+        (60.0, "minutes"),
+    ];
+    for (unit, name) in units {
+        if secs >= unit {
+            return format!("{:.1} {}", secs / unit, name);
+        }
+    }
+    format!("{:.1} seconds", secs)
+}
+
+/// Feasible alternative to a pattern which would take too long to find
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suggestion {
+    /// Search a shorter part of the pattern
+    Shorter { pattern: String, tries: f64 },
+    /// Anchor the tail of the pattern and accept the rest anywhere in the
+    /// fingerprint
+    SplitAnywhere {
+        anchored: String,
+        rest: String,
+        tries: f64,
+    },
+}
+
+impl Suggestion {
+    pub fn tries(&self) -> f64 {
+        match self {
+            Suggestion::Shorter { tries, .. } | Suggestion::SplitAnywhere { tries, .. } => *tries,
+        }
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suggestion::Shorter { pattern, .. } => {
+                write!(f, "search the shorter pattern `{}`", pattern)
+            }
+            Suggestion::SplitAnywhere { anchored, rest, .. } => write!(
+                f,
+                "anchor `{}` and accept `{}` anywhere in the fingerprint",
+                anchored, rest
+            ),
+        }
+    }
+}
+
+/// Suggest feasible decompositions of `l` if finding it at `rate` keys per
+/// second is expected to take longer than `budget`. Returns nothing for
+/// feasible patterns.
+pub fn advise(l: &Literal, rate: f64, budget: Duration) -> Vec<Suggestion> {
+    let max_tries = budget.as_secs_f64() * rate;
+    if 1.0 / l.probability() <= max_tries {
+        return vec![];
+    }
+
+    let text = l.text();
+    let n = text.len();
+    let tries_of = |t: &str, mode| 1.0 / Literal::new(t, mode, l.target()).probability();
+    let part = |k: usize| match l.mode() {
+        MatchMode::Prefix => &text[..k],
+        _ => &text[n - k..],
+    };
+
+    let mut suggestions = vec![];
+    // longest feasible part of the pattern
+    if let Some(k) = (1..n)
+        .rev()
+        .find(|k| tries_of(part(*k), l.mode()) <= max_tries)
+    {
+        suggestions.push(Suggestion::Shorter {
+            pattern: part(k).to_string(),
+            tries: tries_of(part(k), l.mode()),
+        });
+
+        let rest = match l.mode() {
+            MatchMode::Prefix => &text[k..],
+            _ => &text[..n - k],
+        };
+        if !rest.is_empty() {
+            let tries = tries_of(part(k), l.mode()) * tries_of(rest, MatchMode::Anywhere);
+            suggestions.push(Suggestion::SplitAnywhere {
+                anchored: part(k).to_string(),
+                rest: rest.to_string(),
+                tries,
+            });
+        }
+    }
+
+    suggestions.retain(|s| s.tries() <= max_tries);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Target;
+
+    #[test]
+    fn test_expected_tries() {
+        let suffix = Pattern::literal("CAFE", MatchMode::Suffix);
+        assert_eq!(expected_tries(&suffix), Some(65536.0));
+        let wildcard = Pattern::literal("CA?E", MatchMode::Suffix);
+        assert_eq!(expected_tries(&wildcard), Some(4096.0));
+        let both = Pattern::literal("CAFE", MatchMode::Both);
+        assert_eq!(expected_tries(&both), Some(32768.0));
+
+        let p50 = p50_tries(&[suffix, wildcard]).unwrap();
+        assert!((p50 - 4096.0 * 16.0 / 17.0 * std::f64::consts::LN_2).abs() < 1e-6);
+        assert!((match_chance(p50, p50) - 0.5).abs() < 1e-9);
+        assert_eq!(p50_tries(&[]), None);
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let key = KeyConfig::default();
+        let rate =
+            calibrate_with::<crate::keygen::MockKeyGenerator>(&key, 2, Duration::from_millis(20));
+        assert!(rate.unwrap() > 0.0);
+
+        let b = Baseline {
+            fresh: 100.0,
+            retimed: 250.0,
+        };
+        assert_eq!(b.speedup(), 2.5);
+        assert!(b.prefer_retimed());
+        assert!(!Baseline {
+            retimed: 105.0,
+            ..b
+        }
+        .prefer_retimed());
+    }
+
+    #[test]
+    fn test_advise() {
+        let rate = 100_000.0;
+        let budget = Duration::from_secs(3600);
+        let feasible = Literal::new("CAFE", MatchMode::Suffix, Target::Fingerprint);
+        assert!(advise(&feasible, rate, budget).is_empty());
+
+        let long = Literal::new("0123456789ABCDEF", MatchMode::Suffix, Target::Fingerprint);
+        let suggestions = advise(&long, rate, budget);
+        assert!(suggestions.contains(&Suggestion::Shorter {
+            pattern: "9ABCDEF".to_string(),
+            tries: 16f64.powi(7),
+        }));
+        assert!(suggestions.iter().all(|s| s.tries() <= 3600.0 * rate));
+    }
+}
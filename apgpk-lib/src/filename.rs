@@ -0,0 +1,139 @@
+//! Names of the files and directories written for found keys, made writable
+//! on every platform whatever pattern, label or user ID they come from.
+
+/// Default length limit of a name in bytes, leaving room below the common
+/// 255 byte limit for suffixes such as `.json.asc`
+pub const MAX_NAME_LEN: usize = 200;
+
+/// Characters Windows doesn't allow in names, `/` and `\` separate paths
+const RESERVED_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves, with any extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// How names are made safe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamePolicy {
+    /// Longest name in bytes
+    pub max_len: usize,
+    /// Replace non-ASCII characters too, for file systems or archives whose
+    /// encoding can't be relied on
+    pub ascii_only: bool,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self {
+            max_len: MAX_NAME_LEN,
+            ascii_only: false,
+        }
+    }
+}
+
+impl NamePolicy {
+    /// `name` as a single path component valid on Windows and Unix.
+    ///
+    /// Reserved and control characters become `_`, the name is cut to
+    /// `max_len` bytes without splitting a character, trailing dots and
+    /// spaces are dropped and reserved device names get a `_` prefix. An
+    /// empty result, or `.` and `..`, becomes `_`.
+    pub fn sanitize(&self, name: &str) -> String {
+        let mut out: String = name
+            .chars()
+            .map(|c| {
+                let replace = c.is_control()
+                    || RESERVED_CHARS.contains(&c)
+                    || (self.ascii_only && !c.is_ascii());
+                if replace {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        if out.len() > self.max_len {
+            let mut end = self.max_len;
+            while !out.is_char_boundary(end) {
+                end -= 1;
+            }
+            out.truncate(end);
+        }
+        // Windows silently drops them, which makes `.` and `..` empty too
+        out.truncate(out.trim_end_matches(['.', ' ']).len());
+        if out.is_empty() {
+            return "_".to_string();
+        }
+        let stem = out.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            out.insert(0, '_');
+        }
+        out
+    }
+}
+
+/// Armored secret key file of a fingerprint
+pub fn key_file_name(fingerprint: &str) -> String {
+    format!("{}.asc", NamePolicy::default().sanitize(fingerprint))
+}
+
+/// Signed metadata file of a fingerprint, see [`crate::output::sign_metadata`]
+pub fn metadata_file_name(fingerprint: &str) -> String {
+    format!("{}.json.asc", NamePolicy::default().sanitize(fingerprint))
+}
+
+/// Revocation certificate file of a fingerprint, see
+/// [`crate::utils::revocation_cert`]
+pub fn revocation_file_name(fingerprint: &str) -> String {
+    format!("{}.rev", NamePolicy::default().sanitize(fingerprint))
+}
+
+/// Minimal export of a fingerprint's key next to the full one, see
+/// [`crate::utils::Export::Both`]
+pub fn minimal_file_name(fingerprint: &str) -> String {
+    format!("{}.min.asc", NamePolicy::default().sanitize(fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        let policy = NamePolicy::default();
+        assert_eq!(policy.sanitize("CAFE1234"), "CAFE1234");
+        assert_eq!(
+            policy.sanitize("a/b\\c:d*e?f\"g<h>i|j"),
+            "a_b_c_d_e_f_g_h_i_j"
+        );
+        assert_eq!(policy.sanitize("tab\there"), "tab_here");
+        assert_eq!(policy.sanitize("../.."), ".._");
+        assert_eq!(policy.sanitize(".."), "_");
+        assert_eq!(policy.sanitize(""), "_");
+        assert_eq!(policy.sanitize("name. . "), "name");
+        assert_eq!(policy.sanitize("con"), "_con");
+        assert_eq!(policy.sanitize("LPT1.txt"), "_LPT1.txt");
+        assert_eq!(policy.sanitize("CONSOLE"), "CONSOLE");
+        assert_eq!(policy.sanitize("Café"), "Café");
+
+        let ascii = NamePolicy {
+            ascii_only: true,
+            ..policy
+        };
+        assert_eq!(ascii.sanitize("Café"), "Caf_");
+
+        let short = NamePolicy {
+            max_len: 4,
+            ..policy
+        };
+        assert_eq!(short.sanitize("abcdef"), "abcd");
+        // é is 2 bytes and would be cut in half
+        assert_eq!(short.sanitize("abcé"), "abc");
+        assert_eq!(policy.sanitize(&"x".repeat(300)).len(), MAX_NAME_LEN);
+
+        assert_eq!(key_file_name("CAFE"), "CAFE.asc");
+        assert_eq!(metadata_file_name("CAFE"), "CAFE.json.asc");
+    }
+}
@@ -0,0 +1,179 @@
+//! Preparing a found key for real use.
+//!
+//! Found keys carry the user ID they were mined with and nothing else. The
+//! finalized key gets its real user IDs, expiration, preferences and subkeys
+//! bound by fresh self signatures, while the primary key material and
+//! creation time, and so the fingerprint, are kept.
+
+use crate::{
+    config::{self, KeyConfig},
+    error::ApgpkError,
+    keygen::RetimedKeyGenerator,
+    unsigned::{UnsignedKey, UnsignedSubkey},
+};
+use chrono::{SubsecRound, Utc};
+use pgp::{composed::signed_key::SignedSecretKey, types::SecretParams, Deserializable};
+
+/// Unprotected armored secret key, e.g. one saved by a search
+pub fn load_key(armored: &str) -> Result<SignedSecretKey, ApgpkError> {
+    let (key, _) = SignedSecretKey::from_string(armored)?;
+    key.verify()?;
+    if !matches!(key.primary_key.secret_params(), SecretParams::Plain(_)) {
+        return Err(ApgpkError::Other(
+            "the key is passphrase protected, export it without a passphrase first".to_string(),
+        ));
+    }
+    Ok(key)
+}
+
+/// User IDs bound to `key`, the primary one first
+pub fn user_ids(key: &SignedSecretKey) -> Vec<String> {
+    key.details
+        .users
+        .iter()
+        .map(|u| u.id.id().to_string())
+        .collect()
+}
+
+/// `found` with the user IDs, capabilities, expiration and preferences of
+/// `key` instead of its own. Its subkeys are kept, and with
+/// `key.encryption_subkey` a fresh encryption subkey is added if it has none.
+pub fn finalize(found: &SignedSecretKey, key: &KeyConfig) -> Result<UnsignedKey, ApgpkError> {
+    let primary = &found.primary_key;
+    let key = KeyConfig {
+        key_type: config::key_type_of(primary.public_params()).ok_or_else(|| {
+            ApgpkError::Other(
+                "only RSA, EdDSA, ECDH and ECDSA P-256/P-384 keys can be finalized".to_string(),
+            )
+        })?,
+        encryption_subkey: key.encryption_subkey && found.secret_subkeys.is_empty(),
+        ..key.clone()
+    };
+    key.validate()?;
+
+    let mut k = UnsignedKey::from_primary(&key, primary.clone());
+    k.secret_subkeys = found
+        .secret_subkeys
+        .iter()
+        .map(|s| UnsignedSubkey {
+            key: s.key.clone(),
+            key_flags: s
+                .signatures
+                .first()
+                .map(|sig| sig.key_flags())
+                .unwrap_or_default(),
+        })
+        .collect();
+    if key.encryption_subkey {
+        let now = Utc::now().trunc_subsecs(0);
+        k.secret_subkeys
+            .push(UnsignedSubkey::encryption(now, &mut rand::thread_rng())?);
+    }
+    Ok(k)
+}
+
+/// Generator re-dating `existing`, with the user IDs, capabilities and
+/// preferences of `key` but its own expiration and subkeys. Only the primary
+/// key is re-dated, so only its fingerprint changes.
+pub fn redate(
+    existing: &SignedSecretKey,
+    key: &KeyConfig,
+) -> Result<RetimedKeyGenerator, ApgpkError> {
+    let key = KeyConfig {
+        expiration_days: existing
+            .details
+            .key_expiration_time()
+            .and_then(|d| u16::try_from(d.num_days()).ok())
+            .filter(|&days| days > 0),
+        encryption_subkey: false,
+        ..key.clone()
+    };
+    Ok(RetimedKeyGenerator::from_key(finalize(existing, &key)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        keygen::{KeyGenerator, PgpKeyGenerator},
+        utils,
+    };
+    use chrono::Duration;
+    use pgp::types::KeyTrait;
+
+    #[test]
+    fn test_finalize() {
+        let now = Utc::now();
+        let mined = PgpKeyGenerator::new(&KeyConfig::default(), now).generate(now);
+        let armored = utils::export_key(&mined, None).unwrap();
+        let found = load_key(&armored).unwrap();
+        assert_eq!(user_ids(&found), ["apgpk"]);
+
+        let key = KeyConfig {
+            user_ids: vec!["Me <me@example.org>".to_string()],
+            encryption_subkey: true,
+            expiration_days: Some(365),
+            ..KeyConfig::new("Me <me@example.com>".to_string())
+        };
+        let finalized = finalize(&found, &key).unwrap();
+        assert_eq!(finalized.fingerprint(), mined.fingerprint());
+        assert_eq!(finalized.secret_subkeys.len(), 1);
+
+        let armored = utils::export_key(&finalized, Some("passphrase")).unwrap();
+        let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.fingerprint(), mined.fingerprint());
+        assert_eq!(
+            read.primary_key.created_at(),
+            mined.primary_key.created_at()
+        );
+        assert_eq!(
+            read.expires_at(),
+            Some(*read.primary_key.created_at() + Duration::days(365))
+        );
+        assert_eq!(
+            user_ids(&read),
+            ["Me <me@example.com>", "Me <me@example.org>"]
+        );
+        assert!(load_key(&armored).is_err());
+
+        // the subkey is kept rather than a second one added
+        let again = finalize(
+            &load_key(&utils::export_key(&finalized, None).unwrap()).unwrap(),
+            &key,
+        )
+        .unwrap();
+        assert_eq!(again.secret_subkeys.len(), 1);
+    }
+
+    #[test]
+    fn test_redate() {
+        let key = KeyConfig {
+            encryption_subkey: true,
+            expiration_days: Some(30),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        let original = PgpKeyGenerator::new(&key, now).generate(now);
+        let existing = load_key(&utils::export_key(&original, None).unwrap()).unwrap();
+
+        let mut generator = redate(&existing, &KeyConfig::default()).unwrap();
+        let earlier = now - Duration::days(1);
+        let redated = generator.generate(earlier);
+        assert_ne!(redated.fingerprint(), original.fingerprint());
+        assert_eq!(
+            redated.secret_subkeys[0].fingerprint(),
+            original.secret_subkeys[0].fingerprint()
+        );
+        let read = load_key(&utils::export_key(&redated, None).unwrap()).unwrap();
+        assert_eq!(read.fingerprint(), redated.fingerprint());
+        assert_eq!(
+            read.primary_key.created_at().timestamp(),
+            earlier.timestamp()
+        );
+        assert_eq!(
+            read.expires_at(),
+            Some(earlier.trunc_subsecs(0) + Duration::days(30))
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! V4 fingerprints of one public key packet at many creation times.
+//!
+//! The fingerprint is the SHA-1 of `0x99 ‖ length ‖ version ‖ creation time ‖
+//! algorithm ‖ public parameters`, where only the creation time differs
+//! between the candidates of a re-dated keypair. The message is padded and
+//! its SHA-1 message schedule expanded once, so per candidate only the
+//! schedule words of the first block which depend on the creation time are
+//! recomputed. The first SHA-1 round, which covers the fixed first word
+//! only, is done once too. The creation time is the second word of the
+//! first block, so every round after it is done per candidate.
+//!
+//! On x86-64 CPUs with the SHA extensions the blocks are hashed with those
+//! instead, checked once at runtime, see [`backend`].
+
+use crate::keygen;
+use pgp::{packet, ser::Serialize, types::KeyVersion};
+use std::{fmt, sync::OnceLock};
+
+/// Length of a v4 fingerprint in bytes
+pub const FINGERPRINT_LEN: usize = 20;
+
+const H0: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+/// SHA-1 implementation the fingerprints are computed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// x86 SHA extensions
+    ShaNi,
+    /// Portable, starting from the precomputed message schedules and first
+    /// round
+    Scalar,
+}
+
+/// The fastest [`Backend`] this CPU supports, detected once
+pub fn backend() -> Backend {
+    static BACKEND: OnceLock<Backend> = OnceLock::new();
+    *BACKEND.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return Backend::ShaNi;
+        }
+        Backend::Scalar
+    })
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::ShaNi => "SHA-NI",
+            Backend::Scalar => "scalar",
+        })
+    }
+}
+
+/// One public key packet, ready to be fingerprinted at any creation time
+#[derive(Debug, Clone)]
+pub struct TimestampSweep {
+    // padded message as big-endian words, the creation time left at 0
+    blocks: Vec<[u32; 16]>,
+    // message schedule of every block, the creation time left at 0
+    schedules: Vec<[u32; 80]>,
+    // words of the first schedule depending on the creation time, past the
+    // creation time itself
+    varying: Vec<usize>,
+    // working variables after the first round of the first block
+    midstate: [u32; 5],
+}
+
+impl TimestampSweep {
+    /// Sweep over the primary key packet of `key`, None unless it's v4
+    pub fn new(key: &packet::SecretKey) -> Option<Self> {
+        if key.version() != KeyVersion::V4 {
+            return None;
+        }
+        let body = keygen::public_packet(key, *key.created_at())
+            .to_bytes()
+            .ok()?;
+        Self::from_body(&body)
+    }
+
+    /// Sweep over the serialized v4 public key packet body `body`
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        // version, creation time and algorithm at least
+        if body.len() < 6 || body[0] != 4 || body.len() > u16::MAX as usize {
+            return None;
+        }
+        let mut msg = Vec::with_capacity(body.len() + 3 + 72);
+        msg.push(0x99);
+        msg.extend((body.len() as u16).to_be_bytes());
+        msg.push(body[0]);
+        msg.extend([0; 4]);
+        msg.extend(&body[5..]);
+        let bits = msg.len() as u64 * 8;
+        msg.push(0x80);
+        while msg.len() % 64 != 56 {
+            msg.push(0);
+        }
+        msg.extend(bits.to_be_bytes());
+
+        let blocks: Vec<[u32; 16]> = msg
+            .chunks_exact(64)
+            .map(|chunk| {
+                let mut words = [0; 16];
+                for (w, b) in words.iter_mut().zip(chunk.chunks_exact(4)) {
+                    *w = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+                }
+                words
+            })
+            .collect();
+        let schedules = blocks.iter().map(schedule).collect();
+        // the creation time is word 1, which word t depends on through the
+        // words it is expanded from
+        let mut depends = [false; 80];
+        depends[1] = true;
+        for t in 16..80 {
+            depends[t] = depends[t - 3] || depends[t - 8] || depends[t - 14] || depends[t - 16];
+        }
+        let varying = (16..80).filter(|&t| depends[t]).collect();
+        let mut midstate = H0;
+        round(&mut midstate, 0, blocks[0][0]);
+        Some(Self {
+            blocks,
+            schedules,
+            varying,
+            midstate,
+        })
+    }
+
+    /// Padded message as big-endian SHA-1 words, 16 per block, the creation
+    /// time being the second word and 0
+    pub fn blocks(&self) -> &[[u32; 16]] {
+        &self.blocks
+    }
+
+    /// Fingerprint of the packet created at `created_at`, in Unix seconds
+    pub fn fingerprint(&self, created_at: u32) -> [u8; FINGERPRINT_LEN] {
+        self.fingerprint_with(backend(), created_at)
+    }
+
+    fn fingerprint_with(&self, backend: Backend, created_at: u32) -> [u8; FINGERPRINT_LEN] {
+        let mut state = H0;
+        match backend {
+            #[cfg(target_arch = "x86_64")]
+            Backend::ShaNi => {
+                let mut first = self.blocks[0];
+                first[1] = created_at;
+                // SAFETY: only `backend` passes ShaNi, if the CPU supports it
+                unsafe {
+                    shani::compress(&mut state, &first);
+                    for block in &self.blocks[1..] {
+                        shani::compress(&mut state, block);
+                    }
+                }
+            }
+            _ => {
+                let mut w = self.schedules[0];
+                w[1] = created_at;
+                for &t in &self.varying {
+                    w[t] = expand(&w, t);
+                }
+                let mut vars = self.midstate;
+                rounds(&mut vars, &w, 1);
+                add(&mut state, &vars);
+                for w in &self.schedules[1..] {
+                    let mut vars = state;
+                    rounds(&mut vars, w, 0);
+                    add(&mut state, &vars);
+                }
+            }
+        }
+
+        let mut fp = [0; FINGERPRINT_LEN];
+        for (out, word) in fp.chunks_exact_mut(4).zip(state) {
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+        fp
+    }
+}
+
+fn add(state: &mut [u32; 5], vars: &[u32; 5]) {
+    for (s, v) in state.iter_mut().zip(vars) {
+        *s = s.wrapping_add(*v);
+    }
+}
+
+/// SHA-1 message schedule of `block`
+fn schedule(block: &[u32; 16]) -> [u32; 80] {
+    let mut w = [0; 80];
+    w[..16].copy_from_slice(block);
+    for t in 16..80 {
+        w[t] = expand(&w, t);
+    }
+    w
+}
+
+fn expand(w: &[u32; 80], t: usize) -> u32 {
+    (w[t - 3] ^ w[t - 8] ^ w[t - 14] ^ w[t - 16]).rotate_left(1)
+}
+
+/// SHA-1 rounds `from..80` of the message schedule `w` on the working
+/// variables `vars`
+fn rounds(vars: &mut [u32; 5], w: &[u32; 80], from: usize) {
+    for (t, w) in w.iter().enumerate().skip(from) {
+        round(vars, t, *w);
+    }
+}
+
+fn round(vars: &mut [u32; 5], t: usize, w: u32) {
+    let [a, b, c, d, e] = *vars;
+    let (f, k) = match t {
+        0..=19 => ((b & c) | (!b & d), 0x5A827999),
+        20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+        40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+        _ => (b ^ c ^ d, 0xCA62C1D6),
+    };
+    let temp = a
+        .rotate_left(5)
+        .wrapping_add(f)
+        .wrapping_add(e)
+        .wrapping_add(k)
+        .wrapping_add(w);
+    *vars = [temp, a, b.rotate_left(30), c, d];
+}
+
+#[cfg(target_arch = "x86_64")]
+mod shani {
+    use std::arch::x86_64::*;
+
+    macro_rules! rounds4 {
+        ($h0:ident, $h1:ident, $wk:expr, $i:expr) => {
+            _mm_sha1rnds4_epu32($h0, _mm_sha1nexte_epu32($h1, $wk), $i)
+        };
+    }
+
+    macro_rules! schedule_rounds4 {
+        ($h0:ident, $h1:ident, $w0:expr, $w1:expr, $w2:expr, $w3:expr, $w4:expr, $i:expr) => {
+            $w4 = _mm_sha1msg2_epu32(_mm_xor_si128(_mm_sha1msg1_epu32($w0, $w1), $w2), $w3);
+            $h1 = rounds4!($h0, $h1, $w4, $i);
+        };
+    }
+
+    /// SHA-1 compression of `block` into `state`
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub(super) unsafe fn compress(state: &mut [u32; 5], block: &[u32; 16]) {
+        let words = |i: usize| {
+            _mm_set_epi32(
+                block[i] as i32,
+                block[i + 1] as i32,
+                block[i + 2] as i32,
+                block[i + 3] as i32,
+            )
+        };
+        let abcd = _mm_set_epi32(
+            state[0] as i32,
+            state[1] as i32,
+            state[2] as i32,
+            state[3] as i32,
+        );
+        let e = _mm_set_epi32(state[4] as i32, 0, 0, 0);
+        let (mut w0, mut w1, mut w2, mut w3) = (words(0), words(4), words(8), words(12));
+        let mut w4;
+
+        // rounds 0..20
+        let mut h0 = abcd;
+        let mut h1 = _mm_sha1rnds4_epu32(h0, _mm_add_epi32(e, w0), 0);
+        h0 = rounds4!(h1, h0, w1, 0);
+        h1 = rounds4!(h0, h1, w2, 0);
+        h0 = rounds4!(h1, h0, w3, 0);
+        schedule_rounds4!(h0, h1, w0, w1, w2, w3, w4, 0);
+        // rounds 20..40
+        schedule_rounds4!(h1, h0, w1, w2, w3, w4, w0, 1);
+        schedule_rounds4!(h0, h1, w2, w3, w4, w0, w1, 1);
+        schedule_rounds4!(h1, h0, w3, w4, w0, w1, w2, 1);
+        schedule_rounds4!(h0, h1, w4, w0, w1, w2, w3, 1);
+        schedule_rounds4!(h1, h0, w0, w1, w2, w3, w4, 1);
+        // rounds 40..60
+        schedule_rounds4!(h0, h1, w1, w2, w3, w4, w0, 2);
+        schedule_rounds4!(h1, h0, w2, w3, w4, w0, w1, 2);
+        schedule_rounds4!(h0, h1, w3, w4, w0, w1, w2, 2);
+        schedule_rounds4!(h1, h0, w4, w0, w1, w2, w3, 2);
+        schedule_rounds4!(h0, h1, w0, w1, w2, w3, w4, 2);
+        // rounds 60..80
+        schedule_rounds4!(h1, h0, w1, w2, w3, w4, w0, 3);
+        schedule_rounds4!(h0, h1, w2, w3, w4, w0, w1, 3);
+        schedule_rounds4!(h1, h0, w3, w4, w0, w1, w2, 3);
+        schedule_rounds4!(h0, h1, w4, w0, w1, w2, w3, 3);
+        schedule_rounds4!(h1, h0, w0, w1, w2, w3, w4, 3);
+
+        let abcd = _mm_add_epi32(abcd, h0);
+        let e = _mm_sha1nexte_epu32(h1, e);
+        state[0] = _mm_extract_epi32(abcd, 3) as u32;
+        state[1] = _mm_extract_epi32(abcd, 2) as u32;
+        state[2] = _mm_extract_epi32(abcd, 1) as u32;
+        state[3] = _mm_extract_epi32(abcd, 0) as u32;
+        state[4] = _mm_extract_epi32(e, 3) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{parse_key_type, KeyConfig},
+        keygen::{KeyGenerator, PgpKeyGenerator, RetimedKeyGenerator},
+    };
+    use chrono::{TimeZone, Utc};
+    use pgp::types::KeyTrait;
+
+    #[test]
+    fn test_fingerprint() {
+        let now = Utc::now();
+        for key_type in ["eddsa", "cv25519", "rsa2048"] {
+            let key = KeyConfig {
+                key_type: parse_key_type(key_type).unwrap(),
+                can_sign: key_type != "cv25519",
+                can_certify: key_type != "cv25519",
+                can_encrypt: key_type == "cv25519",
+                ..Default::default()
+            };
+            let base = PgpKeyGenerator::new(&key, now).generate(now);
+            let sweep = TimestampSweep::new(&base.primary_key).unwrap();
+            let mut retimed = RetimedKeyGenerator::from_key(base);
+            for ts in [0, 1, 0x5A00_0000, now.timestamp() as u32, u32::MAX] {
+                let created_at = Utc.timestamp_opt(ts as i64, 0).unwrap();
+                assert_eq!(
+                    sweep.fingerprint(ts).to_vec(),
+                    retimed.generate(created_at).fingerprint(),
+                    "{} at {}",
+                    key_type,
+                    ts
+                );
+            }
+        }
+        assert!(TimestampSweep::from_body(&[3, 0, 0, 0, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn test_precomputed() {
+        use sha1::{Digest, Sha1};
+
+        // bodies ending around the block boundaries, as the packet header
+        // takes 3 bytes and the padding at least 9
+        for len in [6, 52, 53, 54, 55, 116, 117, 118, 119, 180, 181] {
+            let body: Vec<u8> = [4, 0, 0, 0, 0, 22]
+                .into_iter()
+                .chain((0..len - 6).map(|i| i as u8))
+                .collect();
+            let sweep = TimestampSweep::from_body(&body).unwrap();
+            for ts in [0, 1, 0x5A00_0000, u32::MAX] {
+                let mut packet = vec![0x99];
+                packet.extend((body.len() as u16).to_be_bytes());
+                packet.push(4);
+                packet.extend(ts.to_be_bytes());
+                packet.extend(&body[5..]);
+                let expected: [u8; FINGERPRINT_LEN] = Sha1::digest(&packet).into();
+                assert_eq!(
+                    sweep.fingerprint_with(Backend::Scalar, ts),
+                    expected,
+                    "{} bytes at {}",
+                    len,
+                    ts
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends() {
+        let body: Vec<u8> = [4, 0, 0, 0, 0, 22].into_iter().chain(0..=200).collect();
+        let sweep = TimestampSweep::from_body(&body).unwrap();
+        for ts in [0, 0x5A00_0000, u32::MAX] {
+            let expected = sweep.fingerprint_with(Backend::Scalar, ts);
+            assert_eq!(sweep.fingerprint(ts), expected, "{} at {}", backend(), ts);
+        }
+    }
+}
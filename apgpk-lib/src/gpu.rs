@@ -0,0 +1,292 @@
+//! Fingerprint sweeps offloaded to a GPU.
+//!
+//! The CPU generates a keypair and uploads its padded public key packet, see
+//! [`TimestampSweep`], then the device hashes every creation time of the
+//! backshift window and reports those passing the [`FpMask`]s of the
+//! patterns. Every reported one is hashed again and matched on the CPU
+//! before its key is built, so a faulty device can miss keys but never
+//! make one up.
+
+use crate::{
+    clock::Clock,
+    config::{invalid, MatchKey, SearchConfig},
+    core::{self, FpMask, Matcher, Msg, Pattern},
+    error::ApgpkError,
+    fingerprint::TimestampSweep,
+    keygen::{KeyGenerator, RetimedKeyGenerator},
+    stop::{StopReason, StopSignal},
+    watchdog::Heartbeat,
+};
+use chrono::prelude::*;
+use std::{sync::mpsc::Sender, time::Instant};
+
+/// Creation times hashed per device call, few enough to stop quickly
+pub const SWEEP_CHUNK: u32 = 1 << 24;
+
+/// GPU programming interface a device is driven through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    /// Behind the `gpu-opencl` feature
+    OpenCl,
+    /// NVIDIA GPUs, behind the `gpu-cuda` feature
+    Cuda,
+}
+
+/// Device hashing many creation times of one packet per call
+pub trait SweepDevice {
+    /// Name of the device for the log
+    fn name(&self) -> String;
+
+    /// Creation times to sweep per call, as many as keep the device busy
+    /// for a moment
+    fn chunk(&self) -> u32 {
+        SWEEP_CHUNK
+    }
+
+    /// Offsets from `first` of the creation times `first..first + count`
+    /// whose fingerprint passes one of the masks the device was set up
+    /// with, in any order
+    fn sweep(
+        &mut self,
+        sweep: &TimestampSweep,
+        first: u32,
+        count: u32,
+    ) -> Result<Vec<u32>, ApgpkError>;
+}
+
+/// Device `index` of `api` filtering with `masks`, see [`masks`]
+pub fn open(api: Api, index: usize, masks: &[FpMask]) -> Result<Box<dyn SweepDevice>, ApgpkError> {
+    let _ = (index, masks);
+    match api {
+        #[cfg(feature = "gpu-opencl")]
+        Api::OpenCl => Ok(Box::new(crate::opencl::OpenClDevice::new(index, masks)?)),
+        #[cfg(feature = "gpu-cuda")]
+        Api::Cuda => Ok(Box::new(crate::cuda::CudaSweepDevice::new(index, masks)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(unsupported(api)),
+    }
+}
+
+/// Names of the devices of `api`, numbered as [`open`] takes them
+pub fn devices(api: Api) -> Result<Vec<String>, ApgpkError> {
+    match api {
+        #[cfg(feature = "gpu-opencl")]
+        Api::OpenCl => crate::opencl::devices(),
+        #[cfg(feature = "gpu-cuda")]
+        Api::Cuda => crate::cuda::devices(),
+        #[allow(unreachable_patterns)]
+        _ => Err(unsupported(api)),
+    }
+}
+
+#[cfg_attr(all(feature = "gpu-opencl", feature = "gpu-cuda"), allow(dead_code))]
+fn unsupported(api: Api) -> ApgpkError {
+    let feature = match api {
+        Api::OpenCl => "gpu-opencl",
+        Api::Cuda => "gpu-cuda",
+    };
+    ApgpkError::Other(format!(
+        "this build has no {:?} support, rebuild with the `{}` feature",
+        api, feature
+    ))
+}
+
+/// `masks` as the kernels take them, 5 mask words then 5 value words each
+#[cfg_attr(
+    not(any(feature = "gpu-opencl", feature = "gpu-cuda")),
+    allow(dead_code)
+)]
+pub(crate) fn mask_words(masks: &[FpMask]) -> Vec<u32> {
+    let words = |bytes: &[u8]| -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    };
+    masks
+        .iter()
+        .flat_map(|m| [words(&m.mask), words(&m.value)].concat())
+        .collect()
+}
+
+/// Masks of `patterns` for a device, after checking the search only matches
+/// what a device can prefilter: the primary key fingerprint against
+/// patterns made of literals
+pub fn masks(config: &SearchConfig, patterns: &[Pattern]) -> Result<Vec<FpMask>, ApgpkError> {
+    if config.scorer.is_some() || !config.detectors.is_empty() {
+        return Err(invalid(
+            "scores and detectors are only computed on the CPU, drop them to search on a GPU",
+        ));
+    }
+    let key = &config.key;
+    if key.match_key != MatchKey::Primary || key.match_keygrip || !key.ssh_patterns.is_empty() {
+        return Err(invalid(
+            "a GPU sweeps the primary key fingerprint only, drop the subkey, keygrip and SSH matching",
+        ));
+    }
+    let masks = patterns
+        .iter()
+        .map(|p| {
+            p.masks().ok_or_else(|| {
+                invalid(format!(
+                    "pattern `{}` can't be prefiltered on a GPU, use literal patterns only",
+                    p
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+    if masks.is_empty() {
+        return Err(invalid("no pattern for the GPU to search"));
+    }
+    Ok(masks)
+}
+
+/// One sweep of the backshift window by `device` with a fresh keypair, like
+/// [`core::search`] does on the CPU
+pub fn search<M: Matcher>(
+    device: &mut dyn SweepDevice,
+    config: &SearchConfig,
+    mut matcher: M,
+    exit_signal: &StopSignal,
+    msg_tx: &Sender<Msg>,
+    heartbeat: &Heartbeat,
+    clock: &dyn Clock,
+) -> Result<(), ApgpkError> {
+    // true if the receiver is gone, which stops the whole pool
+    let send = |msg| {
+        let gone = msg_tx.send(msg).is_err();
+        if gone && exit_signal.stop(StopReason::ReceiverGone) {
+            log::error!("Receiver of the found keys is gone, stopping all workers");
+        }
+        gone
+    };
+    let t = core::start(config, clock);
+    let mut generator = RetimedKeyGenerator::new(&config.key, t);
+    let sweep = generator
+        .sweep()
+        .cloned()
+        .ok_or_else(|| invalid("a GPU sweeps v4 key fingerprints only"))?;
+    let latest = t.timestamp().clamp(0, u32::MAX as i64);
+    let mut first = (t.timestamp() - config.window() + 1).max(0);
+    heartbeat.beat();
+
+    while first <= latest {
+        let count = (latest - first + 1).min(device.chunk().max(1) as i64) as u32;
+        let begin = Instant::now();
+        for offset in device.sweep(&sweep, first as u32, count)? {
+            if offset >= count {
+                log::warn!("{} reported a creation time it didn't sweep", device.name());
+                continue;
+            }
+            let created_at = first as u32 + offset;
+            let Some(m) = matcher.matches(&sweep.fingerprint(created_at)) else {
+                continue;
+            };
+            let k = generator.generate(Utc.timestamp_opt(created_at as i64, 0).unwrap());
+            if send(Msg::Key(Box::new(k), m.pattern)) {
+                return Ok(());
+            }
+        }
+        if send(Msg::Speed(
+            count as u64,
+            count as f64 / begin.elapsed().as_secs_f64(),
+        )) {
+            return Ok(());
+        }
+        heartbeat.beat();
+        if exit_signal.is_stopped() {
+            break;
+        }
+        matcher.refresh()?;
+        first += count as i64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::FixedClock,
+        config::KeyConfig,
+        core::{MatchMode, PatternSet},
+    };
+    use pgp::types::KeyTrait;
+
+    /// Sweeps on the CPU like a device would
+    struct CpuDevice(Vec<FpMask>);
+
+    impl SweepDevice for CpuDevice {
+        fn name(&self) -> String {
+            "CPU".into()
+        }
+
+        fn sweep(
+            &mut self,
+            sweep: &TimestampSweep,
+            first: u32,
+            count: u32,
+        ) -> Result<Vec<u32>, ApgpkError> {
+            Ok((0..count)
+                .filter(|i| {
+                    let fp = sweep.fingerprint(first + i);
+                    self.0.iter().any(|m| m.is_match(&fp))
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let patterns = vec![
+            Pattern::literal("AB", MatchMode::Suffix),
+            Pattern::All(vec![
+                Pattern::literal("C", MatchMode::Prefix),
+                Pattern::Regex(regex::Regex::new("D$").unwrap(), Default::default()),
+            ]),
+            Pattern::Exclude(Box::new(Pattern::literal("1AB", MatchMode::Suffix))),
+        ];
+        let config = SearchConfig::new(KeyConfig::default(), 1);
+        let fp_masks = masks(&config, &patterns).unwrap();
+        assert_eq!(fp_masks.len(), 2);
+        assert!(masks(
+            &config,
+            &[Pattern::Regex(
+                regex::Regex::new("D$").unwrap(),
+                Default::default()
+            )]
+        )
+        .is_err());
+
+        let set = PatternSet::new(patterns.clone());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let clock = FixedClock(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+        search(
+            &mut CpuDevice(fp_masks),
+            &config,
+            set.matcher(None).unwrap(),
+            &StopSignal::new(),
+            &tx,
+            &Heartbeat::default(),
+            &clock,
+        )
+        .unwrap();
+        drop(tx);
+        let keys: Vec<_> = rx
+            .into_iter()
+            .filter_map(|m| match m {
+                Msg::Key(k, _) => Some(k),
+                _ => None,
+            })
+            .collect();
+        // a day of timestamps, about two in 256 of them
+        assert!((300..1200).contains(&keys.len()), "{}", keys.len());
+        for k in keys {
+            let fp = k.fingerprint();
+            assert!(patterns[..2].iter().any(|p| p.is_match(&fp)));
+            assert!(!patterns[2].is_match(&fp));
+            assert!(k.primary_key.created_at() <= &clock.0);
+        }
+    }
+}
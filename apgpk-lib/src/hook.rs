@@ -0,0 +1,99 @@
+use crate::error::ApgpkError;
+use std::{
+    process::{Command, Stdio},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A user command executed for every found key.
+///
+/// Key details are passed through environment variables, each command runs
+/// in its own thread and is killed once `timeout` is exceeded. At most
+/// `max_concurrent` commands run at the same time, further calls to
+/// [`ExecHook::run`] block until a slot frees up.
+#[derive(Debug, Clone)]
+pub struct ExecHook {
+    cmd: String,
+    timeout: Duration,
+    max_concurrent: usize,
+    running: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl ExecHook {
+    pub fn new(cmd: String, timeout: Duration, max_concurrent: usize) -> Self {
+        Self {
+            cmd,
+            timeout,
+            max_concurrent: max_concurrent.max(1),
+            running: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    /// Spawn the command with the given extra environment variables.
+    pub fn run(&self, envs: Vec<(&'static str, String)>) -> Result<(), ApgpkError> {
+        let (lock, cvar) = &*self.running;
+        let mut running = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while *running >= self.max_concurrent {
+            running = cvar.wait(running).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        let mut child = shell(&self.cmd).envs(envs).stdin(Stdio::null()).spawn()?;
+        *running += 1;
+        drop(running);
+
+        let cmd = self.cmd.clone();
+        let timeout = self.timeout;
+        let slots = self.running.clone();
+        thread::spawn(move || {
+            let begin = Instant::now();
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) if status.success() => break,
+                    Ok(Some(status)) => {
+                        log::warn!("Hook `{}` exited with {}", cmd, status);
+                        break;
+                    }
+                    Ok(None) if begin.elapsed() > timeout => {
+                        log::warn!("Hook `{}` timed out after {:?}, killing", cmd, timeout);
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        break;
+                    }
+                    Ok(None) => thread::sleep(Duration::from_millis(50)),
+                    Err(e) => {
+                        log::error!("Failed to wait for hook `{}`: {}", cmd, e);
+                        break;
+                    }
+                }
+            }
+            let (lock, cvar) = &*slots;
+            *lock.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+            cvar.notify_all();
+        });
+        Ok(())
+    }
+
+    /// Block until all running commands have finished.
+    pub fn wait_idle(&self) {
+        let (lock, cvar) = &*self.running;
+        let mut running = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while *running > 0 {
+            running = cvar.wait(running).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("cmd");
+    c.arg("/C").arg(cmd);
+    c
+}
+
+#[cfg(not(windows))]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c").arg(cmd);
+    c
+}
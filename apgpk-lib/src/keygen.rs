@@ -0,0 +1,316 @@
+use crate::{
+    config::{KeyConfig, MatchKey},
+    fingerprint::TimestampSweep,
+    keygrip,
+    seed::Seed,
+    ssh,
+    unsigned::{self, UnsignedKey},
+};
+use chrono::prelude::*;
+use pgp::{
+    packet::{self, PacketTrait},
+    types::KeyTrait,
+};
+
+/// Source of the candidate keys tried by [`crate::core::search`]
+pub trait KeyGenerator: Sized {
+    type Key: Send;
+
+    /// Generator for keys described by `key`, `created_at` being the first
+    /// creation time asked for.
+    fn new(key: &KeyConfig, created_at: DateTime<Utc>) -> Self;
+
+    /// A fresh key created at `created_at`
+    fn generate(&mut self, created_at: DateTime<Utc>) -> Self::Key;
+
+    /// Fingerprints the patterns are matched against
+    fn fingerprints(&self, key: &Self::Key) -> Vec<Vec<u8>>;
+
+    /// [`KeyGenerator::fingerprints`] of the key [`KeyGenerator::generate`]
+    /// would create at `created_at`, without building it. None if the
+    /// generator has no faster way than building the key.
+    fn fingerprints_at(&mut self, _created_at: DateTime<Utc>) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    /// Identifies the keypairs of the generator, if a creation time always
+    /// gives the same key, e.g. a re-dated keypair or one derived from a
+    /// seed. None for fresh random keypairs.
+    fn keypair_id(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Real OpenPGP keys, see [`UnsignedKey::generate`]
+#[derive(Debug)]
+pub struct PgpKeyGenerator {
+    key: KeyConfig,
+}
+
+impl KeyGenerator for PgpKeyGenerator {
+    type Key = UnsignedKey;
+
+    fn new(key: &KeyConfig, _created_at: DateTime<Utc>) -> Self {
+        Self { key: key.clone() }
+    }
+
+    fn generate(&mut self, created_at: DateTime<Utc>) -> UnsignedKey {
+        let key = match &self.key.seed {
+            Some(seed) => {
+                let mut rng = seed.rng(self.key.keypair_counter(created_at));
+                UnsignedKey::generate(&self.key, created_at, &mut rng)
+            }
+            None => UnsignedKey::generate(&self.key, created_at, &mut rand::thread_rng()),
+        };
+        key.unwrap() // validated config can't fail
+    }
+
+    fn fingerprints(&self, key: &UnsignedKey) -> Vec<Vec<u8>> {
+        matched_values(key, &self.key)
+    }
+
+    fn keypair_id(&self) -> Option<u64> {
+        self.key.seed.as_ref().map(Seed::id)
+    }
+}
+
+/// Values of `key` the patterns are matched against according to `config`,
+/// the fingerprints chosen by `match_key` or the keygrip. None at all if
+/// the SSH fingerprint misses every one of `ssh_patterns`.
+pub fn matched_values(key: &UnsignedKey, config: &KeyConfig) -> Vec<Vec<u8>> {
+    if !config.ssh_patterns.is_empty() {
+        let ssh = ssh::key_fingerprint(key);
+        if !ssh.is_some_and(|fp| config.ssh_patterns.iter().any(|p| p.is_match(&fp))) {
+            return vec![];
+        }
+    }
+    if config.match_keygrip {
+        return keygrip::keygrip(key.primary_key.public_params())
+            .map(|grip| grip.to_vec())
+            .into_iter()
+            .collect();
+    }
+    let subkey = || key.secret_subkeys.iter().map(|k| k.fingerprint());
+    match config.match_key {
+        MatchKey::Primary => vec![key.fingerprint()],
+        MatchKey::Subkey => subkey().collect(),
+        MatchKey::Either => std::iter::once(key.fingerprint()).chain(subkey()).collect(),
+    }
+}
+
+/// One OpenPGP keypair tried at every creation time, for workers sharing a
+/// key and each sweeping its own [`crate::config::TimeSlice`].
+///
+/// Only the primary key is re-dated, so its fingerprint is the one matched.
+/// The public key packet is serialized once and only its creation time is
+/// changed before hashing, see [`TimestampSweep`].
+#[derive(Debug, Clone)]
+pub struct RetimedKeyGenerator {
+    base: UnsignedKey,
+    sweep: Option<TimestampSweep>,
+}
+
+impl RetimedKeyGenerator {
+    /// Generator re-dating `base`
+    pub fn from_key(base: UnsignedKey) -> Self {
+        let sweep = TimestampSweep::new(&base.primary_key);
+        Self { base, sweep }
+    }
+
+    /// The packet of the re-dated primary key, None unless it's v4
+    pub fn sweep(&self) -> Option<&TimestampSweep> {
+        self.sweep.as_ref()
+    }
+}
+
+impl KeyGenerator for RetimedKeyGenerator {
+    type Key = UnsignedKey;
+
+    fn new(key: &KeyConfig, created_at: DateTime<Utc>) -> Self {
+        Self::from_key(PgpKeyGenerator::new(key, created_at).generate(created_at))
+    }
+
+    fn generate(&mut self, created_at: DateTime<Utc>) -> UnsignedKey {
+        UnsignedKey {
+            primary_key: retime(&self.base.primary_key, created_at),
+            ..self.base.clone()
+        }
+    }
+
+    fn fingerprints(&self, key: &UnsignedKey) -> Vec<Vec<u8>> {
+        vec![key.fingerprint()]
+    }
+
+    fn fingerprints_at(&mut self, created_at: DateTime<Utc>) -> Option<Vec<Vec<u8>>> {
+        let sweep = self.sweep.as_ref()?;
+        Some(vec![sweep
+            .fingerprint(created_at.timestamp() as u32)
+            .to_vec()])
+    }
+
+    fn keypair_id(&self) -> Option<u64> {
+        let fingerprint = self.base.fingerprint();
+        Some(u64::from_be_bytes(fingerprint[..8].try_into().ok()?))
+    }
+}
+
+/// Fresh keypairs each re-dated to the [`KeyConfig::timestamps_per_key`]
+/// creation times of its [`KeyConfig::keypair_counter`], for key types much
+/// slower to generate than to fingerprint, such as RSA.
+///
+/// Only the primary key is re-dated, so its fingerprint is the one matched.
+#[derive(Debug)]
+pub struct SweepKeyGenerator {
+    fresh: PgpKeyGenerator,
+    current: RetimedKeyGenerator,
+    // keypair counter of `current`
+    counter: i64,
+}
+
+impl KeyGenerator for SweepKeyGenerator {
+    type Key = UnsignedKey;
+
+    fn new(key: &KeyConfig, created_at: DateTime<Utc>) -> Self {
+        let mut fresh = PgpKeyGenerator::new(key, created_at);
+        let current = RetimedKeyGenerator::from_key(fresh.generate(created_at));
+        Self {
+            fresh,
+            current,
+            counter: key.keypair_counter(created_at),
+        }
+    }
+
+    fn generate(&mut self, created_at: DateTime<Utc>) -> UnsignedKey {
+        self.roll(created_at);
+        self.current.generate(created_at)
+    }
+
+    fn fingerprints(&self, key: &UnsignedKey) -> Vec<Vec<u8>> {
+        vec![key.fingerprint()]
+    }
+
+    fn fingerprints_at(&mut self, created_at: DateTime<Utc>) -> Option<Vec<Vec<u8>>> {
+        self.roll(created_at);
+        self.current.fingerprints_at(created_at)
+    }
+
+    fn keypair_id(&self) -> Option<u64> {
+        self.fresh.keypair_id()
+    }
+}
+
+impl SweepKeyGenerator {
+    /// Generate the keypair of `created_at` unless it's the current one
+    fn roll(&mut self, created_at: DateTime<Utc>) {
+        let counter = self.fresh.key.keypair_counter(created_at);
+        if counter != self.counter {
+            self.current = RetimedKeyGenerator::from_key(self.fresh.generate(created_at));
+            self.counter = counter;
+        }
+    }
+}
+
+/// `key` with another creation time, the key material is kept. The self
+/// signatures are only made when the key is signed for export, so they
+/// cover the new time.
+fn retime(key: &packet::SecretKey, created_at: DateTime<Utc>) -> packet::SecretKey {
+    let public = public_packet(key, created_at);
+    unsigned::secret_key(&public, key.secret_params()).unwrap() // parts of a valid key
+}
+
+/// Public part of `key` with another creation time
+pub(crate) fn public_packet(
+    key: &packet::SecretKey,
+    created_at: DateTime<Utc>,
+) -> packet::PublicKey {
+    packet::PublicKey::new(
+        key.packet_version(),
+        key.version(),
+        key.algorithm(),
+        created_at,
+        key.expiration(),
+        key.public_params().clone(),
+    )
+    .unwrap() // the parameters come from a valid key
+}
+
+/// Key of the [`MockKeyGenerator`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockKey {
+    pub fingerprint: [u8; 20],
+    pub created_at: DateTime<Utc>,
+}
+
+/// Deterministic fake keys, orders of magnitude faster than real ones, for
+/// testing everything around the key generation.
+///
+/// The n-th key of a generator always has the same pseudo random
+/// fingerprint, derived from `n` and its creation time.
+#[derive(Debug, Default)]
+pub struct MockKeyGenerator {
+    count: u64,
+}
+
+impl KeyGenerator for MockKeyGenerator {
+    type Key = MockKey;
+
+    fn new(_key: &KeyConfig, _created_at: DateTime<Utc>) -> Self {
+        Self::default()
+    }
+
+    fn generate(&mut self, created_at: DateTime<Utc>) -> MockKey {
+        let mut state = self.count ^ (created_at.timestamp() as u64).rotate_left(32);
+        self.count += 1;
+        let mut fingerprint = [0; 20];
+        for chunk in fingerprint.chunks_mut(8) {
+            let bytes = splitmix64(&mut state).to_be_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        MockKey {
+            fingerprint,
+            created_at,
+        }
+    }
+
+    fn fingerprints(&self, key: &MockKey) -> Vec<Vec<u8>> {
+        vec![key.fingerprint.to_vec()]
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_key_type;
+
+    #[test]
+    fn test_fingerprints_at() {
+        let now = Utc::now();
+        for key_type in ["eddsa", "rsa2048"] {
+            let key = KeyConfig {
+                key_type: parse_key_type(key_type).unwrap(),
+                timestamps_per_key: Some(16),
+                ..Default::default()
+            };
+            let mut retimed = RetimedKeyGenerator::new(&key, now);
+            let mut swept = SweepKeyGenerator::new(&key, now);
+            for s in [0, 1, 15, 16, 1000] {
+                let t = now - chrono::Duration::seconds(s);
+                let built = retimed.generate(t);
+                assert_eq!(
+                    retimed.fingerprints_at(t),
+                    Some(retimed.fingerprints(&built))
+                );
+                let built = swept.generate(t);
+                assert_eq!(swept.fingerprints_at(t), Some(swept.fingerprints(&built)));
+            }
+        }
+    }
+}
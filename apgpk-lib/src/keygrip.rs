@@ -0,0 +1,142 @@
+//! GnuPG keygrips, the SHA-1 of the public key parameters which gpg-agent
+//! and smartcard tools name keys by.
+//!
+//! Unlike the fingerprint, the keygrip doesn't cover the creation time, so
+//! re-dating a key doesn't change it.
+
+use pgp::{crypto::ecc_curve::ECCCurve, types::PublicParams};
+use sha1::{Digest, Sha1};
+use std::sync::OnceLock;
+
+/// Length of a keygrip in bytes
+pub const KEYGRIP_LEN: usize = 20;
+
+/// Domain parameters `p`, `a`, `b`, `g` and `n` of a curve as libgcrypt
+/// hashes them, absolute values without leading zeros
+struct Curve {
+    p: &'static str,
+    a: &'static str,
+    b: &'static str,
+    g: &'static str,
+    n: &'static str,
+}
+
+const P25519: &str = "7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFED";
+const N25519: &str = "1000000000000000000000000000000014DEF9DEA2F79CD65812631A5CF5D3ED";
+
+const ED25519: Curve = Curve {
+    p: P25519,
+    a: "01",
+    b: "2DFC9311D490018C7338BF8688861767FF8FF5B2BEBE27548A14B235ECA6874A",
+    g: "04216936D3CD6E53FEC0A4E231FDD6DC5C692CC7609525A7B2C9562D608F25D51A\
+        6666666666666666666666666666666666666666666666666666666666666658",
+    n: N25519,
+};
+
+const CURVE25519: Curve = Curve {
+    p: P25519,
+    a: "01DB41",
+    b: "01",
+    g: "040000000000000000000000000000000000000000000000000000000000000009\
+        20AE19A1B8A086B4E01EDD2C7748D14C923D4D7E6D7C61B229E9C5A27ECED3D9",
+    n: N25519,
+};
+
+/// Keygrip of a public key, `None` for algorithms and curves not covered
+pub fn keygrip(params: &PublicParams) -> Option<[u8; KEYGRIP_LEN]> {
+    match params {
+        PublicParams::RSA { n, .. } => Some(rsa(n.as_bytes())),
+        PublicParams::EdDSA {
+            curve: ECCCurve::Ed25519,
+            q,
+        } => Some(ecc(&ED25519, &ED25519_DOMAIN, q.as_bytes())),
+        PublicParams::ECDH {
+            curve: ECCCurve::Curve25519,
+            p,
+            ..
+        } => Some(ecc(&CURVE25519, &CURVE25519_DOMAIN, p.as_bytes())),
+        _ => None,
+    }
+}
+
+/// SHA-1 of the modulus as an unsigned MPI, a zero byte in front if its
+/// high bit is set
+fn rsa(n: &[u8]) -> [u8; KEYGRIP_LEN] {
+    let mut h = Sha1::new();
+    if n.first().is_some_and(|b| b & 0x80 != 0) {
+        h.update([0]);
+    }
+    h.update(n);
+    h.finalize().into()
+}
+
+/// Hash state after the domain parameters, shared by all keys of a curve
+static ED25519_DOMAIN: OnceLock<Sha1> = OnceLock::new();
+static CURVE25519_DOMAIN: OnceLock<Sha1> = OnceLock::new();
+
+/// SHA-1 of the S-expression `(1:p<len>:<p>)...(1:q<len>:<q>)`, the point
+/// `q` without its OpenPGP `0x40` prefix
+fn ecc(curve: &Curve, domain: &OnceLock<Sha1>, q: &[u8]) -> [u8; KEYGRIP_LEN] {
+    let mut h = domain
+        .get_or_init(|| {
+            let mut h = Sha1::new();
+            let params = [
+                ('p', curve.p),
+                ('a', curve.a),
+                ('b', curve.b),
+                ('g', curve.g),
+                ('n', curve.n),
+            ];
+            for (name, value) in params {
+                sexp(&mut h, name, &hex::decode(value).unwrap()); // constant hex
+            }
+            h
+        })
+        .clone();
+    sexp(&mut h, 'q', q.strip_prefix(&[0x40]).unwrap_or(q));
+    h.finalize().into()
+}
+
+fn sexp(h: &mut Sha1, name: char, value: &[u8]) {
+    h.update(format!("(1:{}{}:", name, value.len()));
+    h.update(value);
+    h.update(")");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_of(b: [u8; KEYGRIP_LEN]) -> String {
+        hex::encode_upper(b)
+    }
+
+    #[test]
+    fn test_keygrip() {
+        // keys generated and listed with `gpg --with-keygrip`
+        let ed = hex::decode("40F5801D5E99BEF14A4A3B21FE4964C3F2B09CFB28FCA4C0C801622AE40F84BECB")
+            .unwrap();
+        assert_eq!(
+            hex_of(ecc(&ED25519, &ED25519_DOMAIN, &ed)),
+            "3E7EBFFB61CF3C852A5F52BFA5CD76DA4E850126"
+        );
+        let cv = hex::decode("40431C473FFAD9C4F30F94C9425CB78A6344751BBFF72F6CEDABAE060CCCA6C11E")
+            .unwrap();
+        assert_eq!(
+            hex_of(ecc(&CURVE25519, &CURVE25519_DOMAIN, &cv)),
+            "A14F8F55083A854C2D153EAD79C711DDDE9D67B2"
+        );
+        let n = hex::decode(concat!(
+            "B42D7C819E414D29B9309B72233EED2CD090CBC9CE21780046BBD2857211B1A5",
+            "5788DCEF2DBD42AE4465450CA8173A45B4D26D69C45CFD20C15F9F1B1E1C9737",
+            "7EC77333D72D8CCE9058352F2C324A4693DC9E2CA1627564B8A4C31465A889C6",
+            "F9FF0A2891923E096B2505C60B903CC63F707EA9250A8C0785CC843887EC9076",
+            "0CA4470B1342E4F310B027DD4BE871DE31C91B9E6BC378CE197EC7BA188A159B",
+            "A073098100EF417FF09CF7A51E21CF4A8E927D5C92B5D09401FB86B573FFD51B",
+            "6C838FB3C66D279818E82BBD31E51FE6BB1A3E891FEC881D1975D1078DC75000",
+            "45F9422819710DED48CE2BAACA9E33E890C4A8FDCE3526E34DA349C080F259AF",
+        ))
+        .unwrap();
+        assert_eq!(hex_of(rsa(&n)), "576BF58248A1A59A270DC9E7D69C2F154ECA3CB2");
+    }
+}
@@ -1,3 +1,48 @@
+//! Search OpenPGP keys whose fingerprint matches patterns.
+//!
+//! # Panics
+//!
+//! Panics are reserved for broken invariants, such as a key config which
+//! passed validation failing to build. Shared state is only ever updated in
+//! one step, so a lock poisoned by a panicking thread is recovered instead of
+//! propagating the panic, and workers stop through a
+//! [`stop::StopSignal`] instead of unwinding each other. Binaries are
+//! expected to join their workers and report a panicked one as
+//! [`stop::StopReason::WorkerFailed`], which needs `panic = "unwind"`.
+
+pub mod clock;
+pub mod collision;
+pub mod config;
 pub mod core;
+pub mod coverage;
+#[cfg(feature = "gpu-cuda")]
+pub mod cuda;
+pub mod detect;
 pub mod error;
+pub mod estimate;
+pub mod filename;
+pub mod finalize;
+pub mod fingerprint;
+pub mod gpu;
+pub mod hook;
+pub mod keygen;
+pub mod keygrip;
+pub mod notation;
+#[cfg(feature = "gpu-opencl")]
+pub mod opencl;
+pub mod output;
+pub mod patterns;
+pub mod pool;
+pub mod prefs;
+pub mod progress;
+pub mod protect;
+pub mod revoker;
+pub mod score;
+pub mod seed;
+pub mod ssh;
+pub mod stop;
+pub mod uid;
+pub mod unsigned;
 pub mod utils;
+pub mod verify;
+pub mod watchdog;
@@ -0,0 +1,167 @@
+//! Notation data in the self signatures, e.g. `vanity@apgpk=pattern:CAFE`,
+//! so keys carry machine-readable notes about how they were made.
+//!
+//! The OpenPGP backend makes the self signatures without notations, so the
+//! user ID certifications are made anew with the same subpackets plus the
+//! notations.
+
+use crate::error::ApgpkError;
+use pgp::{
+    composed::signed_key::SignedSecretKey,
+    packet::{self, SignatureConfig, SignatureVersion, Subpacket, SubpacketData},
+    types::Tag,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Human-readable notation `name=value`, the name in a `@` namespace as
+/// GnuPG requires for names not defined by the IETF
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Notation {
+    pub name: String,
+    pub value: String,
+}
+
+/// `key` with `notations` in the self signature of every user ID, unchanged
+/// without any
+pub fn notate(
+    mut key: SignedSecretKey,
+    notations: &[Notation],
+) -> Result<SignedSecretKey, ApgpkError> {
+    if notations.is_empty() {
+        return Ok(key);
+    }
+    let mut users = std::mem::take(&mut key.details.users);
+    for user in &mut users {
+        let mut signatures = Vec::with_capacity(user.signatures.len());
+        for sig in &user.signatures {
+            let mut hashed = sig.config.hashed_subpackets.clone();
+            hashed.extend(notations.iter().map(|n| {
+                Subpacket::regular(SubpacketData::Notation(packet::Notation {
+                    readable: true,
+                    name: n.name.as_str().into(),
+                    value: n.value.as_str().into(),
+                }))
+            }));
+            let config = SignatureConfig::new_v4(
+                SignatureVersion::V4,
+                sig.config.typ,
+                sig.config.pub_alg,
+                sig.config.hash_alg,
+                hashed,
+                sig.config.unhashed_subpackets.clone(),
+            );
+            signatures.push(config.sign_certificate(&key, String::new, Tag::UserId, &user.id)?);
+        }
+        user.signatures = signatures;
+    }
+    key.details.users = users;
+    Ok(key)
+}
+
+impl FromStr for Notation {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.split_once('=') else {
+            return Err(ApgpkError::Other(format!(
+                "Invalid notation `{}`, expect name@domain=value",
+                s
+            )));
+        };
+        let name = name.trim();
+        let namespaced = match name.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+            }
+            None => false,
+        };
+        if !namespaced || name.contains(|c: char| c.is_whitespace() || c.is_control()) {
+            return Err(ApgpkError::Other(format!(
+                "Notation name `{}` needs a `@` namespace, e.g. vanity@example.org",
+                name
+            )));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(ApgpkError::Other(format!(
+                "Notation value of `{}` must not contain control characters",
+                name
+            )));
+        }
+        Ok(Notation {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl TryFrom<String> for Notation {
+    type Error = ApgpkError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Notation> for String {
+    fn from(notation: Notation) -> Self {
+        notation.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::KeyConfig,
+        keygen::{KeyGenerator, PgpKeyGenerator},
+    };
+    use chrono::Utc;
+    use pgp::Deserializable;
+
+    #[test]
+    fn test_notate() {
+        let notation: Notation = "vanity@apgpk=pattern:CAFE".parse().unwrap();
+        assert_eq!(notation.name, "vanity@apgpk");
+        assert_eq!(notation.value, "pattern:CAFE");
+        assert_eq!(notation.to_string(), "vanity@apgpk=pattern:CAFE");
+        assert!("vanity=CAFE".parse::<Notation>().is_err());
+        assert!("@apgpk=CAFE".parse::<Notation>().is_err());
+        assert!("vanity@apgpk".parse::<Notation>().is_err());
+
+        let key = KeyConfig {
+            user_ids: vec!["Me <me@example.org>".to_string()],
+            ..KeyConfig::new("Me <me@example.com>".to_string())
+        };
+        let now = Utc::now();
+        let signed = PgpKeyGenerator::new(&key, now)
+            .generate(now)
+            .sign()
+            .unwrap();
+        let armored = notate(signed, &[notation])
+            .unwrap()
+            .to_armored_string(None)
+            .unwrap();
+        let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+        read.verify().unwrap();
+        for user in &read.details.users {
+            let notes: Vec<_> = user.signatures[0]
+                .config
+                .hashed_subpackets
+                .iter()
+                .filter_map(|p| match &p.data {
+                    SubpacketData::Notation(n) => Some((n.name.to_string(), n.value.to_string())),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(notes, [("vanity@apgpk".into(), "pattern:CAFE".into())]);
+        }
+    }
+}
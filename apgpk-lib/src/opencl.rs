@@ -0,0 +1,141 @@
+//! [`SweepDevice`] on OpenCL, behind the `gpu-opencl` feature. The kernel
+//! is in `sweep.cl`.
+
+use crate::{
+    core::FpMask,
+    error::ApgpkError,
+    fingerprint::TimestampSweep,
+    gpu::{mask_words, SweepDevice},
+};
+use ocl::{Buffer, Device, Platform, ProQue};
+
+const KERNEL: &str = include_str!("sweep.cl");
+
+/// Most hits read back per call, more mean the masks are far too loose
+const MAX_HITS: u32 = 1 << 16;
+
+/// One OpenCL device with the masks uploaded
+#[derive(Debug)]
+pub struct OpenClDevice {
+    pro_que: ProQue,
+    name: String,
+    masks: Buffer<u32>,
+    mask_count: u32,
+    hits: Buffer<u32>,
+    hit_count: Buffer<u32>,
+}
+
+impl OpenClDevice {
+    /// Device `index` of [`devices`] filtering with `masks`
+    pub fn new(index: usize, masks: &[FpMask]) -> Result<Self, ApgpkError> {
+        let (platform, device) = all_devices()?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| ApgpkError::Other(format!("There's no OpenCL device #{}", index)))?;
+        Self::build(platform, device, masks).map_err(error)
+    }
+
+    fn build(platform: Platform, device: Device, masks: &[FpMask]) -> ocl::Result<Self> {
+        let pro_que = ProQue::builder()
+            .platform(platform)
+            .device(device)
+            .src(KERNEL)
+            .dims(1)
+            .build()?;
+        let words = mask_words(masks);
+        Ok(Self {
+            name: device.name()?,
+            masks: pro_que
+                .buffer_builder()
+                .len(words.len())
+                .copy_host_slice(&words)
+                .build()?,
+            mask_count: masks.len() as u32,
+            hits: pro_que.buffer_builder().len(MAX_HITS as usize).build()?,
+            hit_count: pro_que.buffer_builder().len(1).fill_val(0).build()?,
+            pro_que,
+        })
+    }
+
+    fn run(&mut self, sweep: &TimestampSweep, first: u32, count: u32) -> ocl::Result<Vec<u32>> {
+        let words: Vec<u32> = sweep.blocks().iter().flatten().copied().collect();
+        let blocks = self
+            .pro_que
+            .buffer_builder()
+            .len(words.len())
+            .copy_host_slice(&words)
+            .build()?;
+        self.hit_count.write(&[0u32][..]).enq()?;
+        let kernel = self
+            .pro_que
+            .kernel_builder("sweep")
+            .global_work_size(count as usize)
+            .arg(&blocks)
+            .arg(sweep.blocks().len() as u32)
+            .arg(first)
+            .arg(count)
+            .arg(&self.masks)
+            .arg(self.mask_count)
+            .arg(&self.hits)
+            .arg(&self.hit_count)
+            .arg(MAX_HITS)
+            .build()?;
+        // SAFETY: the kernel writes `hits` and `hit_count` only, within
+        // `max_hits`
+        unsafe { kernel.enq()? };
+
+        let mut hit_count = [0u32];
+        self.hit_count.read(&mut hit_count[..]).enq()?;
+        if hit_count[0] > MAX_HITS {
+            log::warn!(
+                "{} creation times passed the masks of the GPU, only {} are checked",
+                hit_count[0],
+                MAX_HITS
+            );
+        }
+        let len = hit_count[0].min(MAX_HITS) as usize;
+        let mut hits = vec![0; len];
+        if len > 0 {
+            self.hits.read(&mut hits).len(len).enq()?;
+        }
+        Ok(hits)
+    }
+}
+
+impl SweepDevice for OpenClDevice {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn sweep(
+        &mut self,
+        sweep: &TimestampSweep,
+        first: u32,
+        count: u32,
+    ) -> Result<Vec<u32>, ApgpkError> {
+        self.run(sweep, first, count).map_err(error)
+    }
+}
+
+/// Names of the OpenCL devices of all platforms, in the order
+/// [`OpenClDevice::new`] numbers them
+pub fn devices() -> Result<Vec<String>, ApgpkError> {
+    all_devices()?
+        .into_iter()
+        .map(|(_, device)| device.name().map_err(error))
+        .collect()
+}
+
+fn all_devices() -> Result<Vec<(Platform, Device)>, ApgpkError> {
+    let mut all = vec![];
+    for platform in Platform::list() {
+        for device in Device::list_all(platform).map_err(error)? {
+            all.push((platform, device));
+        }
+    }
+    Ok(all)
+}
+
+fn error(e: ocl::Error) -> ApgpkError {
+    ApgpkError::Other(format!("OpenCL: {}", e))
+}
@@ -0,0 +1,298 @@
+use crate::{
+    error::ApgpkError,
+    filename::{self, NamePolicy},
+    notation::Notation,
+    revoker::Revoker,
+    unsigned::UnsignedKey,
+    utils,
+};
+use pgp::{
+    composed::{message::Message, signed_key::SignedSecretKey},
+    crypto::hash::HashAlgorithm,
+    Deserializable,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Version of the serialized result and config types, bumped on every
+/// incompatible change of their fields
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Metadata of a saved key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FoundKey {
+    pub schema_version: u32,
+    /// Upper case hex fingerprint
+    pub fingerprint: String,
+    /// Last 16 hex digits of the fingerprint
+    pub key_id: String,
+    /// Armored secret key file
+    pub path: PathBuf,
+    /// Digit substitutions off the fuzzy pattern the key matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance: Option<usize>,
+    /// Long key ID of the existing key this collision demo key shares, see
+    /// [`crate::collision`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collision_of: Option<String>,
+    /// Unix time the key expires at, it never does if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// Key type of the primary key, e.g. `eddsa` or `rsa3072`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    /// Why the saved key failed [`utils::self_check`], it passed if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_check_error: Option<String>,
+}
+
+impl FoundKey {
+    pub fn new(fingerprint: String, path: PathBuf) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            key_id: fingerprint[fingerprint.len() - 16..].to_string(),
+            fingerprint,
+            path,
+            distance: None,
+            collision_of: None,
+            expires_at: None,
+            algorithm: None,
+            self_check_error: None,
+        }
+    }
+}
+
+/// Output directory enforcing a quota on the number and size of saved keys.
+///
+/// Once a limit is hit further keys are refused, or with `rotate` saved into
+/// a fresh sibling directory `<dir>.1`, `<dir>.2`, ... with its own quota.
+#[derive(Debug)]
+pub struct OutputDir {
+    base: PathBuf,
+    current: PathBuf,
+    rotation: usize,
+    keys: usize,
+    bytes: u64,
+    max_keys: Option<usize>,
+    max_bytes: Option<u64>,
+    rotate: bool,
+    campaign_key: Option<SignedSecretKey>,
+    names: NamePolicy,
+    collision_of: Option<String>,
+    save: utils::SaveOptions,
+}
+
+impl OutputDir {
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        max_keys: Option<usize>,
+        max_bytes: Option<u64>,
+        rotate: bool,
+    ) -> Self {
+        let base = dir.into();
+        Self {
+            current: base.clone(),
+            base,
+            rotation: 0,
+            keys: 0,
+            bytes: 0,
+            max_keys,
+            max_bytes,
+            rotate,
+            campaign_key: None,
+            names: NamePolicy::default(),
+            collision_of: None,
+            save: utils::SaveOptions::default(),
+        }
+    }
+
+    /// Protect every saved key with `passphrase`
+    pub fn set_passphrase(&mut self, passphrase: String) {
+        self.save.passphrase = Some(passphrase);
+    }
+
+    /// Which exports of every key are written
+    pub fn set_export(&mut self, export: utils::Export) {
+        self.save.export = export;
+    }
+
+    /// Add `notations` to the self signatures of every saved key
+    pub fn set_notations(&mut self, notations: Vec<Notation>) {
+        self.save.notations = notations;
+    }
+
+    /// Designate `revokers` in every saved key
+    pub fn set_revokers(&mut self, revokers: Vec<Revoker>) {
+        self.save.revokers = revokers;
+    }
+
+    /// Save a revocation certificate `<FINGERPRINT>.rev` next to every key
+    pub fn set_revocation_cert(&mut self, enabled: bool) {
+        self.save.revocation_cert = enabled;
+    }
+
+    /// Sign the metadata of every saved key with `key`, see [`sign_metadata`]
+    pub fn set_campaign_key(&mut self, key: SignedSecretKey) {
+        self.campaign_key = Some(key);
+    }
+
+    /// Mark every saved key as a demo key colliding with the key ID
+    pub fn set_collision_of(&mut self, key_id: String) {
+        self.collision_of = Some(key_id);
+    }
+
+    /// How subdirectory names, which may come from pattern labels, are made
+    /// safe
+    pub fn set_name_policy(&mut self, names: NamePolicy) {
+        self.names = names;
+    }
+
+    /// Directory the next key is saved into
+    pub fn path(&self) -> &Path {
+        &self.current
+    }
+
+    /// Save the key, into `subdir` of the output directory if given, or
+    /// return `None` if the quota is exhausted and rotation is disabled.
+    pub fn save(
+        &mut self,
+        k: &UnsignedKey,
+        subdir: Option<&str>,
+    ) -> Result<Option<FoundKey>, ApgpkError> {
+        self.save_match(k, subdir, None)
+    }
+
+    /// [`OutputDir::save`] recording the distance of a fuzzy match in the
+    /// metadata
+    pub fn save_match(
+        &mut self,
+        k: &UnsignedKey,
+        subdir: Option<&str>,
+        distance: Option<usize>,
+    ) -> Result<Option<FoundKey>, ApgpkError> {
+        if !self.admit()? {
+            return Ok(None);
+        }
+        let dir = match subdir {
+            Some(sub) => {
+                let dir = self.current.join(self.names.sanitize(sub));
+                fs::create_dir_all(&dir)?;
+                dir
+            }
+            None => self.current.clone(),
+        };
+        let fp = utils::save_key_with(k, &dir, &self.save)?;
+        let path = dir.join(filename::key_file_name(&fp));
+        let mut bytes = fs::metadata(&path)?.len();
+        if self.save.revocation_cert {
+            bytes += fs::metadata(dir.join(filename::revocation_file_name(&fp)))?.len();
+        }
+        if self.save.export == utils::Export::Both {
+            bytes += fs::metadata(dir.join(filename::minimal_file_name(&fp)))?.len();
+        }
+        let found = FoundKey {
+            distance,
+            collision_of: self.collision_of.clone(),
+            expires_at: k
+                .expiration_days
+                .map(|days| k.primary_key.created_at().timestamp() + days as i64 * 24 * 60 * 60),
+            algorithm: utils::algorithm(k),
+            self_check_error: utils::self_check(
+                &fs::read_to_string(&path)?,
+                self.save.passphrase.as_deref(),
+            )
+            .err()
+            .map(|e| e.to_string()),
+            ..FoundKey::new(fp, path)
+        };
+        if let Some(key) = &self.campaign_key {
+            let signed = dir.join(filename::metadata_file_name(&found.fingerprint));
+            fs::write(&signed, sign_metadata(&found, key)?)?;
+            bytes += fs::metadata(&signed)?.len();
+        }
+        self.record(bytes);
+        Ok(Some(found))
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_keys.is_some_and(|n| self.keys >= n)
+            || self.max_bytes.is_some_and(|n| self.bytes >= n)
+    }
+
+    /// Whether one more key can be saved, rotating to a new directory if needed
+    fn admit(&mut self) -> Result<bool, ApgpkError> {
+        if !self.is_full() {
+            return Ok(true);
+        }
+        if !self.rotate {
+            return Ok(false);
+        }
+        self.rotation += 1;
+        let mut name = self.base.clone().into_os_string();
+        name.push(format!(".{}", self.rotation));
+        self.current = PathBuf::from(name);
+        utils::check_output_dir(&self.current)?;
+        log::warn!(
+            "Output quota reached, rotating to `{}`",
+            self.current.display()
+        );
+        self.keys = 0;
+        self.bytes = 0;
+        Ok(true)
+    }
+
+    fn record(&mut self, bytes: u64) {
+        self.keys += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Load an unprotected armored secret key signing the results of a campaign
+pub fn load_campaign_key(path: impl AsRef<Path>) -> Result<SignedSecretKey, ApgpkError> {
+    let (key, _) = SignedSecretKey::from_armor_single(fs::File::open(path)?)?;
+    key.verify()?;
+    Ok(key)
+}
+
+/// The metadata of a found key as JSON inside an armored message signed by
+/// the campaign key, so results copied from other machines can be checked
+/// with `gpg --verify` against the campaign's public key.
+pub fn sign_metadata(found: &FoundKey, key: &SignedSecretKey) -> Result<String, ApgpkError> {
+    let json = serde_json::to_string_pretty(found)
+        .map_err(|e| ApgpkError::Other(format!("Failed to serialize metadata: {}", e)))?;
+    let name = format!("{}.json", found.fingerprint);
+    let signed = Message::new_literal(name.as_str(), &json).sign(
+        key,
+        String::new,
+        HashAlgorithm::SHA2_256,
+    )?;
+    Ok(signed.to_armored_string(None)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota() {
+        let base = std::env::temp_dir().join(format!("apgpk-quota-{}", std::process::id()));
+        let mut full = OutputDir::new(&base, Some(1), None, false);
+        assert!(full.admit().unwrap());
+        full.record(100);
+        assert!(!full.admit().unwrap());
+
+        let mut rotating = OutputDir::new(&base, None, Some(150), true);
+        assert!(rotating.admit().unwrap());
+        rotating.record(100);
+        assert!(rotating.admit().unwrap());
+        rotating.record(100);
+        assert!(rotating.admit().unwrap());
+        let rotated = rotating.path().to_path_buf();
+        assert!(rotated.to_string_lossy().ends_with(".1"));
+        assert!(rotated.is_dir());
+        fs::remove_dir(rotated).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+//! Generate patterns from plain words.
+
+pub mod expr;
+pub mod template;
+
+/// Hex look-alikes of letters which are not hex digits themselves
+pub const LEET_TABLE: [(char, char); 7] = [
+    ('O', '0'),
+    ('I', '1'),
+    ('L', '1'),
+    ('Z', '2'),
+    ('S', '5'),
+    ('G', '6'),
+    ('T', '7'),
+];
+
+/// Convert a word to its hex look-alike, e.g. `coffee` to `C0FFEE`.
+///
+/// Returns `None` if the word contains a letter without a look-alike.
+pub fn leet(word: &str) -> Option<String> {
+    word.trim()
+        .chars()
+        .map(|c| {
+            let c = c.to_ascii_uppercase();
+            if c.is_ascii_hexdigit() {
+                return Some(c);
+            }
+            LEET_TABLE.iter().find(|(l, _)| *l == c).map(|(_, h)| *h)
+        })
+        .collect()
+}
+
+/// Convert every feasible word of the list, dropping duplicates and words
+/// shorter than `min_len` characters.
+pub fn leet_words<I, S>(words: I, min_len: usize) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut pattern: Vec<String> = vec![];
+    for word in words {
+        match leet(word.as_ref()) {
+            Some(p) if p.len() >= min_len && !pattern.contains(&p) => pattern.push(p),
+            _ => {}
+        }
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leet_table() {
+        assert_eq!(leet("o").as_deref(), Some("0"));
+        assert_eq!(leet("i").as_deref(), Some("1"));
+        assert_eq!(leet("l").as_deref(), Some("1"));
+        assert_eq!(leet("z").as_deref(), Some("2"));
+        assert_eq!(leet("s").as_deref(), Some("5"));
+        assert_eq!(leet("g").as_deref(), Some("6"));
+        assert_eq!(leet("t").as_deref(), Some("7"));
+        for c in "ABCDEF0123456789".chars() {
+            assert_eq!(leet(&c.to_string()), Some(c.to_string()));
+        }
+        for c in "HJKMNPQRUVWXY".chars() {
+            assert_eq!(leet(&c.to_string()), None);
+        }
+    }
+
+    #[test]
+    fn test_leet_words() {
+        assert_eq!(leet("coffee").as_deref(), Some("C0FFEE"));
+        assert_eq!(leet("Goddess").as_deref(), Some("60DDE55"));
+        assert_eq!(leet("hello"), None);
+        assert_eq!(
+            leet_words(["coffee", "hello", "bad", "COFFEE", "tattoo"], 4),
+            vec!["C0FFEE".to_string(), "7A7700".to_string()]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Boolean expressions over literal patterns, e.g.
+//! `(ends CAFE OR ends BABE) AND NOT contains 000000`.
+//!
+//! `NOT` binds tighter than `AND`, which binds tighter than `OR`, and
+//! keywords are case-insensitive. An operand is a hex literal, optionally
+//! preceded by where it is anchored: `starts`, `ends`, `contains` or `both`.
+//! The mode prefixes of pattern lines work too, e.g. `anywhere:CAFE`.
+
+use crate::{
+    core::{MatchMode, Pattern},
+    error::ApgpkError,
+    utils::PatternOptions,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Word(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Word(w) => write!(f, "`{}`", w),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    for c in text.chars() {
+        if c != '(' && c != ')' && !c.is_whitespace() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(Token::Word(std::mem::take(&mut word)));
+        }
+        match c {
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            _ => {}
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+/// Parse an expression into a tree of [`Pattern::All`], [`Pattern::Any`]
+/// and [`Pattern::Not`], whose literals are built with `opts`.
+pub fn parse(text: &str, opts: &PatternOptions) -> Result<Pattern, ApgpkError> {
+    let mut parser = Parser {
+        tokens: tokenize(text),
+        pos: 0,
+        opts,
+    };
+    let pattern = parser.or().and_then(|p| match parser.next() {
+        None => Ok(p),
+        Some(t) => Err(format!("unexpected {}", t)),
+    });
+    pattern.map_err(|e| ApgpkError::Other(format!("Invalid expression `{}`: {}", text.trim(), e)))
+}
+
+/// Recursive descent parser, one method per precedence level
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    opts: &'a PatternOptions,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the keyword if it comes next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.tokens.get(self.pos),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Pattern, String> {
+        let mut parts = vec![self.and()?];
+        while self.keyword("or") {
+            parts.push(self.and()?);
+        }
+        Ok(match parts.len() {
+            1 => parts.remove(0),
+            _ => Pattern::Any(parts),
+        })
+    }
+
+    fn and(&mut self) -> Result<Pattern, String> {
+        let mut parts = vec![self.unary()?];
+        while self.keyword("and") {
+            parts.push(self.unary()?);
+        }
+        Ok(match parts.len() {
+            1 => parts.remove(0),
+            _ => Pattern::All(parts),
+        })
+    }
+
+    fn unary(&mut self) -> Result<Pattern, String> {
+        if self.keyword("not") {
+            return Ok(Pattern::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::LParen) => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err("missing `)`".to_string()),
+                }
+            }
+            Some(Token::Word(w)) => self.operand(w),
+            Some(t) => Err(format!("unexpected {}", t)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn operand(&mut self, word: String) -> Result<Pattern, String> {
+        let anchor = match word.to_lowercase().as_str() {
+            "starts" => Some(MatchMode::Prefix),
+            "ends" => Some(MatchMode::Suffix),
+            "contains" => Some(MatchMode::Anywhere),
+            "both" => Some(MatchMode::Both),
+            _ => None,
+        };
+        let (text, mode) = match anchor {
+            Some(mode) => match self.next() {
+                Some(Token::Word(text)) => (text, mode),
+                _ => return Err(format!("expected a literal after `{}`", word)),
+            },
+            None => match word.split_once(':') {
+                Some((m, text)) => (
+                    text.to_string(),
+                    m.parse::<MatchMode>().map_err(|e| e.to_string())?,
+                ),
+                None => (word, self.opts.mode),
+            },
+        };
+        let text = text.to_uppercase();
+        if text.is_empty()
+            || text.len() > self.opts.target.hex_len()
+            || !text.chars().all(|c| c.is_ascii_hexdigit() || c == '?')
+        {
+            return Err(format!("invalid literal `{}`", text));
+        }
+        Ok(self.opts.literal(&text, mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let opts = PatternOptions::default();
+        let p = parse("(ends CAFE OR ends BABE) and not contains 000000", &opts).unwrap();
+        assert_eq!(p.to_string(), "(*CAFE | *BABE) & not *000000*");
+
+        let fp = |s| hex::decode(s).unwrap();
+        assert!(p.is_match(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4CAFE")));
+        assert!(p.is_match(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BABE")));
+        assert!(!p.is_match(&fp("DEAD5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B4BEEF")));
+        assert!(!p.is_match(&fp("DEAD5C3A9B1E0F7D2400000035F1D9C7A2B4CAFE")));
+
+        // AND binds tighter than OR
+        let p = parse("starts AAAA or ends BBBB and ends ?BBB", &opts).unwrap();
+        assert_eq!(p.to_string(), "(AAAA* | *BBBB & *?BBB)");
+        assert_eq!(
+            parse("prefix:AAAA AND CCCC", &opts).unwrap().to_string(),
+            "AAAA* & *CCCC"
+        );
+
+        for invalid in [
+            "",
+            "(ends CAFE",
+            "ends",
+            "ends CAFE OR",
+            "contains HELLO",
+            "CAFE )",
+        ] {
+            assert!(parse(invalid, &opts).is_err(), "{}", invalid);
+        }
+    }
+}
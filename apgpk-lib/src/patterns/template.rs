@@ -0,0 +1,78 @@
+//! Placeholders in pattern lines, expanded once at startup, e.g.
+//! `CAFE{YEAR}` to `CAFE2024`.
+//!
+//! A placeholder is a name in upper case letters between braces, so regex
+//! repetitions such as `A{4}` are left alone.
+
+use crate::error::ApgpkError;
+use chrono::{Datelike, NaiveDate};
+
+/// Names of the placeholders and what they expand to
+pub const PLACEHOLDERS: [(&str, &str); 5] = [
+    ("YEAR", "current year, e.g. 2024"),
+    ("YY", "last two digits of the year, e.g. 24"),
+    ("MONTH", "month as two digits, e.g. 06"),
+    ("DAY", "day of the month as two digits, e.g. 01"),
+    ("DATE", "date as YYYYMMDD, e.g. 20240601"),
+];
+
+/// `text` with every placeholder replaced according to `today`
+pub fn expand(text: &str, today: NaiveDate) -> Result<String, ApgpkError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let name = after
+            .find('}')
+            .map(|close| &after[..close])
+            .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_uppercase()));
+        let Some(name) = name else {
+            out.push('{');
+            rest = after;
+            continue;
+        };
+        out.push_str(&value(name, today).ok_or_else(|| {
+            ApgpkError::Other(format!(
+                "Unknown placeholder `{{{}}}` in `{}`, expect one of {}",
+                name,
+                text,
+                PLACEHOLDERS
+                    .iter()
+                    .map(|(n, _)| format!("{{{}}}", n))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })?);
+        rest = &after[name.len() + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn value(name: &str, today: NaiveDate) -> Option<String> {
+    Some(match name {
+        "YEAR" => format!("{:04}", today.year()),
+        "YY" => format!("{:02}", today.year() % 100),
+        "MONTH" => format!("{:02}", today.month()),
+        "DAY" => format!("{:02}", today.day()),
+        "DATE" => today.format("%Y%m%d").to_string(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        assert_eq!(expand("CAFE{YEAR}", today).unwrap(), "CAFE2024");
+        assert_eq!(expand("{DATE}", today).unwrap(), "20240601");
+        assert_eq!(expand("{YY}{MONTH}{DAY}", today).unwrap(), "240601");
+        assert_eq!(expand("re:A{4}", today).unwrap(), "re:A{4}");
+        assert_eq!(expand("{ {YEAR", today).unwrap(), "{ {YEAR");
+        assert!(expand("CAFE{WEEK}", today).is_err());
+    }
+}
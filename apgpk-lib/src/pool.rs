@@ -0,0 +1,437 @@
+//! Worker threads of a search, started, watched and joined in one place so
+//! a front end only says what a worker does.
+//!
+//! Every worker is handed a [`Worker`] with its own sender of the results,
+//! and [`Worker::chunk`] narrows the search to its share of the patterns
+//! and of the backshift window. The pool keeps a sender for itself only as
+//! long as stalled workers may have to be replaced, so the receiver sees
+//! the channel disconnect once every worker is done.
+//!
+//! A replaced worker is cancelled through its own stop signal and loses its
+//! heartbeat slot, so if it ever wakes up it returns, dropping its sender,
+//! rather than carrying on beside its replacement. The replacement keeps
+//! the [`Coverage`] of the worker, so it skips the creation times already
+//! swept with the same keypair.
+//!
+//! How every worker returned is recorded as it returns, see [`Done`], so a
+//! worker out of work is told from a failed one without joining them.
+
+use crate::{
+    config::SearchConfig,
+    coverage::Coverage,
+    error::ApgpkError,
+    stop::{panic_message, StopReason, StopSignal},
+    watchdog::{Heartbeat, Watchdog},
+};
+use std::{
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc::Sender, Arc, OnceLock},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Settings of a [`WorkerPool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub threads: usize,
+    /// How long a worker may go without a heartbeat before it is reported as
+    /// stalled, None disables the watchdog
+    pub watchdog_timeout: Option<Duration>,
+    /// Replace stalled workers with fresh ones
+    pub restart_stalled: bool,
+}
+
+impl PoolConfig {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            watchdog_timeout: None,
+            restart_stalled: false,
+        }
+    }
+}
+
+/// What a worker of a [`WorkerPool`] is handed
+#[derive(Debug)]
+pub struct Worker<M> {
+    /// Index of the worker, kept by its replacements
+    pub index: usize,
+    /// Number of workers of the pool
+    pub count: usize,
+    /// Child of the stop signal of the whole search, also stopped alone
+    /// once the worker is replaced
+    pub exit: Arc<StopSignal>,
+    pub tx: Sender<M>,
+    pub heartbeat: Heartbeat,
+    /// Creation times swept by the worker and the ones it replaced
+    pub coverage: Arc<Coverage>,
+}
+
+impl<M> Worker<M> {
+    /// `search` narrowed to this worker: shard `index` of the patterns and
+    /// slice `index` of the backshift window, for a search set up to be
+    /// sharded or sliced, and the coverage of the worker
+    pub fn chunk(&self, search: &SearchConfig) -> SearchConfig {
+        let mut search = search.clone();
+        search.coverage = Some(self.coverage.clone());
+        if search.shard.is_some() {
+            search.shard = Some((self.index, self.count));
+        }
+        if let Some(slice) = &mut search.time_slice {
+            slice.index = self.index;
+            slice.count = self.count;
+        }
+        search
+    }
+}
+
+/// How a worker of a [`WorkerPool`] returns when it doesn't fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Done {
+    /// Stopped through its stop signal
+    Stopped,
+    /// Out of work before being stopped, e.g. done with its slice of a
+    /// seeded search
+    Exhausted,
+}
+
+impl From<()> for Done {
+    fn from(_: ()) -> Self {
+        Done::Stopped
+    }
+}
+
+type Work<M, E> = Arc<dyn Fn(Worker<M>) -> Result<Done, E> + Send + Sync>;
+
+/// Fixed number of workers running the same closure, each with its own
+/// [`Worker`]
+pub struct WorkerPool<M, E> {
+    config: PoolConfig,
+    work: Work<M, E>,
+    stop: Arc<StopSignal>,
+    watchdog: Watchdog,
+    // handed to replacements, only kept if there may be some
+    tx: Option<Sender<M>>,
+    workers: Vec<Slot<E>>,
+    /// kept by the replacements of the workers
+    coverage: Vec<Arc<Coverage>>,
+    /// Replaced workers, never joined as they may never return
+    abandoned: Vec<JoinHandle<Result<Done, E>>>,
+}
+
+struct Slot<E> {
+    handle: JoinHandle<Result<Done, E>>,
+    exit: Arc<StopSignal>,
+    /// Set as the worker returns, None if it failed or panicked
+    done: Arc<OnceLock<Option<Done>>>,
+}
+
+impl<M: Send + 'static, E: Display + Send + 'static> WorkerPool<M, E> {
+    /// Start the workers running `work`, which should return once `stop` is
+    /// stopped unless it runs out of work, see [`Done`]. A worker failing or
+    /// panicking stops it with [`StopReason::WorkerFailed`].
+    pub fn start<F, T>(
+        config: PoolConfig,
+        stop: Arc<StopSignal>,
+        tx: Sender<M>,
+        work: F,
+    ) -> Result<Self, ApgpkError>
+    where
+        F: Fn(Worker<M>) -> Result<T, E> + Send + Sync + 'static,
+        T: Into<Done>,
+    {
+        let mut pool = Self {
+            config,
+            work: Arc::new(move |worker| work(worker).map(Into::into)),
+            stop,
+            watchdog: Watchdog::new(
+                config.threads,
+                config.watchdog_timeout.unwrap_or(Duration::MAX),
+            ),
+            tx: Some(tx),
+            workers: vec![],
+            coverage: (0..config.threads).map(|_| Arc::default()).collect(),
+            abandoned: vec![],
+        };
+        for i in 0..config.threads {
+            let worker = pool.spawn(i, pool.watchdog.heartbeat(i))?;
+            pool.workers.push(worker);
+        }
+        if !(config.restart_stalled && config.watchdog_timeout.is_some()) {
+            pool.tx = None;
+        }
+        Ok(pool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Whether worker `i` has returned
+    pub fn is_finished(&self, i: usize) -> bool {
+        self.workers[i].handle.is_finished()
+    }
+
+    /// Number of workers which have returned
+    pub fn finished(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|w| w.handle.is_finished())
+            .count()
+    }
+
+    pub fn all_finished(&self) -> bool {
+        self.workers.iter().all(|w| w.handle.is_finished())
+    }
+
+    /// Number of workers which returned [`Done::Exhausted`]
+    pub fn exhausted(&self) -> usize {
+        self.count_done(|done| done == Some(Done::Exhausted))
+    }
+
+    /// Number of workers which failed or panicked, known before they are
+    /// joined
+    pub fn failed(&self) -> usize {
+        self.count_done(|done| done.is_none())
+    }
+
+    fn count_done(&self, f: impl Fn(Option<Done>) -> bool) -> usize {
+        self.workers
+            .iter()
+            .filter(|w| w.done.get().is_some_and(|&done| f(done)))
+            .count()
+    }
+
+    /// Candidates skipped by the workers as already swept with the same
+    /// keypair, see [`Coverage`]
+    pub fn skipped(&self) -> u64 {
+        self.coverage.iter().map(|c| c.skipped()).sum()
+    }
+
+    /// Number of replaced workers which haven't returned yet
+    pub fn abandoned(&self) -> usize {
+        self.abandoned.iter().filter(|h| !h.is_finished()).count()
+    }
+
+    /// Report the workers which stopped beating, and replace them with
+    /// fresh ones if the pool is set to. Call it every second or so.
+    pub fn check(&mut self) -> Result<(), ApgpkError> {
+        if self.config.watchdog_timeout.is_none() {
+            return Ok(());
+        }
+        for (i, silent) in self.watchdog.check() {
+            // done, e.g. with its slice of a seeded search
+            if self.is_finished(i) {
+                continue;
+            }
+            log::error!(
+                "Thread {} hasn't reported progress for {}s, it may be stuck",
+                i,
+                silent.as_secs()
+            );
+            if self.tx.is_some() && !self.stop.is_stopped() {
+                self.restart(i)?;
+                log::warn!(
+                    "Restarted thread {}, {} stuck threads abandoned so far",
+                    i,
+                    self.abandoned()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace worker `i` with a new one with its own heartbeat. The old
+    /// thread is cancelled and abandoned rather than joined.
+    fn restart(&mut self, i: usize) -> Result<(), ApgpkError> {
+        let heartbeat = self.watchdog.replace(i);
+        let replacement = self.spawn(i, heartbeat)?;
+        let old = std::mem::replace(&mut self.workers[i], replacement);
+        old.exit.cancel();
+        self.abandoned.push(old.handle);
+        Ok(())
+    }
+
+    /// Wait for every worker, stopping the search if one failed or
+    /// panicked. Returns the number of abandoned workers still running.
+    pub fn join(mut self) -> usize {
+        self.tx = None;
+        for (i, w) in self.workers.into_iter().enumerate() {
+            let failed = match w.handle.join() {
+                Ok(Ok(_)) => false,
+                Ok(Err(e)) => {
+                    log::error!("Worker thread {} failed: {}", i, e);
+                    true
+                }
+                Err(panic) => {
+                    log::error!(
+                        "Worker thread {} panicked: {}",
+                        i,
+                        panic_message(panic.as_ref())
+                    );
+                    true
+                }
+            };
+            if failed {
+                self.stop.stop(StopReason::WorkerFailed);
+            }
+        }
+        self.abandoned.iter().filter(|h| !h.is_finished()).count()
+    }
+
+    fn spawn(&self, i: usize, heartbeat: Heartbeat) -> Result<Slot<E>, ApgpkError> {
+        let tx = self.tx.clone().expect("kept while workers may be spawned");
+        let exit = Arc::new(StopSignal::child(&self.stop));
+        let worker = Worker {
+            index: i,
+            count: self.config.threads,
+            exit: exit.clone(),
+            tx,
+            heartbeat,
+            coverage: self.coverage[i].clone(),
+        };
+        let work = self.work.clone();
+        let done = Arc::new(OnceLock::new());
+        let set_done = done.clone();
+        let handle = thread::Builder::new()
+            .name(format!("apgpk-worker-{}", i))
+            .spawn(move || {
+                log::debug!("Thread {} has been created", i);
+                let result = panic::catch_unwind(AssertUnwindSafe(|| work(worker)));
+                let _ = set_done.set(match &result {
+                    Ok(Ok(done)) => Some(*done),
+                    _ => None,
+                });
+                // a panic is still reported by `join`
+                result.unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })?;
+        Ok(Slot { handle, exit, done })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{KeyConfig, TimeSlice};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_pool() {
+        let stop = Arc::new(StopSignal::new());
+        let (tx, rx) = mpsc::channel();
+        let config = PoolConfig {
+            watchdog_timeout: Some(Duration::ZERO),
+            restart_stalled: true,
+            ..PoolConfig::new(3)
+        };
+        let mut pool = WorkerPool::start(config, stop.clone(), tx, |w: Worker<usize>| {
+            w.tx.send(w.index).unwrap();
+            while w.index == 0 && !w.exit.is_stopped() {
+                thread::yield_now();
+            }
+            match w.index {
+                1 => Err("bad key"),
+                2 => panic!("worker bug"),
+                _ => Ok(()),
+            }
+        })
+        .unwrap();
+        while pool.failed() < 2 {
+            thread::yield_now();
+        }
+        assert_eq!(pool.exhausted(), 0);
+        // worker 0 never beats
+        thread::sleep(Duration::from_millis(10));
+        pool.check().unwrap();
+        assert!(!pool.is_finished(0));
+        assert_eq!(stop.reason(), None);
+        // the replaced worker is cancelled alone and returns
+        while pool.abandoned() > 0 {
+            thread::yield_now();
+        }
+        assert!(!pool.is_finished(0));
+        assert!(!stop.is_stopped());
+
+        stop.stop(StopReason::Signal);
+        assert_eq!(pool.join(), 0);
+        assert_eq!(stop.reason(), Some(StopReason::Signal));
+        // every sender is gone with the workers
+        let mut sent: Vec<_> = rx.iter().collect();
+        sent.sort();
+        assert_eq!(sent, [0, 0, 1, 2]);
+
+        let stop = Arc::new(StopSignal::new());
+        let (tx, rx) = mpsc::channel::<()>();
+        let pool = WorkerPool::start(PoolConfig::new(2), stop.clone(), tx, |w| {
+            if w.index == 1 {
+                Err("bad")
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap();
+        assert_eq!(pool.join(), 0);
+        assert_eq!(stop.reason(), Some(StopReason::WorkerFailed));
+        assert!(rx.recv().is_err());
+
+        // a worker out of work isn't a failed one
+        let stop = Arc::new(StopSignal::new());
+        let (tx, _rx) = mpsc::channel::<()>();
+        let pool = WorkerPool::start(PoolConfig::new(2), stop.clone(), tx, |w| {
+            while w.index == 1 && !w.exit.is_stopped() {
+                thread::yield_now();
+            }
+            Ok::<_, &str>(if w.index == 0 {
+                Done::Exhausted
+            } else {
+                Done::Stopped
+            })
+        })
+        .unwrap();
+        while pool.exhausted() < 1 {
+            thread::yield_now();
+        }
+        assert_eq!(pool.failed(), 0);
+        stop.stop(StopReason::Signal);
+        assert_eq!(pool.join(), 0);
+        assert_eq!(stop.reason(), Some(StopReason::Signal));
+    }
+
+    #[test]
+    fn test_chunk() {
+        let (tx, _rx) = mpsc::channel::<()>();
+        let worker = Worker {
+            index: 2,
+            count: 4,
+            exit: Arc::new(StopSignal::new()),
+            tx,
+            heartbeat: Heartbeat::default(),
+            coverage: Arc::default(),
+        };
+        let search = SearchConfig::new(KeyConfig::default(), 1);
+        assert_eq!(worker.chunk(&search).shard, None);
+        assert_eq!(worker.chunk(&search).time_slice, None);
+
+        let split = SearchConfig {
+            shard: Some((0, 1)),
+            time_slice: Some(TimeSlice {
+                start: 1_700_000_000,
+                index: 0,
+                count: 1,
+            }),
+            ..search
+        };
+        let chunk = worker.chunk(&split);
+        assert_eq!(chunk.shard, Some((2, 4)));
+        assert_eq!(chunk.time_slice.map(|s| (s.index, s.count)), Some((2, 4)));
+        assert!(Arc::ptr_eq(
+            chunk.coverage.as_ref().unwrap(),
+            &worker.coverage
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Algorithm preferences written into the self signature of the generated
+//! keys, given like GnuPG's `setpref`, e.g. `AES256 AES SHA512 SHA256 ZLIB`.
+//!
+//! Every name goes into the list of its kind in the order given. A kind left
+//! out keeps the defaults of the OpenPGP backend.
+
+use crate::error::ApgpkError;
+use pgp::{
+    crypto::{hash::HashAlgorithm, sym::SymmetricKeyAlgorithm},
+    types::CompressionAlgorithm,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Names of the symmetric algorithms, the first one being the one shown
+const SYMMETRIC: &[(&str, SymmetricKeyAlgorithm)] = &[
+    ("AES256", SymmetricKeyAlgorithm::AES256),
+    ("AES192", SymmetricKeyAlgorithm::AES192),
+    ("AES", SymmetricKeyAlgorithm::AES128),
+    ("AES128", SymmetricKeyAlgorithm::AES128),
+    ("TWOFISH", SymmetricKeyAlgorithm::Twofish),
+    ("CAMELLIA256", SymmetricKeyAlgorithm::Camellia256),
+    ("CAMELLIA192", SymmetricKeyAlgorithm::Camellia192),
+    ("CAMELLIA128", SymmetricKeyAlgorithm::Camellia128),
+    ("CAST5", SymmetricKeyAlgorithm::CAST5),
+    ("BLOWFISH", SymmetricKeyAlgorithm::Blowfish),
+    ("3DES", SymmetricKeyAlgorithm::TripleDES),
+    ("IDEA", SymmetricKeyAlgorithm::IDEA),
+];
+
+const HASH: &[(&str, HashAlgorithm)] = &[
+    ("SHA512", HashAlgorithm::SHA2_512),
+    ("SHA384", HashAlgorithm::SHA2_384),
+    ("SHA256", HashAlgorithm::SHA2_256),
+    ("SHA224", HashAlgorithm::SHA2_224),
+    ("SHA1", HashAlgorithm::SHA1),
+    ("RIPEMD160", HashAlgorithm::RIPEMD160),
+];
+
+const COMPRESSION: &[(&str, CompressionAlgorithm)] = &[
+    ("ZLIB", CompressionAlgorithm::ZLIB),
+    ("BZIP2", CompressionAlgorithm::BZip2),
+    ("ZIP", CompressionAlgorithm::ZIP),
+    ("UNCOMPRESSED", CompressionAlgorithm::Uncompressed),
+];
+
+/// Preferred algorithms, most preferred first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Preferences {
+    pub symmetric: Vec<SymmetricKeyAlgorithm>,
+    pub hash: Vec<HashAlgorithm>,
+    pub compression: Vec<CompressionAlgorithm>,
+}
+
+/// Add the algorithm named `name` in `table` to `list`, false if unknown
+fn push<T: Copy + PartialEq>(
+    table: &[(&str, T)],
+    name: &str,
+    list: &mut Vec<T>,
+) -> Result<bool, ApgpkError> {
+    let Some((_, alg)) = table.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) else {
+        return Ok(false);
+    };
+    if list.contains(alg) {
+        return Err(ApgpkError::Other(format!(
+            "Algorithm `{}` is preferred twice",
+            name
+        )));
+    }
+    list.push(*alg);
+    Ok(true)
+}
+
+fn names<'a, T: PartialEq>(
+    table: &'a [(&'a str, T)],
+    list: &'a [T],
+) -> impl Iterator<Item = &'a str> {
+    list.iter()
+        .filter_map(move |alg| table.iter().find(|(_, a)| a == alg).map(|(n, _)| *n))
+}
+
+impl FromStr for Preferences {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut prefs = Preferences {
+            symmetric: vec![],
+            hash: vec![],
+            compression: vec![],
+        };
+        for name in s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|n| !n.is_empty())
+        {
+            if !(push(SYMMETRIC, name, &mut prefs.symmetric)?
+                || push(HASH, name, &mut prefs.hash)?
+                || push(COMPRESSION, name, &mut prefs.compression)?)
+            {
+                return Err(ApgpkError::Other(format!(
+                    "Unknown algorithm `{}` in the preferences, expect e.g. AES256, SHA512 or ZLIB",
+                    name
+                )));
+            }
+        }
+        if prefs.symmetric.is_empty() && prefs.hash.is_empty() && prefs.compression.is_empty() {
+            return Err(ApgpkError::Other("The preferences are empty".to_string()));
+        }
+        Ok(prefs)
+    }
+}
+
+impl fmt::Display for Preferences {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all: Vec<_> = names(SYMMETRIC, &self.symmetric)
+            .chain(names(HASH, &self.hash))
+            .chain(names(COMPRESSION, &self.compression))
+            .collect();
+        write!(f, "{}", all.join(" "))
+    }
+}
+
+impl TryFrom<String> for Preferences {
+    type Error = ApgpkError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Preferences> for String {
+    fn from(prefs: Preferences) -> Self {
+        prefs.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::KeyConfig,
+        keygen::{KeyGenerator, PgpKeyGenerator},
+    };
+    use chrono::Utc;
+
+    #[test]
+    fn test_preferences() {
+        let prefs: Preferences = "aes256 AES sha512,SHA256 ZLIB".parse().unwrap();
+        assert_eq!(
+            prefs.symmetric,
+            [SymmetricKeyAlgorithm::AES256, SymmetricKeyAlgorithm::AES128]
+        );
+        assert_eq!(
+            prefs.hash,
+            [HashAlgorithm::SHA2_512, HashAlgorithm::SHA2_256]
+        );
+        assert_eq!(prefs.compression, [CompressionAlgorithm::ZLIB]);
+        assert_eq!(prefs.to_string(), "AES256 AES SHA512 SHA256 ZLIB");
+        assert!("AES256 MD5".parse::<Preferences>().is_err());
+        assert!("AES AES128".parse::<Preferences>().is_err());
+        assert!(" ".parse::<Preferences>().is_err());
+
+        let key = KeyConfig {
+            preferences: Some(prefs.clone()),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        let signed = PgpKeyGenerator::new(&key, now)
+            .generate(now)
+            .sign()
+            .unwrap();
+        let sig = &signed.details.users[0].signatures[0];
+        assert_eq!(sig.preferred_symmetric_algs(), prefs.symmetric.as_slice());
+        assert_eq!(sig.preferred_hash_algs(), prefs.hash.as_slice());
+    }
+}
@@ -0,0 +1,271 @@
+//! Report the progress of a search to any set of sinks.
+
+use crate::error::ApgpkError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Snapshot of the search progress
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    /// Keys generated so far
+    pub tried: u64,
+    /// Candidates skipped as already tried with the same keypair, see
+    /// [`crate::coverage`]
+    #[serde(default)]
+    pub skipped: u64,
+    /// Keys per second of all threads
+    pub rate: f64,
+    /// Keys saved so far
+    pub found: usize,
+    pub threads: usize,
+    pub uptime_secs: u64,
+    /// Keys expected for a 50% chance of any pattern matching, `None` if it
+    /// can't be computed
+    pub p50_tries: Option<f64>,
+}
+
+impl Progress {
+    /// Share of [`Progress::p50_tries`] tried so far
+    pub fn p50_share(&self) -> Option<f64> {
+        self.p50_tries.map(|p50| self.tried as f64 / p50)
+    }
+}
+
+/// Sink of the progress of a search, called by the receiver of the found keys
+pub trait ProgressReporter {
+    /// Periodic progress update
+    fn report(&mut self, progress: &Progress) -> Result<(), ApgpkError>;
+
+    /// A key has been found and saved
+    fn found(&mut self, _fingerprint: &str) -> Result<(), ApgpkError> {
+        Ok(())
+    }
+
+    /// The search is over
+    fn finish(&mut self, _progress: &Progress) -> Result<(), ApgpkError> {
+        Ok(())
+    }
+}
+
+/// Human readable log lines
+#[derive(Debug, Default)]
+pub struct LogReporter;
+
+impl ProgressReporter for LogReporter {
+    fn report(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        log::info!(
+            "Current speed estimated ({} threads) {:.2} key/s",
+            progress.threads,
+            progress.rate
+        );
+        if let Some(p50) = progress.p50_tries {
+            log::info!(
+                "Progress (probabilistic): {:.1}% of the keys expected for a 50% chance of a match, chance so far {:.1}%",
+                100.0 * progress.tried as f64 / p50,
+                100.0 * crate::estimate::match_chance(progress.tried as f64, p50)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// One JSON object per line, `{"event":"progress",...}` or
+/// `{"event":"found","fingerprint":...}`
+#[derive(Debug)]
+pub struct JsonReporter<W> {
+    out: W,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonEvent<'a> {
+    Progress(&'a Progress),
+    Found { fingerprint: &'a str },
+    Finish(&'a Progress),
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    fn write(&mut self, event: JsonEvent) -> Result<(), ApgpkError> {
+        let line = serde_json::to_string(&event)
+            .map_err(|e| ApgpkError::Other(format!("Failed to serialize progress: {}", e)))?;
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> ProgressReporter for JsonReporter<W> {
+    fn report(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        self.write(JsonEvent::Progress(progress))
+    }
+
+    fn found(&mut self, fingerprint: &str) -> Result<(), ApgpkError> {
+        self.write(JsonEvent::Found { fingerprint })
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        self.write(JsonEvent::Finish(progress))
+    }
+}
+
+/// Width of the [`BarReporter`] bar in characters
+const BAR_WIDTH: usize = 30;
+
+/// Single line progress bar on stderr, filled up to the keys expected for a
+/// 50% chance of a match
+#[derive(Debug, Default)]
+pub struct BarReporter;
+
+impl BarReporter {
+    fn render(progress: &Progress) -> String {
+        let bar = match progress.p50_share() {
+            Some(share) => {
+                let filled = ((share.min(1.0) * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+                format!(
+                    "[{}{}] {:5.1}% of P50",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    100.0 * share
+                )
+            }
+            None => format!("[{}] P50 unknown", "?".repeat(BAR_WIDTH)),
+        };
+        format!(
+            "{} | {:.0} key/s | {} tried | {} found",
+            bar, progress.rate, progress.tried, progress.found
+        )
+    }
+}
+
+impl ProgressReporter for BarReporter {
+    fn report(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        let mut err = io::stderr().lock();
+        write!(err, "\r{}", Self::render(progress))?;
+        err.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        eprintln!("\r{}", Self::render(progress));
+        Ok(())
+    }
+}
+
+/// Metrics in the Prometheus text format, rewritten on every report into a
+/// file read by the node exporter textfile collector
+#[derive(Debug)]
+pub struct PrometheusReporter {
+    path: PathBuf,
+}
+
+impl PrometheusReporter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn render(progress: &Progress) -> String {
+        let metrics = [
+            (
+                "apgpk_keys_tried_total",
+                "counter",
+                "Keys generated",
+                progress.tried as f64,
+            ),
+            (
+                "apgpk_keys_skipped_total",
+                "counter",
+                "Candidates skipped as already tried with the same keypair",
+                progress.skipped as f64,
+            ),
+            (
+                "apgpk_keys_per_second",
+                "gauge",
+                "Keys generated per second",
+                progress.rate,
+            ),
+            (
+                "apgpk_keys_found_total",
+                "counter",
+                "Keys found",
+                progress.found as f64,
+            ),
+            (
+                "apgpk_uptime_seconds",
+                "gauge",
+                "Seconds since the search started",
+                progress.uptime_secs as f64,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                name, help, name, kind, name, value
+            ));
+        }
+        text
+    }
+}
+
+impl ProgressReporter for PrometheusReporter {
+    fn report(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        // the collector must never read a half written file
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, Self::render(progress))?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<(), ApgpkError> {
+        self.report(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reporters() {
+        let progress = Progress {
+            tried: 500,
+            skipped: 20,
+            rate: 100.0,
+            found: 1,
+            threads: 2,
+            uptime_secs: 5,
+            p50_tries: Some(1000.0),
+        };
+        assert_eq!(progress.p50_share(), Some(0.5));
+
+        let mut json = JsonReporter::new(vec![]);
+        json.report(&progress).unwrap();
+        json.found("CAFE").unwrap();
+        let out = String::from_utf8(json.out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert!(lines[0].starts_with(r#"{"event":"progress","tried":500,"#));
+        assert_eq!(lines[1], r#"{"event":"found","fingerprint":"CAFE"}"#);
+
+        let bar = BarReporter::render(&progress);
+        assert!(bar.starts_with(&format!(
+            "[{}{}]  50.0% of P50",
+            "#".repeat(15),
+            "-".repeat(15)
+        )));
+
+        let metrics = PrometheusReporter::render(&progress);
+        assert!(
+            metrics.contains("# TYPE apgpk_keys_tried_total counter\napgpk_keys_tried_total 500\n")
+        );
+        assert!(metrics.contains("\napgpk_keys_skipped_total 20\n"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Passphrase protection of the exported secret keys.
+//!
+//! Keys are searched and signed unprotected, the secret parameters are only
+//! encrypted right before export. The self signatures and the fingerprint
+//! cover public parts only, so they stay valid.
+
+use crate::{error::ApgpkError, unsigned};
+use pgp::{
+    composed::signed_key::SignedSecretKey,
+    crypto::sym::SymmetricKeyAlgorithm,
+    packet::{self, PacketTrait},
+    types::{KeyTrait, KeyVersion, SecretParams, StringToKey},
+};
+use rand::{CryptoRng, Rng};
+
+/// S2K usage octet of secret parameters encrypted with a SHA-1 checksum,
+/// what GnuPG writes (RFC 4880, 5.5.3)
+const S2K_USAGE_SHA1: u8 = 254;
+
+/// `key` with the secret parameters of the primary key and all subkeys
+/// encrypted by `passphrase`, with AES-256 and an iterated and salted S2K
+pub fn protect(mut key: SignedSecretKey, passphrase: &str) -> Result<SignedSecretKey, ApgpkError> {
+    if passphrase.is_empty() {
+        return Err(ApgpkError::Other("the passphrase is empty".to_string()));
+    }
+    let mut rng = rand::thread_rng();
+
+    let p = &key.primary_key;
+    let public = packet::PublicKey::new(
+        p.packet_version(),
+        p.version(),
+        p.algorithm(),
+        *p.created_at(),
+        p.expiration(),
+        p.public_params().clone(),
+    )?;
+    let params = encrypt(p.secret_params(), p.version(), passphrase, &mut rng)?;
+    key.primary_key = unsigned::secret_key(&public, &params)?;
+
+    for subkey in &mut key.secret_subkeys {
+        let s = &subkey.key;
+        let public = packet::PublicSubkey::new(
+            s.packet_version(),
+            s.version(),
+            s.algorithm(),
+            *s.created_at(),
+            s.expiration(),
+            s.public_params().clone(),
+        )?;
+        let params = encrypt(s.secret_params(), s.version(), passphrase, &mut rng)?;
+        subkey.key = unsigned::secret_subkey(&public, &params)?;
+    }
+    Ok(key)
+}
+
+fn encrypt<R: Rng + CryptoRng>(
+    params: &SecretParams,
+    version: KeyVersion,
+    passphrase: &str,
+    rng: &mut R,
+) -> Result<SecretParams, ApgpkError> {
+    let SecretParams::Plain(plain) = params else {
+        return Ok(params.clone());
+    };
+    let s2k = StringToKey::new_default(rng);
+    let encrypted = plain.clone().encrypt(
+        rng,
+        passphrase,
+        SymmetricKeyAlgorithm::AES256,
+        s2k,
+        version,
+        S2K_USAGE_SHA1,
+    )?;
+    Ok(SecretParams::Encrypted(encrypted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::KeyConfig,
+        keygen::{KeyGenerator, PgpKeyGenerator},
+    };
+    use chrono::Utc;
+    use pgp::{
+        types::{KeyTrait, SecretKeyTrait},
+        Deserializable,
+    };
+
+    #[test]
+    fn test_protect() {
+        let key = KeyConfig {
+            encryption_subkey: true,
+            ..Default::default()
+        };
+        let now = Utc::now();
+        let k = PgpKeyGenerator::new(&key, now).generate(now);
+        let signed = k.sign().unwrap();
+        let fp = signed.fingerprint();
+        assert!(protect(signed.clone(), "").is_err());
+
+        let armored = protect(signed, "correct horse")
+            .unwrap()
+            .to_armored_string(None)
+            .unwrap();
+        let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.fingerprint(), fp);
+        assert!(matches!(
+            read.primary_key.secret_params(),
+            SecretParams::Encrypted(_)
+        ));
+        assert!(matches!(
+            read.secret_subkeys[0].key.secret_params(),
+            SecretParams::Encrypted(_)
+        ));
+        assert!(read
+            .unlock(|| "correct horse".to_string(), |_| Ok(()))
+            .is_ok());
+        assert!(read.unlock(|| "wrong".to_string(), |_| Ok(())).is_err());
+    }
+}
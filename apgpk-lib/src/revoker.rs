@@ -0,0 +1,182 @@
+//! Designated revokers, keys allowed to revoke the generated keys, e.g. an
+//! organization's escrow key.
+//!
+//! They are named like GnuPG's `Revoker:` key parameter, `ALGO:FINGERPRINT`,
+//! and bound by a direct-key signature of the primary key.
+
+use crate::error::ApgpkError;
+use chrono::{SubsecRound, Utc};
+use pgp::{
+    composed::signed_key::SignedSecretKey,
+    crypto::{hash::HashAlgorithm, public_key::PublicKeyAlgorithm},
+    packet::{
+        Signature, SignatureConfig, SignatureType, SignatureVersion, Subpacket, SubpacketData,
+    },
+    types::{KeyTrait, RevocationKey, RevocationKeyClass},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Algorithms of revoker keys, by name and by OpenPGP algorithm ID
+const ALGORITHMS: &[(&str, u8, PublicKeyAlgorithm)] = &[
+    ("rsa", 1, PublicKeyAlgorithm::RSA),
+    ("dsa", 17, PublicKeyAlgorithm::DSA),
+    ("ecdsa", 19, PublicKeyAlgorithm::ECDSA),
+    ("eddsa", 22, PublicKeyAlgorithm::EdDSA),
+];
+
+/// V4 key allowed to revoke the generated keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Revoker {
+    pub algorithm: PublicKeyAlgorithm,
+    pub fingerprint: [u8; 20],
+}
+
+/// `key` with a direct-key signature designating `revokers`, unchanged
+/// without any
+pub fn designate(
+    mut key: SignedSecretKey,
+    revokers: &[Revoker],
+) -> Result<SignedSecretKey, ApgpkError> {
+    if revokers.is_empty() {
+        return Ok(key);
+    }
+    let sig = direct_key_signature(&key, revokers)?;
+    key.details.direct_signatures.push(sig);
+    Ok(key)
+}
+
+fn direct_key_signature(
+    key: &SignedSecretKey,
+    revokers: &[Revoker],
+) -> Result<Signature, ApgpkError> {
+    let mut hashed = vec![Subpacket::regular(SubpacketData::SignatureCreationTime(
+        Utc::now().trunc_subsecs(0),
+    ))];
+    hashed.extend(revokers.iter().map(|r| {
+        Subpacket::regular(SubpacketData::RevocationKey(RevocationKey::new(
+            RevocationKeyClass::Default,
+            r.algorithm,
+            &r.fingerprint,
+        )))
+    }));
+    let unhashed = vec![Subpacket::regular(SubpacketData::Issuer(key.key_id()))];
+    Ok(SignatureConfig::new_v4(
+        SignatureVersion::V4,
+        SignatureType::Key,
+        key.algorithm(),
+        HashAlgorithm::SHA2_256,
+        hashed,
+        unhashed,
+    )
+    .sign_key(key, String::new, key)?)
+}
+
+impl FromStr for Revoker {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ApgpkError::Other(format!(
+                "Invalid revoker `{}`, expect ALGO:FINGERPRINT, e.g. eddsa:<40 hex digits>",
+                s
+            ))
+        };
+        let (algo, fp) = s.split_once(':').ok_or_else(invalid)?;
+        let algo = algo.trim().to_lowercase();
+        let algorithm = ALGORITHMS
+            .iter()
+            .find(|(name, id, _)| *name == algo || id.to_string() == algo)
+            .map(|(_, _, alg)| *alg)
+            .ok_or_else(|| {
+                ApgpkError::Other(format!(
+                    "Unknown revoker algorithm `{}`, expect rsa, dsa, ecdsa or eddsa",
+                    algo
+                ))
+            })?;
+        let fp: String = fp.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut fingerprint = [0; 20];
+        hex::decode_to_slice(fp.trim_start_matches("0x"), &mut fingerprint)
+            .map_err(|_| invalid())?;
+        Ok(Revoker {
+            algorithm,
+            fingerprint,
+        })
+    }
+}
+
+impl fmt::Display for Revoker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = ALGORITHMS
+            .iter()
+            .find(|(_, _, alg)| *alg == self.algorithm)
+            .map_or("?", |(name, _, _)| *name);
+        write!(f, "{}:{}", name, hex::encode_upper(self.fingerprint))
+    }
+}
+
+impl TryFrom<String> for Revoker {
+    type Error = ApgpkError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Revoker> for String {
+    fn from(revoker: Revoker) -> Self {
+        revoker.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::KeyConfig,
+        keygen::{KeyGenerator, PgpKeyGenerator},
+    };
+    use pgp::Deserializable;
+
+    #[test]
+    fn test_designate() {
+        let fp = "0123456789ABCDEF0123456789ABCDEF01234567";
+        let revoker: Revoker = format!("22:{}", fp).parse().unwrap();
+        assert_eq!(revoker.algorithm, PublicKeyAlgorithm::EdDSA);
+        assert_eq!(revoker.to_string(), format!("eddsa:{}", fp));
+        assert_eq!(
+            "RSA:0123 4567 89AB CDEF 0123  4567 89AB CDEF 0123 4567"
+                .parse::<Revoker>()
+                .unwrap()
+                .fingerprint,
+            revoker.fingerprint
+        );
+        assert!(fp.parse::<Revoker>().is_err());
+        assert!(format!("x25519:{}", fp).parse::<Revoker>().is_err());
+        assert!("eddsa:0123".parse::<Revoker>().is_err());
+
+        let now = Utc::now();
+        let signed = PgpKeyGenerator::new(&KeyConfig::default(), now)
+            .generate(now)
+            .sign()
+            .unwrap();
+        let armored = designate(signed, std::slice::from_ref(&revoker))
+            .unwrap()
+            .to_armored_string(None)
+            .unwrap();
+        let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+        read.verify().unwrap();
+        let designated: Vec<_> = read
+            .details
+            .direct_signatures
+            .iter()
+            .flat_map(|sig| &sig.config.hashed_subpackets)
+            .filter_map(|p| match &p.data {
+                SubpacketData::RevocationKey(r) => Some(r.fingerprint.to_vec()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(designated, [revoker.fingerprint.to_vec()]);
+    }
+}
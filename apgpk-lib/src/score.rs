@@ -0,0 +1,158 @@
+use crate::{core::Pattern, error::ApgpkError};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Weights of the fingerprint features summed up by [`Scorer`]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreWeights {
+    /// per hex digit of the longest suffix shared with a literal pattern
+    pub suffix: f64,
+    /// per repeated hex digit at the end of the fingerprint
+    pub tail_run: f64,
+    /// per `0` at the start of the fingerprint
+    pub leading_zeros: f64,
+    /// per hex digit of the longest run of the same digit anywhere
+    pub longest_run: f64,
+}
+
+impl FromStr for ScoreWeights {
+    type Err = ApgpkError;
+
+    /// Parse `name=weight` pairs separated by commas, e.g.
+    /// `suffix=1,tail_run=2`. Missing weights are 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut w = ScoreWeights::default();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').ok_or_else(|| {
+                ApgpkError::Other(format!("Expect `name=weight`, got `{}`", pair))
+            })?;
+            let value: f64 = value.trim().parse().map_err(|_| {
+                ApgpkError::Other(format!("Invalid weight `{}` for `{}`", value, name))
+            })?;
+            match name.trim() {
+                "suffix" => w.suffix = value,
+                "tail_run" => w.tail_run = value,
+                "leading_zeros" => w.leading_zeros = value,
+                "longest_run" => w.longest_run = value,
+                _ => {
+                    return Err(ApgpkError::Other(format!(
+                        "Unknown score feature `{}`, expect one of suffix, tail_run, leading_zeros, longest_run",
+                        name
+                    )))
+                }
+            }
+        }
+        Ok(w)
+    }
+}
+
+/// Scores fingerprints and keeps the ones reaching a threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scorer {
+    weights: ScoreWeights,
+    threshold: f64,
+    /// literal patterns the suffix feature is measured against
+    suffixes: Vec<String>,
+}
+
+impl Scorer {
+    pub fn new(weights: ScoreWeights, threshold: f64, pars: &[Pattern]) -> Self {
+        let suffixes = pars
+            .iter()
+            .filter_map(|p| match p {
+                Pattern::Literal(l) => Some(l.text().to_string()),
+                _ => None,
+            })
+            .collect();
+        Self {
+            weights,
+            threshold,
+            suffixes,
+        }
+    }
+
+    /// Score of a fingerprint given in upper case hex
+    pub fn score(&self, fp: &str) -> f64 {
+        let w = &self.weights;
+        let mut score = 0.0;
+        if w.suffix != 0.0 {
+            let longest = self
+                .suffixes
+                .iter()
+                .map(|s| common_suffix_len(fp, s))
+                .max()
+                .unwrap_or(0);
+            score += w.suffix * longest as f64;
+        }
+        if w.tail_run != 0.0 {
+            score += w.tail_run * tail_run(fp) as f64;
+        }
+        if w.leading_zeros != 0.0 {
+            score += w.leading_zeros * fp.bytes().take_while(|b| *b == b'0').count() as f64;
+        }
+        if w.longest_run != 0.0 {
+            score += w.longest_run * longest_run(fp) as f64;
+        }
+        score
+    }
+
+    /// Score of the fingerprint if it reaches the threshold
+    pub fn keep(&self, fp: &str) -> Option<f64> {
+        let score = self.score(fp);
+        (score >= self.threshold).then_some(score)
+    }
+}
+
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.bytes()
+        .rev()
+        .zip(b.bytes().rev())
+        .take_while(|(x, y)| x == y || *y == b'?')
+        .count()
+}
+
+fn tail_run(fp: &str) -> usize {
+    match fp.bytes().last() {
+        Some(last) => fp.bytes().rev().take_while(|b| *b == last).count(),
+        None => 0,
+    }
+}
+
+fn longest_run(fp: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev = None;
+    for b in fp.bytes() {
+        run = if prev == Some(b) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        prev = Some(b);
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MatchMode;
+
+    #[test]
+    fn test_score() {
+        let weights: ScoreWeights = "suffix=1, tail_run=2,leading_zeros=0.5,longest_run=1"
+            .parse()
+            .unwrap();
+        let scorer = Scorer::new(
+            weights,
+            20.0,
+            &[Pattern::literal("CAFEFFFF", MatchMode::Suffix)],
+        );
+        let fp = "000D5C3A9B1E0F7D24C6A8B3E5F1D9C7A2B0FFFF";
+        // suffix 4, tail run 4, leading zeros 3, longest run 4
+        assert_eq!(scorer.score(fp), 4.0 + 8.0 + 1.5 + 4.0);
+        assert_eq!(scorer.keep(fp), None);
+        assert!(scorer
+            .keep("0000000000000000000000000000000000000000")
+            .is_some());
+        assert!("unknown=1".parse::<ScoreWeights>().is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! Reproducible key material for seeded searches.
+//!
+//! The keypair tried at a creation time is derived from the seed and its
+//! counter, [`KeyConfig::keypair_counter`], which only depends on the
+//! creation time. A found key is re-derived from `(seed, counter, timestamp)`
+//! by [`derive_key`], and a seeded search sweeping the same window finds the
+//! same keys again, whatever the number of threads.
+//!
+//! Anyone knowing the seed knows the secret keys, so a seed must be kept as
+//! secret as the keys themselves.
+
+use crate::{
+    config::KeyConfig,
+    error::ApgpkError,
+    keygen::{KeyGenerator, PgpKeyGenerator},
+    unsigned::UnsignedKey,
+};
+use chrono::prelude::*;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+/// Domain of the RNG seeds, bumped if the derivation ever changes
+const DOMAIN: &[u8] = b"apgpk seed v1";
+
+/// Seed of a reproducible search
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Seed([u8; 32]);
+
+impl Seed {
+    /// RNG of the keypair with index `counter`
+    pub fn rng(&self, counter: i64) -> ChaCha20Rng {
+        let digest = Sha256::new()
+            .chain_update(DOMAIN)
+            .chain_update(self.0)
+            .chain_update(counter.to_be_bytes())
+            .finalize();
+        ChaCha20Rng::from_seed(digest.into())
+    }
+
+    /// Identifier of the keypairs of the seed, which doesn't give it away
+    pub fn id(&self) -> u64 {
+        let digest = Sha256::new()
+            .chain_update(DOMAIN)
+            .chain_update(b"id")
+            .chain_update(self.0)
+            .finalize();
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+}
+
+/// Key created at `created_at` by a search with `key`, which must be seeded.
+///
+/// With several creation times per keypair the subkeys of the found key may
+/// be dated differently, the primary key is the same.
+pub fn derive_key(key: &KeyConfig, created_at: DateTime<Utc>) -> Result<UnsignedKey, ApgpkError> {
+    if key.seed.is_none() {
+        return Err(ApgpkError::Other(
+            "keys can only be re-derived from a seed".to_string(),
+        ));
+    }
+    Ok(PgpKeyGenerator::new(key, created_at).generate(created_at))
+}
+
+/// 64 hex digits are taken as they are, any other text is hashed
+impl FromStr for Seed {
+    type Err = ApgpkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ApgpkError::Other("the seed is empty".to_string()));
+        }
+        let mut seed = [0; 32];
+        if hex::decode_to_slice(s, &mut seed).is_err() {
+            seed = Sha256::digest(s.as_bytes()).into();
+        }
+        Ok(Seed(seed))
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// Doesn't show the seed, which gives away the keys
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Seed(..)")
+    }
+}
+
+impl TryFrom<String> for Seed {
+    type Error = ApgpkError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Seed> for String {
+    fn from(seed: Seed) -> Self {
+        seed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keygen::SweepKeyGenerator;
+    use pgp::types::KeyTrait;
+
+    #[test]
+    fn test_derive_key() {
+        let seed: Seed = "correct horse battery staple".parse().unwrap();
+        assert_eq!(seed.to_string().parse::<Seed>().unwrap(), seed);
+        assert_eq!(format!("{:?}", seed), "Seed(..)");
+
+        let key = KeyConfig {
+            seed: Some(seed),
+            ..Default::default()
+        };
+        let t = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let mut generator = PgpKeyGenerator::new(&key, t);
+        let found = generator.generate(t);
+        let other = generator.generate(t - chrono::Duration::seconds(1));
+        assert_ne!(found.fingerprint(), other.fingerprint());
+        assert_eq!(
+            derive_key(&key, t).unwrap().fingerprint(),
+            found.fingerprint()
+        );
+        assert!(derive_key(&KeyConfig::default(), t).is_err());
+
+        let rsa = KeyConfig {
+            key_type: pgp::composed::KeyType::Rsa(2048),
+            ..key
+        };
+        let per_key = rsa.timestamps_per_key() as i64;
+        let block = Utc.timestamp_opt(per_key * 26_000, 0).unwrap();
+        let late = block + chrono::Duration::seconds(per_key - 1);
+        let swept = SweepKeyGenerator::new(&rsa, block).generate(late);
+        assert_eq!(
+            derive_key(&rsa, late).unwrap().fingerprint(),
+            swept.fingerprint()
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! OpenSSH fingerprints of the generated Ed25519 keys, so a key can be
+//! pretty both as an OpenPGP key and as an SSH key.
+//!
+//! OpenSSH shows `SHA256:` and the unpadded base64 of the SHA-256 of the
+//! public key blob, e.g. `SHA256:ov95hbgGu2HOBzd4RIto7n4BtJpdr+nvwOHOJDWjlG4`.
+//! Patterns are matched case-sensitively against the 43 base64 characters.
+
+use crate::{core::MatchMode, error::ApgpkError, unsigned::UnsignedKey};
+use pgp::{crypto::ecc_curve::ECCCurve, types::PublicParams};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Base64 characters of a SHA-256 fingerprint without padding
+pub const SSH_FP_LEN: usize = 43;
+
+const KEY_TYPE: &str = "ssh-ed25519";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// OpenSSH wire encoding of an Ed25519 public key
+pub fn public_key_blob(q: &[u8; 32]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(4 + KEY_TYPE.len() + 4 + q.len());
+    for field in [KEY_TYPE.as_bytes(), q] {
+        blob.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        blob.extend_from_slice(field);
+    }
+    blob
+}
+
+/// Base64 part of the SHA-256 fingerprint of an Ed25519 public key
+pub fn fingerprint(q: &[u8; 32]) -> String {
+    base64(&Sha256::digest(public_key_blob(q)))
+}
+
+/// Base64 part of the SSH fingerprint of the primary key, `None` unless it
+/// is an Ed25519 key
+pub fn key_fingerprint(k: &UnsignedKey) -> Option<String> {
+    match k.primary_key.public_params() {
+        PublicParams::EdDSA {
+            curve: ECCCurve::Ed25519,
+            q,
+        } => {
+            // the OpenPGP point has a 0x40 prefix
+            let q: &[u8; 32] = q.as_bytes().strip_prefix(&[0x40])?.try_into().ok()?;
+            Some(fingerprint(q))
+        }
+        _ => None,
+    }
+}
+
+/// Unpadded standard base64
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() * 4).div_ceil(3));
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            out.push(BASE64[((n >> (18 - 6 * i)) & 0x3F) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Text the SSH fingerprint has to contain where `mode` says
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SshPattern {
+    text: String,
+    mode: MatchMode,
+}
+
+impl SshPattern {
+    /// Pattern of base64 characters, optionally with a mode prefix as in
+    /// pattern files, e.g. `prefix:apgpk`
+    pub fn parse(s: &str, default_mode: MatchMode) -> Result<Self, ApgpkError> {
+        let (text, mode) = match s.trim().split_once(':') {
+            Some((m, text)) => (text, m.parse()?),
+            None => (s.trim(), default_mode),
+        };
+        if text.is_empty() || text.len() > SSH_FP_LEN || !text.bytes().all(|b| BASE64.contains(&b))
+        {
+            return Err(ApgpkError::Other(format!(
+                "SSH pattern `{}` must be 1 to {} base64 characters (A-Z, a-z, 0-9, + and /)",
+                s, SSH_FP_LEN
+            )));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            mode,
+        })
+    }
+
+    pub fn is_match(&self, fingerprint: &str) -> bool {
+        match self.mode {
+            MatchMode::Prefix => fingerprint.starts_with(&self.text),
+            MatchMode::Suffix => fingerprint.ends_with(&self.text),
+            MatchMode::Both => {
+                fingerprint.starts_with(&self.text) || fingerprint.ends_with(&self.text)
+            }
+            MatchMode::Anywhere => fingerprint.contains(&self.text),
+        }
+    }
+}
+
+impl fmt::Display for SshPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            MatchMode::Prefix => write!(f, "SHA256:{}*", self.text),
+            MatchMode::Suffix => write!(f, "SHA256:*{}", self.text),
+            MatchMode::Both => write!(f, "SHA256:{}* | SHA256:*{}", self.text, self.text),
+            MatchMode::Anywhere => write!(f, "SHA256:*{}*", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        // key made by `ssh-keygen -t ed25519` and listed by `ssh-keygen -l`
+        let q: [u8; 32] =
+            hex::decode("4C8C9E215BC4C01596478A2EFA068698FE97F2B924822E9F0770C6386C59E80F")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(
+            base64(&public_key_blob(&q)),
+            "AAAAC3NzaC1lZDI1NTE5AAAAIEyMniFbxMAVlkeKLvoGhpj+l/K5JIIunwdwxjhsWegP"
+        );
+        let fp = fingerprint(&q);
+        assert_eq!(fp, "ov95hbgGu2HOBzd4RIto7n4BtJpdr+nvwOHOJDWjlG4");
+
+        let p = SshPattern::parse("lG4", MatchMode::Suffix).unwrap();
+        assert!(p.is_match(&fp));
+        assert!(!SshPattern::parse("lg4", MatchMode::Suffix)
+            .unwrap()
+            .is_match(&fp));
+        assert!(SshPattern::parse("prefix:ov95", MatchMode::Suffix)
+            .unwrap()
+            .is_match(&fp));
+        assert!(SshPattern::parse("a=b", MatchMode::Suffix).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+/// Why a search stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// SIGINT / SIGTERM received
+    Signal,
+    /// The requested number of keys has been found
+    CountReached,
+    /// A key of a pattern designated to end the search has been found
+    PatternFound,
+    /// The time limit of the search has been reached
+    Deadline,
+    /// Found keys couldn't be saved anymore
+    SinkError,
+    /// The output directory quota is exhausted
+    QuotaReached,
+    /// Nothing receives the found keys anymore
+    ReceiverGone,
+    /// A worker thread panicked or failed
+    WorkerFailed,
+    /// Every creation time of a seeded search has been tried
+    Exhausted,
+}
+
+impl StopReason {
+    /// Process exit code reported for this reason.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            StopReason::CountReached | StopReason::PatternFound => 0,
+            StopReason::SinkError => 1,
+            StopReason::Deadline => 2,
+            StopReason::QuotaReached => 3,
+            StopReason::ReceiverGone => 4,
+            StopReason::WorkerFailed => 5,
+            StopReason::Exhausted => 6,
+            StopReason::Signal => 130,
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StopReason::Signal => "signal received",
+            StopReason::CountReached => "key count reached",
+            StopReason::PatternFound => "stop pattern found",
+            StopReason::Deadline => "deadline reached",
+            StopReason::SinkError => "fatal error saving keys",
+            StopReason::QuotaReached => "output quota reached",
+            StopReason::ReceiverGone => "receiver of the found keys is gone",
+            StopReason::WorkerFailed => "worker thread failed",
+            StopReason::Exhausted => "search space exhausted",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Exit flag shared by all workers which remembers the first reason given.
+///
+/// A worker may also get a [`StopSignal::child`] of the search's one, which
+/// can be [cancelled](StopSignal::cancel) alone.
+#[derive(Debug, Default)]
+pub struct StopSignal {
+    stopped: AtomicBool,
+    reason: Mutex<Option<StopReason>>,
+    parent: Option<Arc<StopSignal>>,
+}
+
+impl StopSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Signal stopped with `parent`, or alone by [`StopSignal::cancel`].
+    /// Stopping it stops `parent`.
+    pub fn child(parent: &Arc<StopSignal>) -> Self {
+        Self {
+            parent: Some(parent.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// Request the search to stop. Returns `false` if it was already stopped,
+    /// in which case the original reason is kept.
+    pub fn stop(&self, reason: StopReason) -> bool {
+        if let Some(parent) = &self.parent {
+            return parent.stop(reason);
+        }
+        let mut r = self.reason.lock().unwrap_or_else(PoisonError::into_inner);
+        if r.is_some() {
+            return false;
+        }
+        *r = Some(reason);
+        self.stopped.store(true, Ordering::Relaxed);
+        true
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed) || self.parent.as_ref().is_some_and(|p| p.is_stopped())
+    }
+
+    /// Stop the worker of this signal only, e.g. one replaced after
+    /// stalling. The reason stays unset.
+    pub fn cancel(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reason(&self) -> Option<StopReason> {
+        match &self.parent {
+            Some(parent) => parent.reason(),
+            None => *self.reason.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+}
+
+/// Message of a panic caught by joining a thread
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_poisoned() {
+        let signal = Arc::new(StopSignal::new());
+        let poisoner = signal.clone();
+        let panicked = thread::spawn(move || {
+            let _guard = poisoner.reason.lock().unwrap();
+            panic!("worker bug");
+        })
+        .join()
+        .unwrap_err();
+        assert_eq!(panic_message(panicked.as_ref()), "worker bug");
+        assert!(signal.reason.is_poisoned());
+
+        assert!(signal.stop(StopReason::WorkerFailed));
+        assert!(!signal.stop(StopReason::Signal));
+        assert_eq!(signal.reason(), Some(StopReason::WorkerFailed));
+        assert_eq!(StopReason::WorkerFailed.exit_code(), 5);
+    }
+
+    #[test]
+    fn test_child() {
+        let search = Arc::new(StopSignal::new());
+        let (a, b) = (StopSignal::child(&search), StopSignal::child(&search));
+        a.cancel();
+        assert!(a.is_stopped());
+        assert!(!b.is_stopped() && !search.is_stopped());
+
+        assert!(b.stop(StopReason::ReceiverGone));
+        assert!(search.is_stopped());
+        assert_eq!(a.reason(), Some(StopReason::ReceiverGone));
+    }
+}
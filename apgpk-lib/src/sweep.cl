@@ -0,0 +1,69 @@
+// Fingerprints of one v4 public key packet at consecutive creation times,
+// see fingerprint.rs. Work item i hashes creation time `first + i` and
+// reports i if the fingerprint passes one of the masks.
+//
+// blocks: padded message as big-endian SHA-1 words, 16 per block, the
+//         creation time being word 1 of block 0
+// masks:  5 mask words then 5 value words per mask
+
+__constant uint K[4] = {0x5A827999, 0x6ED9EBA1, 0x8F1BBCDC, 0xCA62C1D6};
+
+__kernel void sweep(__global const uint *blocks, uint block_count, uint first,
+                    uint count, __global const uint *masks, uint mask_count,
+                    __global uint *hits, __global volatile uint *hit_count,
+                    uint max_hits) {
+    uint i = get_global_id(0);
+    if (i >= count) {
+        return;
+    }
+
+    uint h[5] = {0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0};
+    for (uint b = 0; b < block_count; b++) {
+        uint w[80];
+        for (uint t = 0; t < 16; t++) {
+            w[t] = blocks[b * 16 + t];
+        }
+        if (b == 0) {
+            w[1] = first + i;
+        }
+        for (uint t = 16; t < 80; t++) {
+            w[t] = rotate(w[t - 3] ^ w[t - 8] ^ w[t - 14] ^ w[t - 16], 1u);
+        }
+
+        uint a = h[0], bb = h[1], c = h[2], d = h[3], e = h[4];
+        for (uint t = 0; t < 80; t++) {
+            uint f;
+            if (t < 20) {
+                f = (bb & c) | (~bb & d);
+            } else if (t < 40 || t >= 60) {
+                f = bb ^ c ^ d;
+            } else {
+                f = (bb & c) | (bb & d) | (c & d);
+            }
+            uint temp = rotate(a, 5u) + f + e + K[t / 20] + w[t];
+            e = d;
+            d = c;
+            c = rotate(bb, 30u);
+            bb = a;
+            a = temp;
+        }
+        h[0] += a;
+        h[1] += bb;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+    }
+
+    for (uint m = 0; m < mask_count; m++) {
+        __global const uint *mv = masks + m * 10;
+        if ((h[0] & mv[0]) == mv[5] && (h[1] & mv[1]) == mv[6] &&
+            (h[2] & mv[2]) == mv[7] && (h[3] & mv[3]) == mv[8] &&
+            (h[4] & mv[4]) == mv[9]) {
+            uint slot = atomic_inc(hit_count);
+            if (slot < max_hits) {
+                hits[slot] = i;
+            }
+            return;
+        }
+    }
+}
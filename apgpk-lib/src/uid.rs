@@ -0,0 +1,128 @@
+//! User IDs assembled from their parts, `Name (Comment) <email>` as GnuPG
+//! writes them, so the parts can't run into each other.
+
+use crate::{
+    config::{self, invalid},
+    error::ApgpkError,
+};
+use serde::{Deserialize, Serialize};
+
+/// Parts of a user ID, a name or an e-mail address at least
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserIdSpec {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl UserIdSpec {
+    /// The user ID, after checking every part
+    pub fn to_uid(&self) -> Result<String, ApgpkError> {
+        let (name, email, comment) = (part(&self.name), part(&self.email), part(&self.comment));
+        if name.is_none() && email.is_none() {
+            return Err(invalid("a user ID needs a name or an e-mail address"));
+        }
+        if let Some(name) = name {
+            if name.contains(['<', '>', '(', ')']) {
+                return Err(invalid(format!(
+                    "name `{}` must not contain `<`, `>` or parentheses",
+                    name
+                )));
+            }
+        }
+        if let Some(comment) = comment {
+            if comment.contains(['<', '>', '(', ')']) {
+                return Err(invalid(format!(
+                    "comment `{}` must not contain `<`, `>` or parentheses",
+                    comment
+                )));
+            }
+        }
+        if let Some(email) = email {
+            validate_email(email)?;
+        }
+
+        let mut parts = vec![];
+        parts.extend(name.map(str::to_string));
+        parts.extend(comment.map(|c| format!("({})", c)));
+        parts.extend(email.map(|e| format!("<{}>", e)));
+        let uid = parts.join(" ");
+        config::validate_uid(&uid)?;
+        Ok(uid)
+    }
+}
+
+/// Check `email` is a plain `local@domain` address, which is all GnuPG takes
+fn validate_email(email: &str) -> Result<(), ApgpkError> {
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.is_empty()
+                && !domain.contains('@')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains("..")
+                && !email
+                    .chars()
+                    .any(|c| c.is_whitespace() || "<>()[],;:\"\\".contains(c))
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(invalid(format!("`{}` isn't a valid e-mail address", email)));
+    }
+    Ok(())
+}
+
+/// A trimmed part of a user ID, `None` if missing or blank
+fn part(p: &Option<String>) -> Option<&str> {
+    p.as_deref().map(str::trim).filter(|p| !p.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, email: &str, comment: &str) -> UserIdSpec {
+        let part = |p: &str| Some(p.to_string()).filter(|p| !p.is_empty());
+        UserIdSpec {
+            name: part(name),
+            email: part(email),
+            comment: part(comment),
+        }
+    }
+
+    #[test]
+    fn test_to_uid() {
+        assert_eq!(
+            spec("Alice Liddell", "alice@example.org", "work")
+                .to_uid()
+                .unwrap(),
+            "Alice Liddell (work) <alice@example.org>"
+        );
+        assert_eq!(
+            spec(" Alice ", "alice@example.org", "").to_uid().unwrap(),
+            "Alice <alice@example.org>"
+        );
+        assert_eq!(
+            spec("", "alice@example.org", "").to_uid().unwrap(),
+            "<alice@example.org>"
+        );
+        assert_eq!(spec("Alice", "", "").to_uid().unwrap(), "Alice");
+        assert!(spec("", "", "work").to_uid().is_err());
+        assert!(spec("Alice <a@b.c>", "", "").to_uid().is_err());
+        assert!(spec("Alice", "", "(x)").to_uid().is_err());
+        for email in [
+            "alice",
+            "@example.org",
+            "alice@",
+            "a b@c.d",
+            "a@b@c",
+            "a@.org",
+        ] {
+            assert!(spec("Alice", email, "").to_uid().is_err(), "{}", email);
+        }
+        assert!(spec("Alice\nBob", "", "").to_uid().is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Keys as the search makes them, before their self signatures.
+//!
+//! The composed secret key of the OpenPGP backend keeps its parts private,
+//! so neither the matched fingerprints nor the key material can be read
+//! before signing. Candidate keys are [`UnsignedKey`]s instead, whose self
+//! signatures are only made for export by [`UnsignedKey::sign`].
+
+use crate::{config::KeyConfig, error::ApgpkError, prefs::Preferences};
+use chrono::{DateTime, SubsecRound, TimeZone, Utc};
+use pgp::{
+    composed::{
+        signed_key::{SignedKeyDetails, SignedSecretKey, SignedSecretSubKey},
+        KeyType,
+    },
+    crypto::{hash::HashAlgorithm, public_key::PublicKeyAlgorithm},
+    packet::{
+        self, KeyFlags, PacketTrait, SignatureConfig, SignatureType, SignatureVersion, Subpacket,
+        SubpacketData, UserId,
+    },
+    ser::Serialize,
+    types::{EcdsaPublicParams, KeyId, KeyTrait, KeyVersion, PublicParams, SecretParams},
+};
+use rand::{CryptoRng, Rng};
+
+/// Secret key with everything its self signatures will bind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedKey {
+    pub primary_key: packet::SecretKey,
+    /// Primary user ID
+    pub uid: String,
+    /// Further user IDs
+    pub user_ids: Vec<String>,
+    /// Capabilities of the primary key
+    pub key_flags: KeyFlags,
+    /// Preferred algorithms, empty lists if missing
+    pub preferences: Option<Preferences>,
+    /// Days after its creation the key expires, it never does if missing
+    pub expiration_days: Option<u16>,
+    pub secret_subkeys: Vec<UnsignedSubkey>,
+}
+
+/// Secret subkey and the capabilities its binding signature will give it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedSubkey {
+    pub key: packet::SecretSubkey,
+    pub key_flags: KeyFlags,
+}
+
+impl UnsignedKey {
+    /// Fresh key described by `key` created at `created_at`, the key material
+    /// of the primary key and the subkey both drawn from `rng`
+    pub fn generate<R: Rng + CryptoRng>(
+        key: &KeyConfig,
+        created_at: DateTime<Utc>,
+        rng: &mut R,
+    ) -> Result<Self, ApgpkError> {
+        let (public_params, secret_params) = key.key_type.generate_with_rng(rng, None)?;
+        let public = packet::PublicKey::new(
+            Default::default(),
+            KeyVersion::V4,
+            key.key_type.to_alg(),
+            created_at,
+            None,
+            public_params,
+        )?;
+        let mut k = Self::from_primary(key, secret_key(&public, &secret_params)?);
+        if key.encryption_subkey {
+            k.secret_subkeys
+                .push(UnsignedSubkey::encryption(created_at, rng)?);
+        }
+        Ok(k)
+    }
+
+    /// Key described by `key` around an existing primary key, without any
+    /// subkey
+    pub fn from_primary(key: &KeyConfig, primary_key: packet::SecretKey) -> Self {
+        let mut key_flags = KeyFlags::default();
+        key_flags.set_certify(key.can_certify);
+        key_flags.set_sign(key.can_sign);
+        key_flags.set_encrypt_comms(key.can_encrypt);
+        key_flags.set_encrypt_storage(key.can_encrypt);
+        key_flags.set_authentication(key.can_authenticate);
+        Self {
+            primary_key,
+            uid: key.uid.clone(),
+            user_ids: key.user_ids.clone(),
+            key_flags,
+            preferences: key.preferences.clone(),
+            expiration_days: key.expiration_days,
+            secret_subkeys: vec![],
+        }
+    }
+
+    /// The key with its self signatures: a certification of every user ID,
+    /// the primary one first, and a binding signature of every subkey
+    pub fn sign(&self) -> Result<SignedSecretKey, ApgpkError> {
+        let key = &self.primary_key;
+        let now = Utc::now().trunc_subsecs(0);
+        let users = std::iter::once(&self.uid)
+            .chain(&self.user_ids)
+            .enumerate()
+            .map(|(i, id)| {
+                let id = UserId::from_str(Default::default(), id);
+                let mut hashed = vec![];
+                if i == 0 {
+                    hashed.push(Subpacket::regular(SubpacketData::IsPrimary(true)));
+                }
+                hashed.extend(self.certification_subpackets(now));
+                let sig = self.signature_config(SignatureType::CertGeneric, hashed);
+                let sig = sig.sign_certificate(key, String::new, id.tag(), &id)?;
+                Ok(id.into_signed(sig))
+            })
+            .collect::<Result<Vec<_>, ApgpkError>>()?;
+        let subkeys = self
+            .secret_subkeys
+            .iter()
+            .map(|s| {
+                let hashed = vec![
+                    Subpacket::regular(SubpacketData::SignatureCreationTime(now)),
+                    Subpacket::regular(SubpacketData::KeyFlags(s.key_flags.into())),
+                    self.issuer_fingerprint(),
+                ];
+                let sig = self.signature_config(SignatureType::SubkeyBinding, hashed);
+                let sig = sig.sign_key_binding(key, String::new, &s.key)?;
+                Ok(SignedSecretSubKey::new(s.key.clone(), vec![sig]))
+            })
+            .collect::<Result<Vec<_>, ApgpkError>>()?;
+        Ok(SignedSecretKey::new(
+            key.clone(),
+            SignedKeyDetails::new(vec![], vec![], users, vec![]),
+            vec![],
+            subkeys,
+        ))
+    }
+
+    /// Hashed subpackets of a user ID certification besides `IsPrimary`. The
+    /// key expiration time is written here, pgp's own signing drops it.
+    fn certification_subpackets(&self, now: DateTime<Utc>) -> Vec<Subpacket> {
+        let prefs = self.preferences.as_ref();
+        let mut hashed = vec![
+            Subpacket::regular(SubpacketData::SignatureCreationTime(now)),
+            Subpacket::regular(SubpacketData::KeyFlags(self.key_flags.into())),
+            Subpacket::regular(SubpacketData::PreferredSymmetricAlgorithms(
+                prefs
+                    .map(|p| p.symmetric.as_slice().into())
+                    .unwrap_or_default(),
+            )),
+            Subpacket::regular(SubpacketData::PreferredHashAlgorithms(
+                prefs.map(|p| p.hash.as_slice().into()).unwrap_or_default(),
+            )),
+            Subpacket::regular(SubpacketData::PreferredCompressionAlgorithms(
+                prefs
+                    .map(|p| p.compression.as_slice().into())
+                    .unwrap_or_default(),
+            )),
+            self.issuer_fingerprint(),
+        ];
+        if let Some(days) = self.expiration_days {
+            // seconds after the creation time, as a timestamp
+            let secs = Utc.timestamp_opt(days as i64 * 24 * 60 * 60, 0).unwrap();
+            hashed.push(Subpacket::regular(SubpacketData::KeyExpirationTime(secs)));
+        }
+        hashed
+    }
+
+    fn issuer_fingerprint(&self) -> Subpacket {
+        Subpacket::regular(SubpacketData::IssuerFingerprint(
+            Default::default(),
+            self.fingerprint().as_slice().into(),
+        ))
+    }
+
+    /// Self signature of type `typ` issued by the primary key. P-384 keys
+    /// sign with SHA-384, as the digest shouldn't be shorter than the curve.
+    fn signature_config(&self, typ: SignatureType, hashed: Vec<Subpacket>) -> SignatureConfig {
+        let hash = match self.primary_key.public_params() {
+            PublicParams::ECDSA(EcdsaPublicParams::P384 { .. }) => HashAlgorithm::SHA2_384,
+            _ => HashAlgorithm::SHA2_256,
+        };
+        SignatureConfig::new_v4(
+            SignatureVersion::V4,
+            typ,
+            self.algorithm(),
+            hash,
+            hashed,
+            vec![Subpacket::regular(SubpacketData::Issuer(self.key_id()))],
+        )
+    }
+}
+
+impl UnsignedSubkey {
+    /// Fresh Cv25519 encryption subkey created at `created_at`
+    pub fn encryption<R: Rng + CryptoRng>(
+        created_at: DateTime<Utc>,
+        rng: &mut R,
+    ) -> Result<Self, ApgpkError> {
+        let (public_params, secret_params) = KeyType::ECDH.generate_with_rng(rng, None)?;
+        let public = packet::PublicSubkey::new(
+            Default::default(),
+            KeyVersion::V4,
+            PublicKeyAlgorithm::ECDH,
+            created_at,
+            None,
+            public_params,
+        )?;
+        let mut key_flags = KeyFlags::default();
+        key_flags.set_encrypt_comms(true);
+        key_flags.set_encrypt_storage(true);
+        Ok(Self {
+            key: secret_subkey(&public, &secret_params)?,
+            key_flags,
+        })
+    }
+}
+
+impl KeyTrait for UnsignedKey {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.primary_key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.primary_key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.primary_key.algorithm()
+    }
+}
+
+impl KeyTrait for UnsignedSubkey {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.key.algorithm()
+    }
+}
+
+/// Secret key packet of `public` and `secret`. pgp has no constructor for
+/// it, so the packet body is serialized and parsed back.
+pub(crate) fn secret_key(
+    public: &packet::PublicKey,
+    secret: &SecretParams,
+) -> Result<packet::SecretKey, ApgpkError> {
+    let body = body(public, secret)?;
+    Ok(packet::SecretKey::from_slice(
+        public.packet_version(),
+        &body,
+    )?)
+}
+
+/// [`secret_key`] for subkeys
+pub(crate) fn secret_subkey(
+    public: &packet::PublicSubkey,
+    secret: &SecretParams,
+) -> Result<packet::SecretSubkey, ApgpkError> {
+    let body = body(public, secret)?;
+    Ok(packet::SecretSubkey::from_slice(
+        public.packet_version(),
+        &body,
+    )?)
+}
+
+fn body(public: &impl Serialize, secret: &SecretParams) -> Result<Vec<u8>, ApgpkError> {
+    let mut body = public.to_bytes()?;
+    secret.to_writer(&mut body)?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgp::Deserializable;
+
+    #[test]
+    fn test_sign() {
+        let key = KeyConfig {
+            user_ids: vec!["Me <me@example.org>".to_string()],
+            encryption_subkey: true,
+            ..KeyConfig::new("Me <me@example.com>".to_string())
+        };
+        let now = Utc::now().trunc_subsecs(0);
+        let k = UnsignedKey::generate(&key, now, &mut rand::thread_rng()).unwrap();
+        assert_eq!(k.primary_key.created_at(), &now);
+        let armored = k.sign().unwrap().to_armored_string(None).unwrap();
+        let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.fingerprint(), k.fingerprint());
+        assert_eq!(read.details.users[0].id.id(), "Me <me@example.com>");
+        assert_eq!(read.details.users[1].id.id(), "Me <me@example.org>");
+        assert_eq!(
+            read.secret_subkeys[0].key.fingerprint(),
+            k.secret_subkeys[0].fingerprint()
+        );
+        assert!(read.secret_subkeys[0].signatures[0]
+            .key_flags()
+            .encrypt_comms());
+    }
+}
@@ -1,27 +1,265 @@
-use crate::error::ApgpkError;
+use crate::{
+    config::{self, KeyConfig},
+    core::{Literal, MatchMode, Pattern, PatternSpec, Target, FP_HEX_LEN},
+    error::ApgpkError,
+    estimate, filename, keygen, keygrip,
+    notation::{self, Notation},
+    patterns, protect,
+    revoker::{self, Revoker},
+    unsigned::{UnsignedKey, UnsignedSubkey},
+};
+
+/// Literal patterns shorter than this are ignored
+pub const MIN_PATTERN_LEN: usize = 5;
+use chrono::{Local, SubsecRound, Utc};
 use hex::ToHex;
-use pgp::{composed::key::SecretKey, types::KeyTrait};
+use pgp::{
+    armor::{self, BlockType},
+    composed::{message::Message, signed_key::SignedSecretKey},
+    crypto::hash::HashAlgorithm,
+    packet::{self, SignatureConfig, SignatureType, SignatureVersion, Subpacket, SubpacketData},
+    ser::Serialize,
+    types::KeyTrait,
+    Deserializable,
+};
+use regex::RegexBuilder;
+use serde::Deserialize;
 use std::{
+    collections::BTreeMap,
     fs,
     io::{self, BufRead},
     path::Path,
+    str::FromStr,
 };
 
-pub fn key2hex(k: &SecretKey) -> String {
+pub fn key2hex(k: &UnsignedKey) -> String {
     k.fingerprint().encode_hex_upper::<String>()
 }
 
-pub fn save_key(k: &SecretKey, dir: impl AsRef<Path>) -> Result<String, ApgpkError> {
-    let armored_key = k.to_owned().sign(String::new)?.to_armored_string(None)?;
+/// Digit substitutions between the closest fingerprint of the key, or its
+/// keygrip, and a [`Pattern::Fuzzy`], see [`Pattern::distance`]
+pub fn match_distance(k: &UnsignedKey, key: &KeyConfig, p: &Pattern) -> Option<usize> {
+    let values = if key.match_keygrip {
+        keygen::matched_values(k, key)
+    } else {
+        std::iter::once(k.fingerprint())
+            .chain(k.secret_subkeys.iter().map(|s| s.fingerprint()))
+            .collect()
+    };
+    values.iter().filter_map(|v| p.distance(v)).min()
+}
+
+/// Key type of the primary key as [`config::parse_key_type`] takes it
+pub fn algorithm(k: &UnsignedKey) -> Option<String> {
+    config::key_type_of(k.primary_key.public_params()).map(|t| config::key_type_id(&t))
+}
+
+/// Upper case hex keygrip of the primary key, see [`crate::keygrip`]
+pub fn keygrip_hex(k: &UnsignedKey) -> Option<String> {
+    keygrip::keygrip(k.primary_key.public_params()).map(hex::encode_upper)
+}
+
+pub fn save_key(k: &UnsignedKey, dir: impl AsRef<Path>) -> Result<String, ApgpkError> {
+    save_key_with(k, dir, &SaveOptions::default())
+}
+
+/// How [`save_key_with`] exports a key
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    /// Protect the secret key with this passphrase, see [`protect::protect`]
+    pub passphrase: Option<String>,
+    /// Also write a revocation certificate, see [`revocation_cert`]
+    pub revocation_cert: bool,
+    /// Keys allowed to revoke the key, see [`revoker::designate`]
+    pub revokers: Vec<Revoker>,
+    /// Which exports of the key are written
+    pub export: Export,
+    /// Notations in the self signatures, see [`notation::notate`]
+    pub notations: Vec<Notation>,
+}
+
+/// Exports of a key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Export {
+    /// Everything: all user IDs, subkeys and direct-key signatures
+    #[default]
+    Full,
+    /// Only the primary key and user ID with its self signature, see
+    /// [`minimal`]
+    Minimal,
+    /// The full export and the minimal one as `<FINGERPRINT>.min.asc`
+    Both,
+}
+
+impl FromStr for Export {
+    type Err = ApgpkError;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "full" => Ok(Export::Full),
+            "minimal" => Ok(Export::Minimal),
+            "both" => Ok(Export::Both),
+            _ => Err(ApgpkError::Other(format!(
+                "Unknown export `{}`, expect full, minimal or both",
+                s
+            ))),
+        }
+    }
+}
+
+/// [`save_key`] as `opts` ask for
+pub fn save_key_with(
+    k: &UnsignedKey,
+    dir: impl AsRef<Path>,
+    opts: &SaveOptions,
+) -> Result<String, ApgpkError> {
+    let signed = sign(k, opts)?;
     let fp = k.fingerprint().encode_hex_upper::<String>();
-    let filename = format!("{}.asc", &fp);
-    let path = dir.as_ref().join(filename);
+    if opts.revocation_cert {
+        let path = dir.as_ref().join(filename::revocation_file_name(&fp));
+        std::fs::write(path, revocation_cert(&signed)?)?;
+    }
+    let passphrase = opts.passphrase.as_deref();
+    if opts.export == Export::Both {
+        let path = dir.as_ref().join(filename::minimal_file_name(&fp));
+        std::fs::write(path, armor_signed(minimal(signed.clone()), passphrase)?)?;
+    }
+    let signed = match opts.export {
+        Export::Minimal => minimal(signed),
+        Export::Full | Export::Both => signed,
+    };
+    let armored_key = armor_signed(signed, passphrase)?;
+
+    let path = dir.as_ref().join(filename::key_file_name(&fp));
 
     std::fs::write(path, armored_key)?;
     Ok(fp)
 }
 
+/// The smallest transferable secret key of `signed`: the primary key and
+/// the primary user ID with its self signature. Further user IDs, subkeys
+/// and direct-key signatures are dropped.
+pub fn minimal(mut signed: SignedSecretKey) -> SignedSecretKey {
+    signed.details.users.truncate(1);
+    signed.details.user_attributes.clear();
+    signed.details.direct_signatures.clear();
+    signed.public_subkeys.clear();
+    signed.secret_subkeys.clear();
+    signed
+}
+
+/// Armored secret key as [`save_key_with`] writes it, protected with
+/// `passphrase` if given
+pub fn export_key(k: &UnsignedKey, passphrase: Option<&str>) -> Result<String, ApgpkError> {
+    let opts = SaveOptions {
+        passphrase: passphrase.map(str::to_string),
+        ..Default::default()
+    };
+    export_key_with(k, &opts)
+}
+
+/// [`export_key`] as `opts` ask for, without the revocation certificate.
+/// [`Export::Both`] exports the full key.
+pub fn export_key_with(k: &UnsignedKey, opts: &SaveOptions) -> Result<String, ApgpkError> {
+    let signed = match opts.export {
+        Export::Minimal => minimal(sign(k, opts)?),
+        Export::Full | Export::Both => sign(k, opts)?,
+    };
+    armor_signed(signed, opts.passphrase.as_deref())
+}
+
+/// Self-signed key with the signatures `opts` ask for
+fn sign(k: &UnsignedKey, opts: &SaveOptions) -> Result<SignedSecretKey, ApgpkError> {
+    let signed = notation::notate(k.sign()?, &opts.notations)?;
+    revoker::designate(signed, &opts.revokers)
+}
+
+fn armor_signed(
+    mut signed: SignedSecretKey,
+    passphrase: Option<&str>,
+) -> Result<String, ApgpkError> {
+    if let Some(passphrase) = passphrase {
+        signed = protect::protect(signed, passphrase)?;
+    }
+    Ok(signed.to_armored_string(None)?)
+}
+
+/// Armored revocation certificate of the unprotected key, like
+/// `gpg --gen-revoke` makes it: a key revocation signature without a
+/// reason, which `gpg --import` applies to revoke the key.
+pub fn revocation_cert(k: &SignedSecretKey) -> Result<String, ApgpkError> {
+    let hashed = vec![Subpacket::regular(SubpacketData::SignatureCreationTime(
+        Utc::now().trunc_subsecs(0),
+    ))];
+    let unhashed = vec![Subpacket::regular(SubpacketData::Issuer(k.key_id()))];
+    let sig = SignatureConfig::new_v4(
+        SignatureVersion::V4,
+        SignatureType::KeyRevocation,
+        k.algorithm(),
+        HashAlgorithm::SHA2_256,
+        hashed,
+        unhashed,
+    )
+    .sign_key(k, String::new, k)?;
+
+    let mut packet = vec![];
+    packet::write_packet(&mut packet, &sig)?;
+    let headers = BTreeMap::from([(
+        "Comment".to_string(),
+        "This is a revocation certificate".to_string(),
+    )]);
+    let mut armored = vec![];
+    armor::write(
+        &RawPackets(packet),
+        BlockType::PublicKey,
+        &mut armored,
+        Some(&headers),
+    )?;
+    String::from_utf8(armored).map_err(|e| ApgpkError::Other(e.to_string()))
+}
+
+/// Check an exported key works: it parses, its self signatures verify, and
+/// a message signed with it verifies against it
+pub fn self_check(armored: &str, passphrase: Option<&str>) -> Result<(), ApgpkError> {
+    let (key, _) = SignedSecretKey::from_string(armored)?;
+    key.verify()?;
+    let pw = || passphrase.unwrap_or_default().to_string();
+    let signed = Message::new_literal("self-check", "apgpk self-check")
+        .sign(&key, pw, HashAlgorithm::SHA2_256)?
+        .to_armored_string(None)?;
+    let (read, _) = Message::from_string(&signed)?;
+    read.verify(&key)?;
+    Ok(())
+}
+
+/// Packets serialized already, to armor them
+struct RawPackets(Vec<u8>);
+
+impl Serialize for RawPackets {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> pgp::errors::Result<()> {
+        writer.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
+/// The key with `uid` as primary user ID instead, its further user IDs are
+/// kept. The fingerprint stays the same, it only covers the key material and
+/// creation time.
+pub fn with_uid(k: UnsignedKey, uid: &str) -> UnsignedKey {
+    UnsignedKey {
+        uid: uid.to_string(),
+        ..k
+    }
+}
+
+/// The key with a fresh Cv25519 encryption subkey added, bound by the
+/// primary key once it is signed for export. The fingerprint stays the same.
+pub fn with_encryption_subkey(mut k: UnsignedKey) -> UnsignedKey {
+    let subkey = UnsignedSubkey::encryption(Utc::now().trunc_subsecs(0), &mut rand::thread_rng());
+    k.secret_subkeys.push(subkey.unwrap()); // Cv25519 keys can always be made
+    k
+}
+
 pub fn check_output_dir<T>(path: T) -> Result<(), ApgpkError>
 where
     T: AsRef<Path>,
@@ -41,11 +279,78 @@ where
     Ok(())
 }
 
-pub fn parse_pattern<T>(path: T) -> Result<Vec<String>, ApgpkError>
+/// Directory name of the keys found by a pattern, e.g. `CAFE1234` for the
+/// suffix `*CAFE1234`. Characters other than letters and digits become `_`,
+/// and long regexes are cut to [`filename::MAX_NAME_LEN`].
+pub fn pattern_dir_name(p: &Pattern) -> String {
+    let name: String = p
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    filename::NamePolicy::default().sanitize(name.trim_matches('_'))
+}
+
+/// How the lines of a pattern file are interpreted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatternOptions {
+    /// Treat every line as a regular expression
+    pub regex: bool,
+    /// Default anchor of literal patterns
+    pub mode: MatchMode,
+    /// Part of the fingerprint patterns are matched against
+    pub target: Target,
+    /// Keep literal patterns shorter than [`MIN_PATTERN_LEN`] instead of
+    /// rejecting them
+    pub allow_short: bool,
+    /// Only accept literals starting on a 4-digit block boundary
+    pub align_blocks: bool,
+    /// Accept fingerprints up to this many digit substitutions off plain
+    /// literal patterns, see [`Pattern::Fuzzy`]
+    pub max_distance: usize,
+}
+
+impl PatternOptions {
+    /// Literal pattern matched against `self.target`
+    pub fn literal(&self, text: &str, mode: MatchMode) -> Pattern {
+        let l = Literal::new(text, mode, self.target);
+        if !self.align_blocks {
+            return Pattern::Literal(l);
+        }
+        let l = l.align_blocks();
+        if l.probability() == 0.0 {
+            log::warn!(
+                "Pattern `{}` can't start on a block boundary and will never match",
+                text
+            );
+        }
+        Pattern::Literal(l)
+    }
+}
+
+/// Parse the pattern file, one pattern per line.
+///
+/// Lines starting with `re:` are always treated as regular expressions, other
+/// lines are literals anchored according to `opts.mode` unless `opts.regex`
+/// is set. A literal line can pick its own anchor with a mode prefix such as
+/// `anywhere:CAFE` or `prefix:CAFE`. Lines prefixed with `!` are exclusions,
+/// e.g. `!DEAD` rejects every fingerprint containing `DEAD`. Literals joined
+/// by `&` must all match the same key, e.g. `prefix:AAAA & suffix:FFFF`, and
+/// lines starting with `expr:` are [`patterns::expr`] expressions. With
+/// `opts.max_distance` plain literals become [`Pattern::Fuzzy`]. Placeholders
+/// such as `{YEAR}` are expanded first, see [`patterns::template`].
+///
+/// Literals shorter than [`MIN_PATTERN_LEN`] are an error unless
+/// `opts.allow_short` is set, as are literals with characters other than hex
+/// digits and `?`. A path of `-` reads the patterns from stdin, a path ending
+/// in `.toml` is parsed with [`parse_pattern_toml`].
+pub fn parse_pattern<T>(path: T, opts: PatternOptions) -> Result<Vec<PatternSpec>, ApgpkError>
 where
     T: AsRef<Path>,
 {
-    let mut pattern = vec![];
+    if path.as_ref() == Path::new("-") {
+        return parse_pattern_reader(io::stdin().lock(), opts);
+    }
 
     if !path.as_ref().exists() {
         return Err(ApgpkError::Other(format!(
@@ -61,34 +366,662 @@ where
         )));
     }
 
+    if path.as_ref().extension().is_some_and(|ext| ext == "toml") {
+        return parse_pattern_toml(&fs::read_to_string(path.as_ref())?, opts);
+    }
+
     let f = fs::File::open(path.as_ref())?;
-    let lines = io::BufReader::new(f).lines();
-    let mut short_pattern_warning = false;
-    for line in lines {
-        let line = line?.trim().to_uppercase();
+    parse_pattern_reader(io::BufReader::new(f), opts)
+}
+
+/// Parse patterns line by line from `reader`, see [`parse_pattern`].
+pub fn parse_pattern_reader<R>(
+    reader: R,
+    opts: PatternOptions,
+) -> Result<Vec<PatternSpec>, ApgpkError>
+where
+    R: BufRead,
+{
+    let entries = reader
+        .lines()
+        .map(|line| {
+            line.map(|pattern| PatternEntry {
+                pattern,
+                ..Default::default()
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    parse_entries(entries, opts, "line")
+}
+
+/// One `[[pattern]]` table of a TOML pattern file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternEntry {
+    pattern: String,
+    mode: Option<MatchMode>,
+    label: Option<String>,
+    quota: Option<usize>,
+    uid: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    max_distance: Option<usize>,
+}
+
+impl PatternEntry {
+    fn into_spec(self, pattern: Pattern) -> PatternSpec {
+        PatternSpec {
+            pattern,
+            label: self.label,
+            quota: self.quota,
+            uid: self.uid,
+            priority: self.priority,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternFile {
+    #[serde(default)]
+    pattern: Vec<PatternEntry>,
+}
+
+/// Parse a TOML pattern file holding a table per pattern, e.g.
+///
+/// ```toml
+/// [[pattern]]
+/// pattern = "CAFEBABE"
+/// mode = "anywhere"
+/// label = "cafe"
+/// quota = 3
+/// uid = "Cafe <cafe@example.org>"
+/// priority = 10
+/// max_distance = 1
+/// ```
+///
+/// Only `pattern` is required, it is parsed like a line of a plain pattern
+/// file. The patterns are returned by descending priority.
+pub fn parse_pattern_toml(
+    text: &str,
+    opts: PatternOptions,
+) -> Result<Vec<PatternSpec>, ApgpkError> {
+    let file: PatternFile = toml::from_str(text)
+        .map_err(|e| ApgpkError::Other(format!("Invalid TOML pattern file: {}", e)))?;
+    let mut specs = parse_entries(file.pattern, opts, "pattern")?;
+    specs.sort_by_key(|s| std::cmp::Reverse(s.priority));
+    Ok(specs)
+}
+
+/// Parse the entries of a pattern file, numbered as `unit` in errors
+fn parse_entries(
+    entries: Vec<PatternEntry>,
+    opts: PatternOptions,
+    unit: &str,
+) -> Result<Vec<PatternSpec>, ApgpkError> {
+    let mut specs = vec![];
+    let mut short_patterns = vec![];
+    let mut invalid = vec![];
+    let mut expanded = vec![];
+    let today = Local::now().date_naive();
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        if let Some(uid) = &entry.uid {
+            config::validate_uid(uid)
+                .map_err(|e| ApgpkError::Other(format!("{} {}: {}", unit, i + 1, e)))?;
+        }
+        let template = std::mem::take(&mut entry.pattern);
+        let text = patterns::template::expand(&template, today)?;
+        if text != template {
+            expanded.push(format!("{} => {}", template.trim(), text.trim()));
+        }
+        let line = text.trim();
+        let opts = PatternOptions {
+            mode: entry.mode.unwrap_or(opts.mode),
+            max_distance: entry.max_distance.unwrap_or(opts.max_distance),
+            ..opts
+        };
+        // exclusions reject any fingerprint containing them unless a mode is given
+        let (line, exclude, default_mode) = match line.strip_prefix('!') {
+            Some(rest) => (rest.trim(), true, entry.mode.unwrap_or(MatchMode::Anywhere)),
+            None => (line, false, opts.mode),
+        };
+        let wrap = |p: Pattern| {
+            if exclude {
+                Pattern::Exclude(Box::new(p))
+            } else {
+                p
+            }
+        };
+        if let Some(expr) = line.strip_prefix("re:") {
+            specs.push(entry.into_spec(wrap(parse_regex(expr, opts.target)?)));
+            continue;
+        }
+        if let Some(text) = line.strip_prefix("expr:") {
+            let expr = patterns::expr::parse(text, &opts)?;
+            let max = 16f64.powi(-(MIN_PATTERN_LEN as i32));
+            if !opts.allow_short
+                && !exclude
+                && estimate::probability(&expr).is_some_and(|p| p > max)
+            {
+                return Err(ApgpkError::Other(format!(
+                    "Expression `{}` would match too many keys, pass `--allow-short-patterns` to keep it",
+                    expr
+                )));
+            }
+            specs.push(entry.into_spec(wrap(expr)));
+            continue;
+        }
+        if opts.regex && !line.is_empty() {
+            specs.push(entry.into_spec(wrap(parse_regex(line, opts.target)?)));
+            continue;
+        }
+        if line.contains('&') {
+            let opts = PatternOptions {
+                allow_short: opts.allow_short || exclude,
+                ..opts
+            };
+            let compound = parse_compound(line, default_mode, opts)?;
+            specs.push(entry.into_spec(wrap(compound)));
+            continue;
+        }
+        let (line, line_mode) = split_mode(line, default_mode)?;
+        if !line.chars().all(|c| c.is_ascii_hexdigit() || c == '?') {
+            invalid.push(invalid_line(unit, i + 1, &line));
+            continue;
+        }
         match line.len() {
             0 => {}
-            1..=4 => {
-                short_pattern_warning = true;
+            n if n < MIN_PATTERN_LEN && !opts.allow_short && !exclude => {
+                return Err(ApgpkError::Other(format!(
+                    "Pattern `{}` is shorter than {} hex digits and would match too many keys, pass `--allow-short-patterns` to keep it",
+                    line, MIN_PATTERN_LEN
+                )));
+            }
+            n if n > opts.target.hex_len() => {
+                return Err(ApgpkError::Other(format!(
+                    "Pattern `{}` is longer than the {} hex digits of the target",
+                    line,
+                    opts.target.hex_len()
+                )));
             }
-            _ => {
-                pattern.push(line);
+            n if opts.max_distance >= n && !exclude => {
+                return Err(ApgpkError::Other(format!(
+                    "Pattern `{}` is within {} substitutions of any fingerprint",
+                    line, opts.max_distance
+                )));
+            }
+            n => {
+                if n < MIN_PATTERN_LEN && !exclude {
+                    short_patterns.push(line.clone());
+                }
+                let pattern = match opts.literal(&line, line_mode) {
+                    Pattern::Literal(l) if opts.max_distance > 0 && !exclude => {
+                        Pattern::Fuzzy(l, opts.max_distance)
+                    }
+                    p => p,
+                };
+                specs.push(entry.into_spec(wrap(pattern)));
             }
         }
     }
 
-    if short_pattern_warning {
-        log::warn!("Too short(<=4) patterns are included, this may cause perfermance issue. For secure those patterns are ignored")
+    if !invalid.is_empty() {
+        return Err(ApgpkError::Other(format!(
+            "Patterns may only contain hex digits and `?`, invalid {}s:\n{}",
+            unit,
+            invalid.join("\n")
+        )));
+    }
+
+    if !expanded.is_empty() {
+        log::info!("Expanded placeholders: {:?}", expanded);
     }
 
-    if pattern.is_empty() {
+    if !short_patterns.is_empty() {
+        log::warn!(
+            "!!! Short patterns {:?} are kept, they match about one key in every {} and may flood the output directory",
+            short_patterns,
+            16usize.pow(short_patterns.iter().map(|p| p.len()).min().unwrap_or(0) as u32)
+        );
+    }
+
+    if specs
+        .iter()
+        .all(|s| matches!(s.pattern, Pattern::Exclude(_)))
+    {
         let default_pattern = "ABCDEF".to_string();
         log::warn!(
             "Warning: No pattern found, use default pattern `{}`",
             default_pattern
         );
-        pattern.push(default_pattern);
+        specs.push(PatternSpec::new(opts.literal(&default_pattern, opts.mode)));
+    }
+
+    Ok(specs)
+}
+
+/// Parse literals joined by `&`, e.g. `prefix:AAAA & suffix:FFFF`, into a
+/// pattern matching if all of them do. The minimal length applies to their
+/// digits in total.
+fn parse_compound(
+    line: &str,
+    default_mode: MatchMode,
+    opts: PatternOptions,
+) -> Result<Pattern, ApgpkError> {
+    let mut parts = vec![];
+    let mut digits = 0;
+    for part in line.split('&') {
+        let (text, mode) = split_mode(part, default_mode)?;
+        if text.is_empty()
+            || text.len() > opts.target.hex_len()
+            || !text.chars().all(|c| c.is_ascii_hexdigit() || c == '?')
+        {
+            return Err(ApgpkError::Other(format!(
+                "Invalid part `{}` of the compound pattern `{}`",
+                part.trim(),
+                line
+            )));
+        }
+        digits += text.len();
+        parts.push(opts.literal(&text, mode));
+    }
+    if digits < MIN_PATTERN_LEN && !opts.allow_short {
+        return Err(ApgpkError::Other(format!(
+            "Pattern `{}` has fewer than {} hex digits and would match too many keys, pass `--allow-short-patterns` to keep it",
+            line, MIN_PATTERN_LEN
+        )));
+    }
+    Ok(Pattern::All(parts))
+}
+
+/// Split the mode prefix off a literal and normalize the rest with
+/// [`normalize_fragment`]. Hex digits before a colon are part of a pasted
+/// fragment such as `AB:CD:EF`, not a mode.
+fn split_mode(text: &str, default_mode: MatchMode) -> Result<(String, MatchMode), ApgpkError> {
+    let (text, mode) = match text.trim().split_once(':') {
+        Some((m, rest)) if !is_fragment(m) => (rest, m.parse::<MatchMode>()?),
+        _ => (text, default_mode),
+    };
+    Ok((normalize_fragment(text).to_uppercase(), mode))
+}
+
+/// A fingerprint fragment as pasted from `gpg --fingerprint` and the like,
+/// e.g. `0xABCD EF12` or `AB:CD:EF`, without its spaces, colons and `0x`
+pub fn normalize_fragment(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(rest) => rest.to_string(),
+        None => text,
+    }
+}
+
+fn is_fragment(text: &str) -> bool {
+    let text = normalize_fragment(text);
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit() || c == '?')
+}
+
+/// Describe an invalid literal, suggesting its hex look-alike if it has one
+fn invalid_line(unit: &str, number: usize, line: &str) -> String {
+    match patterns::leet(line) {
+        Some(hex) => format!("  {} {}: `{}`, did you mean `{}`?", unit, number, line, hex),
+        None => format!("  {} {}: `{}`", unit, number, line),
+    }
+}
+
+/// Read a plain word list, one word per line, and convert the words having
+/// a hex look-alike into patterns.
+pub fn parse_wordlist<T>(path: T, opts: PatternOptions) -> Result<Vec<Pattern>, ApgpkError>
+where
+    T: AsRef<Path>,
+{
+    let f = fs::File::open(path.as_ref())?;
+    let words = io::BufReader::new(f)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?;
+    let total = words.iter().filter(|w| !w.trim().is_empty()).count();
+    let min_len = if opts.allow_short { 1 } else { MIN_PATTERN_LEN };
+    let converted = patterns::leet_words(&words, min_len);
+    log::info!(
+        "{} of {} words converted to hex patterns",
+        converted.len(),
+        total
+    );
+    Ok(converted
+        .iter()
+        .map(|p| opts.literal(p, opts.mode))
+        .collect())
+}
+
+/// Suffix pattern imitating the last `len` hex digits of an existing
+/// fingerprint, which may be given with spaces as printed by GnuPG.
+pub fn imitation_pattern(fingerprint: &str, len: usize) -> Result<Pattern, ApgpkError> {
+    let fp: String = fingerprint
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if fp.len() != FP_HEX_LEN || !fp.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ApgpkError::Other(format!(
+            "`{}` isn't a {}-digit hex fingerprint",
+            fingerprint, FP_HEX_LEN
+        )));
+    }
+    if !(1..=FP_HEX_LEN).contains(&len) {
+        return Err(ApgpkError::Other(format!(
+            "Imitated length must be within 1..={}, got {}",
+            FP_HEX_LEN, len
+        )));
+    }
+    Ok(Pattern::literal(&fp[FP_HEX_LEN - len..], MatchMode::Suffix))
+}
+
+fn parse_regex(expr: &str, target: Target) -> Result<Pattern, ApgpkError> {
+    let re = RegexBuilder::new(expr.trim())
+        .case_insensitive(true)
+        .build()?;
+    Ok(Pattern::Regex(re, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keygen::{KeyGenerator, PgpKeyGenerator};
+
+    #[test]
+    fn test_parse_pattern_reader() {
+        let input = "CAFEBABE\nanywhere:C0FFEE\nre:^DEAD\n\n";
+        let pattern = parse_pattern_reader(input.as_bytes(), PatternOptions::default()).unwrap();
+        let shown: Vec<_> = pattern.iter().map(|p| p.pattern.to_string()).collect();
+        assert_eq!(shown, ["*CAFEBABE", "*C0FFEE*", "re:^DEAD"]);
+        assert!(parse_pattern_reader("ABC".as_bytes(), PatternOptions::default()).is_err());
+
+        let input = "CAFE5\n!DEAD\n!re:BEEF$\n!suffix:BEEF0";
+        let pattern = parse_pattern_reader(input.as_bytes(), PatternOptions::default()).unwrap();
+        let shown: Vec<_> = pattern.iter().map(|p| p.pattern.to_string()).collect();
+        assert_eq!(shown, ["*CAFE5", "!*DEAD*", "!re:BEEF$", "!*BEEF0"]);
+        assert_eq!(pattern_dir_name(&pattern[0].pattern), "CAFE5");
+        assert_eq!(pattern_dir_name(&pattern[2].pattern), "re_BEEF");
+
+        let input = "prefix:AAAA & FFFF\n!prefix:0 & 0";
+        let pattern = parse_pattern_reader(input.as_bytes(), PatternOptions::default()).unwrap();
+        let shown: Vec<_> = pattern.iter().map(|p| p.pattern.to_string()).collect();
+        assert_eq!(shown, ["AAAA* & *FFFF", "!0* & *0*"]);
+        assert!(parse_pattern_reader("AA & FF".as_bytes(), PatternOptions::default()).is_err());
+        let input = "expr:(ends CAFE00 OR ends BABE00) AND NOT contains 000000";
+        let pattern = parse_pattern_reader(input.as_bytes(), PatternOptions::default()).unwrap();
+        assert_eq!(
+            pattern[0].pattern.to_string(),
+            "(*CAFE00 | *BABE00) & not *000000*"
+        );
+        let input = "expr:ends CAFE OR ends BABE";
+        assert!(parse_pattern_reader(input.as_bytes(), PatternOptions::default()).is_err());
+        assert!(
+            parse_pattern_reader("AAAA & COFFEE".as_bytes(), PatternOptions::default()).is_err()
+        );
+
+        let input = "CAFEBABE\nCOFFEE\nHELLO7";
+        let err = parse_pattern_reader(input.as_bytes(), PatternOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("line 2: `COFFEE`, did you mean `C0FFEE`?"));
+        assert!(err.ends_with("line 3: `HELLO7`"));
+
+        // pasted fragments
+        let input = "ABCD EF12 3456\n0xC0FFEE\nprefix:AB:CD:EF\n0xDEAD BEEF & suffix:12 34 5";
+        let pattern = parse_pattern_reader(input.as_bytes(), PatternOptions::default()).unwrap();
+        let shown: Vec<_> = pattern.iter().map(|p| p.pattern.to_string()).collect();
+        assert_eq!(
+            shown,
+            ["*ABCDEF123456", "*C0FFEE", "ABCDEF*", "*DEADBEEF & *12345"]
+        );
+        assert!(parse_pattern_reader("prefx:CAFE5".as_bytes(), PatternOptions::default()).is_err());
     }
 
-    Ok(pattern)
+    #[test]
+    fn test_unicode_uid() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        let dir = std::env::temp_dir().join(format!("apgpk-uid-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for uid in [
+            "Jürgen Müller <j@example.org>",
+            "张伟 <zw@example.cn>",
+            "Ελένη 🦀",
+            "محمد",
+        ] {
+            let key = KeyConfig::new(uid.to_string());
+            key.validate().unwrap();
+            let json = serde_json::to_string(&key).unwrap();
+            assert_eq!(serde_json::from_str::<KeyConfig>(&json).unwrap(), key);
+
+            let now = Utc::now();
+            let k = PgpKeyGenerator::new(&key, now).generate(now);
+            let fp = save_key(&with_uid(k.clone(), uid), &dir).unwrap();
+            let path = dir.join(filename::key_file_name(&fp));
+            let (read, _) =
+                SignedSecretKey::from_string(&fs::read_to_string(&path).unwrap()).unwrap();
+            read.verify().unwrap();
+            assert_eq!(read.details.users[0].id.id(), uid);
+            assert_eq!(
+                filename::NamePolicy::default()
+                    .sanitize(uid)
+                    .chars()
+                    .count(),
+                uid.chars().count()
+            );
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_dir(dir).unwrap();
+
+        let input = "[[pattern]]\npattern = \"CAFEBABE\"\nuid = \"a\\nb\"\n";
+        assert!(parse_pattern_toml(input, PatternOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_pattern_toml() {
+        let input = r#"
+            [[pattern]]
+            pattern = "C0FFEE"
+
+            [[pattern]]
+            pattern = "CAFEBABE"
+            mode = "anywhere"
+            label = "cafe"
+            quota = 3
+            uid = "Cafe <cafe@example.org>"
+            priority = 10
+        "#;
+        let specs = parse_pattern_toml(input, PatternOptions::default()).unwrap();
+        let shown: Vec<_> = specs.iter().map(|s| s.pattern.to_string()).collect();
+        assert_eq!(shown, ["*CAFEBABE*", "*C0FFEE"]);
+        assert_eq!(specs[0].name(), "cafe");
+        assert_eq!(specs[0].quota, Some(3));
+        assert_eq!(specs[0].uid.as_deref(), Some("Cafe <cafe@example.org>"));
+        assert_eq!(specs[1].name(), "C0FFEE");
+
+        let fuzzy = "[[pattern]]\npattern = \"DEADBEEFCAFE\"\nmax_distance = 2";
+        let specs = parse_pattern_toml(fuzzy, PatternOptions::default()).unwrap();
+        assert_eq!(specs[0].pattern.to_string(), "*DEADBEEFCAFE~2");
+        let everything = "[[pattern]]\npattern = \"CAFE\"\nmax_distance = 4";
+        let opts = PatternOptions {
+            allow_short: true,
+            ..Default::default()
+        };
+        assert!(parse_pattern_toml(everything, opts).is_err());
+
+        let unknown = "[[pattern]]\npattern = \"C0FFEE\"\ncolor = \"red\"";
+        assert!(parse_pattern_toml(unknown, PatternOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_revocation_cert() {
+        use pgp::{
+            armor::Dearmor,
+            packet::{Packet, PacketParser},
+            types::SecretKeyTrait,
+        };
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("apgpk-rev-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = Utc::now();
+        let k = PgpKeyGenerator::new(&KeyConfig::default(), now).generate(now);
+        let opts = SaveOptions {
+            revocation_cert: true,
+            ..Default::default()
+        };
+        let fp = save_key_with(&k, &dir, &opts).unwrap();
+        let path = dir.join(filename::revocation_file_name(&fp));
+        let armored = fs::read_to_string(&path).unwrap();
+        assert!(armored.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+        assert!(armored.contains("Comment: This is a revocation certificate"));
+
+        let mut bytes = vec![];
+        Dearmor::new(std::io::Cursor::new(armored.as_bytes()))
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let packets: Vec<_> = PacketParser::new(bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let [Packet::Signature(sig)] = packets.as_slice() else {
+            panic!("expected one signature packet, got {:?}", packets);
+        };
+        assert_eq!(sig.typ(), SignatureType::KeyRevocation);
+        sig.verify_key(&k.primary_key.public_key()).unwrap();
+        fs::remove_file(path).unwrap();
+        fs::remove_file(dir.join(filename::key_file_name(&fp))).unwrap();
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_with_encryption_subkey() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        let dir = std::env::temp_dir().join(format!("apgpk-subkey-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = Utc::now();
+        let k = PgpKeyGenerator::new(&KeyConfig::default(), now).generate(now);
+        let fp = save_key(&with_encryption_subkey(k.clone()), &dir).unwrap();
+        assert_eq!(fp, key2hex(&k));
+        assert_eq!(algorithm(&k).as_deref(), Some("eddsa"));
+        assert!(self_check(&export_key(&k, Some("secret")).unwrap(), Some("secret")).is_ok());
+        assert!(self_check(&export_key(&k, Some("secret")).unwrap(), Some("wrong")).is_err());
+        assert!(self_check("not a key", None).is_err());
+        let path = dir.join(filename::key_file_name(&fp));
+        let (read, _) = SignedSecretKey::from_string(&fs::read_to_string(&path).unwrap()).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.secret_subkeys.len(), 1);
+        fs::remove_file(path).unwrap();
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_minimal_export() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        let dir = std::env::temp_dir().join(format!("apgpk-minimal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = KeyConfig {
+            user_ids: vec!["Me <me@example.org>".to_string()],
+            encryption_subkey: true,
+            ..KeyConfig::new("Me <me@example.com>".to_string())
+        };
+        let now = Utc::now();
+        let k = PgpKeyGenerator::new(&key, now).generate(now);
+        let opts = SaveOptions {
+            export: "both".parse().unwrap(),
+            ..Default::default()
+        };
+        let fp = save_key_with(&k, &dir, &opts).unwrap();
+        let read = |name: String| {
+            let path = dir.join(name);
+            let armored = fs::read_to_string(&path).unwrap();
+            fs::remove_file(path).unwrap();
+            let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+            read.verify().unwrap();
+            (read, armored.len())
+        };
+        let (full, full_len) = read(filename::key_file_name(&fp));
+        let (min, min_len) = read(filename::minimal_file_name(&fp));
+        assert_eq!(full.details.users.len(), 2);
+        assert_eq!(full.secret_subkeys.len(), 1);
+        assert_eq!(min.fingerprint(), full.fingerprint());
+        assert_eq!(min.details.users.len(), 1);
+        assert_eq!(min.details.users[0].id.id(), "Me <me@example.com>");
+        assert!(min.secret_subkeys.is_empty());
+        assert!(min_len < full_len);
+        assert!("tiny".parse::<Export>().is_err());
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_user_ids() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        let dir = std::env::temp_dir().join(format!("apgpk-user-ids-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = KeyConfig {
+            user_ids: vec!["Me <me@example.org>".to_string()],
+            ..KeyConfig::new("Me <me@example.com>".to_string())
+        };
+        key.validate().unwrap();
+        let now = Utc::now();
+        let k = PgpKeyGenerator::new(&key, now).generate(now);
+        let fp = save_key(&with_uid(k.clone(), "Work <me@example.net>"), &dir).unwrap();
+        let path = dir.join(filename::key_file_name(&fp));
+        let (read, _) = SignedSecretKey::from_string(&fs::read_to_string(&path).unwrap()).unwrap();
+        read.verify().unwrap();
+        let ids: Vec<_> = read.details.users.iter().map(|u| u.id.id()).collect();
+        assert_eq!(ids, ["Work <me@example.net>", "Me <me@example.org>"]);
+        fs::remove_file(path).unwrap();
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_expiration() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        let dir = std::env::temp_dir().join(format!("apgpk-expiration-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = KeyConfig {
+            expiration_days: Some(730),
+            ..Default::default()
+        };
+        key.validate().unwrap();
+        let now = Utc::now().trunc_subsecs(0);
+        let k = PgpKeyGenerator::new(&key, now).generate(now);
+        let fp = save_key(&k, &dir).unwrap();
+        let path = dir.join(filename::key_file_name(&fp));
+        let (read, _) = SignedSecretKey::from_string(&fs::read_to_string(&path).unwrap()).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.expires_at(), Some(now + chrono::Duration::days(730)));
+        fs::remove_file(path).unwrap();
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_ecdsa_key() {
+        use pgp::{composed::signed_key::SignedSecretKey, Deserializable};
+
+        for (name, subkey) in [("p256", false), ("p384", true)] {
+            let key = KeyConfig {
+                key_type: config::parse_key_type(name).unwrap(),
+                encryption_subkey: subkey,
+                ..Default::default()
+            };
+            key.validate().unwrap();
+            let now = Utc::now();
+            let k = PgpKeyGenerator::new(&key, now).generate(now);
+            assert_eq!(algorithm(&k).as_deref(), Some(name));
+            let armored = export_key(&k, Some("secret")).unwrap();
+            self_check(&armored, Some("secret")).unwrap();
+            let (read, _) = SignedSecretKey::from_string(&armored).unwrap();
+            read.verify().unwrap();
+            assert_eq!(read.fingerprint(), k.fingerprint());
+            assert_eq!(read.secret_subkeys.len(), subkey as usize);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+//! Check keys generated elsewhere against patterns, e.g. for services
+//! accepting vanity keys and validating the pattern they claim, with the
+//! same matching as a search.
+
+use crate::{
+    core::{PatternMatcher, PatternSpec, FP_LEN},
+    error::ApgpkError,
+    output::SCHEMA_VERSION,
+};
+use hex::ToHex;
+use pgp::{composed::signed_key::SignedPublicKey, types::KeyTrait, Deserializable};
+use serde::Serialize;
+use std::io::Cursor;
+
+/// What a key matched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchReport {
+    pub schema_version: u32,
+    /// Upper case hex fingerprint of the primary key
+    pub fingerprint: String,
+    /// One entry per matching fingerprint, primary key first
+    pub matches: Vec<KeyMatch>,
+}
+
+/// A fingerprint of the key matching a pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyMatch {
+    /// Upper case hex fingerprint, of the primary key or a subkey
+    pub fingerprint: String,
+    pub subkey: bool,
+    /// The matched pattern as shown in the log
+    pub pattern: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Digit substitutions off a fuzzy pattern
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<usize>,
+}
+
+impl MatchReport {
+    pub fn is_match(&self) -> bool {
+        !self.matches.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap() // plain strings and numbers
+    }
+}
+
+/// Match the primary key and the subkeys of an armored public key against
+/// `specs`, exclusions included. The self signatures are verified first, so
+/// a subkey bound by someone else doesn't count.
+pub fn check_public_key(armored: &str, specs: &[PatternSpec]) -> Result<MatchReport, ApgpkError> {
+    let (key, _) = SignedPublicKey::from_armor_single(Cursor::new(armored))?;
+    key.verify()?;
+    let patterns: Vec<_> = specs.iter().map(|s| s.pattern.clone()).collect();
+    let matcher = PatternMatcher::new(&patterns)?;
+
+    let primary = key.fingerprint();
+    let matches = std::iter::once((primary.clone(), false))
+        .chain(key.public_subkeys.iter().map(|k| (k.fingerprint(), true)))
+        // only v4 fingerprints are matched
+        .filter(|(fp, _)| fp.len() == FP_LEN)
+        .filter_map(|(fp, subkey)| {
+            let spec = &specs[matcher.find(&fp)?];
+            Some(KeyMatch {
+                fingerprint: fp.encode_hex_upper(),
+                subkey,
+                pattern: spec.pattern.to_string(),
+                label: spec.label.clone(),
+                distance: spec.pattern.distance(&fp),
+            })
+        })
+        .collect();
+    Ok(MatchReport {
+        schema_version: SCHEMA_VERSION,
+        fingerprint: primary.encode_hex_upper(),
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::KeyConfig,
+        core::{MatchMode, Pattern},
+        keygen::{KeyGenerator, PgpKeyGenerator},
+    };
+    use chrono::Utc;
+    use pgp::types::SecretKeyTrait;
+
+    #[test]
+    fn test_check_public_key() {
+        let now = Utc::now();
+        let key = PgpKeyGenerator::new(&KeyConfig::default(), now).generate(now);
+        let secret = key.sign().unwrap();
+        let armored = secret
+            .public_key()
+            .sign(&secret, String::new)
+            .unwrap()
+            .to_armored_string(None)
+            .unwrap();
+        let hex: String = secret.fingerprint().encode_hex_upper();
+
+        let suffix = PatternSpec::new(Pattern::literal(&hex[30..], MatchMode::Suffix));
+        let report = check_public_key(&armored, std::slice::from_ref(&suffix)).unwrap();
+        assert!(report.is_match());
+        assert_eq!(report.fingerprint, hex);
+        assert!(!report.matches[0].subkey);
+        assert!(report.to_json().contains(&hex));
+
+        // excluded like in a search
+        let exclude = Pattern::Exclude(Box::new(Pattern::literal(&hex[..10], MatchMode::Prefix)));
+        let report = check_public_key(&armored, &[suffix, PatternSpec::new(exclude)]).unwrap();
+        assert!(!report.is_match());
+
+        assert!(check_public_key("not a key", &[]).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Progress beacon of a single worker, shared with the [`Watchdog`].
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    start: Instant,
+    last: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            last: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Record that the worker is still making progress.
+    pub fn beat(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last.store(elapsed, Ordering::Relaxed);
+    }
+
+    fn since_last(&self) -> Duration {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+/// Watches the heartbeats of all workers and reports the ones which stopped
+/// beating for longer than `timeout` (hung RNG, livelock, ...).
+#[derive(Debug)]
+pub struct Watchdog {
+    timeout: Duration,
+    heartbeats: Vec<Heartbeat>,
+    alerted: Vec<AtomicBool>,
+}
+
+impl Watchdog {
+    pub fn new(workers: usize, timeout: Duration) -> Self {
+        let start = Instant::now();
+        Self {
+            timeout,
+            heartbeats: (0..workers).map(|_| Heartbeat::new(start)).collect(),
+            alerted: (0..workers).map(|_| AtomicBool::new(false)).collect(),
+        }
+    }
+
+    /// Heartbeat handle for the worker at slot `worker`.
+    pub fn heartbeat(&self, worker: usize) -> Heartbeat {
+        self.heartbeats[worker].clone()
+    }
+
+    /// Fresh heartbeat for a replacement of the worker at slot `worker`,
+    /// the beats of the old one aren't watched anymore.
+    pub fn replace(&mut self, worker: usize) -> Heartbeat {
+        let heartbeat = Heartbeat::new(self.heartbeats[worker].start);
+        heartbeat.beat();
+        self.heartbeats[worker] = heartbeat.clone();
+        self.alerted[worker].store(false, Ordering::Relaxed);
+        heartbeat
+    }
+
+    /// Return the workers which have stalled since the last check, together
+    /// with how long they have been silent. A stalled worker is reported only
+    /// once until it beats again.
+    pub fn check(&self) -> Vec<(usize, Duration)> {
+        let mut stalled = vec![];
+        for (i, hb) in self.heartbeats.iter().enumerate() {
+            let silent = hb.since_last();
+            if silent > self.timeout {
+                if !self.alerted[i].swap(true, Ordering::Relaxed) {
+                    stalled.push((i, silent));
+                }
+            } else {
+                self.alerted[i].store(false, Ordering::Relaxed);
+            }
+        }
+        stalled
+    }
+}
@@ -0,0 +1,130 @@
+//! Development tasks, run with `cargo xtask <task>`.
+//!
+//! `feature-matrix` builds and tests a package for every meaningful
+//! combination of its features: none, the defaults, each feature on its own
+//! and all of them. Without a `--target` the tests run; with one, such as
+//! `wasm32-unknown-unknown`, the combinations are only checked since their
+//! tests can't run on the host.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+const USAGE: &str = "\
+usage: cargo xtask feature-matrix [--package <name>]... [--target <triple>]
+
+  --package  package to check, repeatable, default `apgpk-lib`
+  --target   target triple to only check the combinations for";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("feature-matrix") => match feature_matrix(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn feature_matrix(args: &[String]) -> Result<(), String> {
+    let mut packages = vec![];
+    let mut target = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--package" | "-p" => packages.push(args.next().ok_or(USAGE)?.clone()),
+            "--target" => target = Some(args.next().ok_or(USAGE)?.clone()),
+            _ => return Err(format!("unknown argument `{}`\n{}", arg, USAGE)),
+        }
+    }
+    if packages.is_empty() {
+        packages.push("apgpk-lib".to_string());
+    }
+
+    let mut failed = vec![];
+    for package in &packages {
+        let manifest = workspace_root().join(package).join("Cargo.toml");
+        let features = declared_features(&manifest)?;
+        for combination in combinations(&features) {
+            let label = format!("{} [{}]", package, combination.join(" "));
+            println!("==> {}", label);
+            if !cargo(package, &combination, target.as_deref()) {
+                failed.push(label);
+            }
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(format!("failed combinations:\n  {}", failed.join("\n  ")))
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the workspace")
+        .to_path_buf()
+}
+
+/// Features declared in the `[features]` table of a manifest, without
+/// `default`
+fn declared_features(manifest: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(manifest)
+        .map_err(|e| format!("can't read `{}`: {}", manifest.display(), e))?;
+    let mut in_features = false;
+    let mut features = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_features = line == "[features]";
+            continue;
+        }
+        if !in_features || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((name, _)) = line.split_once('=') {
+            let name = name.trim().trim_matches('"');
+            if name != "default" {
+                features.push(name.to_string());
+            }
+        }
+    }
+    Ok(features)
+}
+
+/// Cargo feature flags of each combination to build
+fn combinations(features: &[String]) -> Vec<Vec<String>> {
+    let mut out = vec![vec!["--no-default-features".to_string()], vec![]];
+    for feature in features {
+        out.push(vec![
+            "--no-default-features".to_string(),
+            "--features".to_string(),
+            feature.clone(),
+        ]);
+    }
+    if features.len() > 1 {
+        out.push(vec!["--all-features".to_string()]);
+    }
+    out
+}
+
+/// Test a combination, or only check it for another target
+fn cargo(package: &str, flags: &[String], target: Option<&str>) -> bool {
+    let mut cmd = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    cmd.current_dir(workspace_root())
+        .arg(if target.is_none() { "test" } else { "check" })
+        .args(["--package", package])
+        .args(flags);
+    if let Some(target) = target {
+        cmd.args(["--target", target]);
+    }
+    cmd.status().is_ok_and(|s| s.success())
+}